@@ -0,0 +1,141 @@
+//! Plays a match between two engines without the GUI.
+//!
+//! engine_match --engine stockfish --option "Skill Level=5" --engine stockfish --option "Skill Level=10"
+//!              --tc 10+0.1 --games 100 --openings book.epd --pgn match.pgn --sprt 0,10
+
+use std::{env, path::PathBuf, process, sync::{atomic::AtomicBool, mpsc, Arc}, thread};
+
+use chess_app::game::{chess_engine::Protocol, engine_registry::{EngineConfig, EngineRegistry}, match_runner::{load_openings, run_match, Adjudication, MatchConfig, MatchEvent, MatchLimit, MatchPlayer, SprtConfig}};
+
+const USAGE: &str = "usage: engine_match --engine NAME|PATH [--name LABEL] [--protocol uci|xboard] [--option NAME=VALUE]... [--tc SPEC]
+                    --engine NAME|PATH [...]
+                    [--games N] [--openings FILE.epd|FILE.pgn] [--pgn FILE] [--sprt ELO0,ELO1]
+                    [--resign CP/MOVES] [--draw CP/MOVES/FROM_MOVE] [--max-moves N]
+SPEC is 40+0.4 (seconds plus increment), st=1, depth=8 or nodes=10000.
+A --tc given before the first --engine applies to both engines.";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn numbers<T: std::str::FromStr>(value: &str, separator: char) -> Vec<T> {
+    value.split(separator).map(|n| n.trim().parse().unwrap_or_else(|_| fail(&format!("not a number: {}", n)))).collect()
+}
+
+/// A registered engine by name, or a new one by path.
+fn engine_config(registry: &EngineRegistry, name_or_path: &str) -> EngineConfig {
+    registry
+        .engines
+        .iter()
+        .find(|e| e.name.eq_ignore_ascii_case(name_or_path) || e.path == name_or_path)
+        .cloned()
+        .unwrap_or_else(|| EngineConfig {
+            name: name_or_path.rsplit('/').next().unwrap_or(name_or_path).to_string(),
+            path: name_or_path.to_string(),
+            args: Vec::new(),
+            protocol: Protocol::Uci,
+            options: Vec::new(),
+            values: Default::default(),
+        })
+}
+
+fn main() {
+    let registry = EngineRegistry::load();
+    let mut players: Vec<MatchPlayer> = Vec::new();
+    let mut default_limit = MatchLimit::MoveTime(1000);
+    let mut openings = Vec::new();
+    let mut games = 2;
+    let mut adjudication = Adjudication::default();
+    let mut sprt = None;
+    let mut pgn_path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+        match flag.as_str() {
+            "--engine" => {
+                let config = engine_config(&registry, &value());
+                players.push(MatchPlayer { name: config.name.clone(), config, limit: default_limit });
+            }
+            "--name" | "--protocol" | "--option" => {
+                let value = value();
+                let Some(player) = players.last_mut() else { fail(&format!("{} must follow --engine", flag)) };
+                match flag.as_str() {
+                    "--name" => player.name = value,
+                    "--protocol" => {
+                        player.config.protocol = match value.as_str() {
+                            "uci" => Protocol::Uci,
+                            "xboard" | "cecp" => Protocol::Cecp,
+                            _ => fail(&format!("unknown protocol {}", value)),
+                        }
+                    }
+                    _ => {
+                        let Some((name, option)) = value.split_once('=') else { fail("--option takes NAME=VALUE") };
+                        player.config.values.insert(name.to_string(), option.to_string());
+                    }
+                }
+            }
+            "--tc" => {
+                let limit = MatchLimit::parse(&value()).unwrap_or_else(|| fail("bad time control"));
+                match players.last_mut() {
+                    Some(player) => player.limit = limit,
+                    None => default_limit = limit,
+                }
+            }
+            "--games" => games = numbers(&value(), ',')[0],
+            "--openings" => {
+                openings = load_openings(&PathBuf::from(value())).unwrap_or_else(|e| fail(&e));
+            }
+            "--pgn" => pgn_path = Some(PathBuf::from(value())),
+            "--sprt" => {
+                let elos: Vec<f64> = numbers(&value(), ',');
+                if elos.len() != 2 {
+                    fail("--sprt takes ELO0,ELO1");
+                }
+                sprt = Some(SprtConfig { elo0: elos[0], elo1: elos[1], ..SprtConfig::default() });
+            }
+            "--resign" => {
+                let [cp, moves] = numbers::<i32>(&value(), '/')[..] else { fail("--resign takes CP/MOVES") };
+                adjudication.resign_cp = cp;
+                adjudication.resign_moves = moves.max(0) as u32;
+            }
+            "--draw" => {
+                let [cp, moves, from] = numbers::<i32>(&value(), '/')[..] else { fail("--draw takes CP/MOVES/FROM_MOVE") };
+                adjudication.draw_cp = cp;
+                adjudication.draw_moves = moves.max(0) as u32;
+                adjudication.draw_from_move = from.max(0) as u32;
+            }
+            "--max-moves" => adjudication.max_moves = numbers(&value(), ',')[0],
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("unknown argument {}", flag)),
+        }
+    }
+    let players: [MatchPlayer; 2] = players.try_into().unwrap_or_else(|_| fail("exactly two engines are needed"));
+    let config = MatchConfig { players, openings, games, adjudication, sprt, pgn_path, event: "Engine match".to_string() };
+
+    let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let runner = thread::spawn(move || run_match(config, tx, stop));
+    for event in rx {
+        match event {
+            MatchEvent::GameFinished { game, result, reason, stats, llr } => {
+                print!("Game {}/{}: {} ({})  {}", game + 1, games, result, reason, stats);
+                match (llr, sprt) {
+                    (Some(llr), Some(sprt)) => {
+                        let (lower, upper) = sprt.bounds();
+                        println!("  LLR {:.2} [{:.2}, {:.2}]", llr, lower, upper);
+                    }
+                    _ => println!(),
+                }
+            }
+            MatchEvent::Finished { stats, reason } => println!("Finished: {}. {}", reason, stats),
+            MatchEvent::Error(e) => eprintln!("error: {}", e),
+            _ => {}
+        }
+    }
+    let _ = runner.join();
+}
@@ -1,6 +1,6 @@
 use std::{error::Error};
 
-use crate::{engine::{fen::fen_parser, move_gen::MoveError, ChessPiece, PieceColor, PieceType}, etc::{DEFAULT_FEN, DEFAULT_STARTING}, game::controller::LostBy};
+use crate::{engine::{fen::fen_parser, game_tree::GameTree, move_gen::MoveError, pgn::MoveTime, score::{annotation, Score, Wdl}, ChessPiece, PieceColor, PieceType}, etc::{DEFAULT_FEN, DEFAULT_STARTING}, game::controller::LostBy};
 use chrono::Local;
 
 #[derive(Clone)]
@@ -31,27 +31,42 @@ pub struct BoardState {
     pub black_taken:     Vec<ChessPiece>,
     pub promtion_pending: Option<((u8, u8), (u8,u8))>,
     pub checkmate_square: Option<(u8, u8)>,
-    pub past_evaluation: f32, 
-    pub current_evaluation: f32,
+    pub past_evaluation: Score,
+    pub current_evaluation: Score,
+    /// Chances that go with `current_evaluation`.
+    pub current_wdl: Wdl,
 
 }
 #[derive(Clone)]
 pub enum GameResult {WhiteWin, BlackWin, Draw, Unfinished}
+
+impl GameResult {
+    /// Result as PGN writes it.
+    pub fn pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unfinished => "*",
+        }
+    }
+}
 #[derive(Clone)]
 pub struct BoardMetaData{
     pub starting_position: String,
     pub date: String,
-    pub move_list: Vec<MoveStruct>,
+    /// Every move tried, with the line leading to the board's position current.
+    pub moves: GameTree,
     pub termination: LostBy,
     pub result: GameResult,
     pub white_player_elo: u32,
     pub black_player_elo: u32,
     pub white_player_name: String,
     pub black_player_name: String,
-    
-
+    /// Value of the PGN TimeControl tag, "-" for a game without a clock.
+    pub time_control: String,
 }
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct MoveStruct{
     pub move_number: usize,
     pub san: String,
@@ -60,8 +75,17 @@ pub struct MoveStruct{
     pub to: String,
     pub promotion: Option<PieceType>,
     pub is_capture:bool,
-    pub evaluation: f32,
-    pub time_stamp: f32,
+    /// Engine evaluation after the move, once the eval bar reached full depth on it.
+    pub evaluation: Option<Score>,
+    pub wdl: Option<Wdl>,
+    /// Clock after the move and time it took, when the game was timed.
+    pub time: MoveTime,
+    /// Comment shown after the move, empty for none.
+    pub comment: String,
+    /// Comment in front of the move, such as one opening a variation.
+    pub comment_before: String,
+    /// PGN annotation glyphs given to the move, such as 1 for "!".
+    pub nags: Vec<u8>,
 }
 
 #[derive(Clone)]
@@ -70,9 +94,9 @@ pub enum CastleType {QueenSide, KingSide}
 
 impl Default for Board{
     fn default() -> Self {
-    match fen_parser(&DEFAULT_FEN.to_owned()){
-        Ok(board) => return board,
-        Err( e) => return Board{
+    match fen_parser(DEFAULT_FEN){
+        Ok(board) => board,
+        Err(_) => Board{
             squares: [[None; 8]; 8],
             turn: PieceColor::White,
             white_big_castle: true,
@@ -102,8 +126,9 @@ impl Default for BoardState{
             black_taken: Vec::new(),
             promtion_pending: None,
             checkmate_square: None,
-            past_evaluation: 0.0,
-            current_evaluation:0.0,
+            past_evaluation: Score::default(),
+            current_evaluation: Score::default(),
+            current_wdl: Wdl::default(),
         }
     }
 }
@@ -112,39 +137,50 @@ impl Default for BoardMetaData {
         Self{
             starting_position: DEFAULT_FEN.to_string(),
             date: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-            move_list: Vec::new(),
+            moves: GameTree::default(),
             termination: LostBy::Draw,
             result: GameResult::Unfinished,
             white_player_elo: 0,
             black_player_elo: 0,
             white_player_name: String::new(),
             black_player_name: String::new(),
+            time_control: "-".to_string(),
         }
     }
         }
   
-impl Default for MoveStruct {
-    fn default() -> Self {
-        MoveStruct {
-            move_number: 0,
-            san: String::new(),
-            uci: String::new(),
-            from: String::new(),
-            to: String::new(),
-            promotion: None,
-            is_capture: false,
-            evaluation: 0.0,
-            time_stamp: 0.0,
+impl BoardMetaData {
+    pub fn player_name(&self, color: PieceColor) -> &str {
+        match color {
+            PieceColor::White => &self.white_player_name,
+            PieceColor::Black => &self.black_player_name,
         }
     }
+
+    /// "?!", "?" or "??" for move `index` of the current line, once it and the move before
+    /// it were evaluated.
+    pub fn annotation(&self, index: usize) -> Option<&'static str> {
+        let line = self.moves.line();
+        let before = line.get(index.checked_sub(1)?)?.wdl?;
+        let after = line.get(index)?.wdl?;
+        annotation(before, after, self.mover(index))
+    }
+
+    /// Color that played move `index`.
+    pub fn mover(&self, index: usize) -> PieceColor {
+        let black_starts = self.starting_position.split_whitespace().nth(1) == Some("b");
+        if (index % 2 == 1) != black_starts { PieceColor::Black } else { PieceColor::White }
+    }
 }
 
 impl From<&str> for MoveStruct {
     fn from(uci: &str) -> Self {
-        let mut mv = MoveStruct::default();
-        mv.uci = uci.to_string();
-        mv.from = uci.get(0..2).unwrap_or_default().to_string();
-        mv.to = uci.get(2..4).unwrap_or_default().to_string();
+        let mut mv = MoveStruct {
+            uci: uci.to_string(),
+            from: uci.get(0..2).unwrap_or_default().to_string(),
+            to: uci.get(2..4).unwrap_or_default().to_string(),
+            ..MoveStruct::default()
+        };
         if let Some(p) = uci.chars().nth(4) {
             mv.promotion = Some(match p.to_ascii_lowercase() {
                 'q' => PieceType::Queen,
@@ -160,10 +196,7 @@ impl From<&str> for MoveStruct {
 
 impl From<&String> for Board{
     fn from(fen: &String) -> Self {
-        match fen_parser(fen){
-            Ok(b) => b,
-            Err(e) => Board::default(),
-        }
+        fen_parser(fen).unwrap_or_default()
     }
 }
 impl Board {
@@ -173,19 +206,20 @@ impl Board {
         
         
         // Clone the piece to compute legal moves without holding a mutable borrow on self
-        let piece = match self.squares[old_rank as usize][old_file as usize].as_ref() {
-            Some(p) => p.clone(),
+        let piece = match self.squares[old_rank as usize][old_file as usize] {
+            Some(p) => p,
             None => return Err(MoveError::IllegalMove),
         };
         let (quiets, captures) = self.get_legal_moves(&piece);
-        let capture_piece = self.squares[new_rank as usize][new_file as usize].clone();
+        let capture_piece = self.squares[new_rank as usize][new_file as usize];
+        if quiets.contains(&new_pos) || captures.contains(&new_pos) {
+            self.update_castling_rights(old_pos, new_pos);
+        }
         // Now borrow the moving piece mutably to apply the move
         match self.squares[old_rank as usize][old_file as usize].as_mut() {
             Some(moving_piece) => {
 
                 
-                println!("Quiet moves: {:?}", quiets);
-                println!("Capture moves: {:?}", captures);
                 let is_quiet = quiets.contains(&new_pos);
                 let is_capture = captures.contains(&new_pos);
                 if is_capture {
@@ -222,8 +256,7 @@ impl Board {
                                         }
                                     }
                                     self.en_passant_target = None;
-                                    if !self.state.promtion_pending.is_some() {
-                                        let uci = self.encode_uci_move(old_pos, new_pos, None);
+                                    if self.state.promtion_pending.is_none() {
                                         self.record_move(old_pos, new_pos, None, true);
                                     }
                                     self.change_turn();
@@ -249,8 +282,7 @@ impl Board {
                                     }
                                     self.squares[epr as usize][epf as usize]=None;
                                     self.en_passant_target = None;
-                                    if !self.state.promtion_pending.is_some() {
-                                        let uci = self.encode_uci_move(old_pos, new_pos, None);
+                                    if self.state.promtion_pending.is_none() {
                                         self.record_move(old_pos, new_pos, None, true);
                                     }
                                     self.change_turn();
@@ -276,15 +308,13 @@ impl Board {
                                     self.state.black_taken.push(capture);
                                 }
                             }
-                            let uci = self.encode_uci_move(old_pos, new_pos, None);
                             self.record_move(old_pos, new_pos, None, true);
                             self.change_turn();
                             self.deselect_piece();
                         }
                     }
                     
-                }else 
-                if is_quiet  {
+                }else if is_quiet  {
                     self.en_passant_target = None;
                     if moving_piece.kind == PieceType::Pawn {
                         let mv_delta = old_rank.abs_diff(new_rank);
@@ -300,14 +330,17 @@ impl Board {
                     self.squares[new_rank as usize][new_file as usize] = Some(*moving_piece);
                     self.squares[old_rank as usize][old_file as usize] = None;
 
-                    if piece.color == PieceColor::Black {
-                        self.fullmove_number+=1;
-                    }
                     if piece.kind == PieceType::Pawn {
                         self.halfmove_clock = 0;
+                        if new_rank == 0 || new_rank == 7 {
+                            self.state.promtion_pending = Some((new_pos, old_pos));
+                        }
+                    } else {
+                        self.halfmove_clock += 1;
+                    }
+                    if self.state.promtion_pending.is_none() {
+                        self.record_move(old_pos, new_pos, None, false);
                     }
-                    let uci = self.encode_uci_move(old_pos, new_pos, None);
-                    self.record_move(old_pos, new_pos, None, false);
                                    
                     self.change_turn();
                     self.deselect_piece();
@@ -327,8 +360,9 @@ impl Board {
         
         
         
-        //iffailed return the piece to itssquare
-       
+        if piece.color == PieceColor::Black {
+            self.fullmove_number+=1;
+        }
         Ok(())
     }
     
@@ -384,14 +418,20 @@ impl Board {
             
             
             
+        }
+        self.en_passant_target = None;
+        self.halfmove_clock += 1;
+        self.record_move(king_pos, king.position, None, false);
+        if king.color == PieceColor::Black {
+            self.fullmove_number += 1;
         }
         self.change_turn();
         self.deselect_piece();
-        return Ok(());
+        Ok(())
     
     }
     else {
-        return Err("Cannot castle".into());
+        Err("Cannot castle".into())
     }
 
 
@@ -419,49 +459,30 @@ impl Board {
         let from_rank = 8 - from.0;
         let to_file = file_chars[to.1 as usize];
         let to_rank = 8 - to.0;
-        let mut move_record = MoveStruct {
-            move_number: self.fullmove_number as usize,
-            uci: uci,
+        // promotions are recorded once the turn has already passed to the other side
+        let mover = self.squares[to.0 as usize][to.1 as usize].map(|p| p.color).unwrap_or(self.turn);
+        let move_number = if mover == PieceColor::Black && self.turn == PieceColor::White {
+            self.fullmove_number.saturating_sub(1)
+        } else {
+            self.fullmove_number
+        };
+        let move_record = MoveStruct {
+            move_number: move_number as usize,
+            uci,
             from: format!("{}{}", from_file, from_rank),
             to: format!("{}{}", to_file, to_rank),
-            promotion: promotion,
-            is_capture: is_capture,
-            evaluation: 0.0,
-            time_stamp: 0.0,
-            san: "".to_string(), // Could use std::time::SystemTime if needed
+            promotion,
+            is_capture,
+            evaluation: None,
+            wdl: None,
+            time: MoveTime::default(),
+            comment: String::new(),
+            comment_before: String::new(),
+            nags: Vec::new(),
+            san: self.san_after_move(from, to, promotion, is_capture),
         };
         
-        // For SAN notation you would need more complex logic
-        // This is a simplified version that just shows the piece movement
-        if let Some(piece) = self.squares[to.0 as usize][to.1 as usize].as_ref() {
-            let piece_letter = match piece.kind {
-                PieceType::Pawn => "",
-                PieceType::Knight => "N",
-                PieceType::Bishop => "B",
-                PieceType::Rook => "R",
-                PieceType::Queen => "Q",
-                PieceType::King => "K",
-            };
-            
-            let capture_symbol = if is_capture { "x" } else { "" };
-            
-            move_record.san = format!(
-                "{}{}{}{}{}",
-                piece_letter,
-                move_record.from,
-                capture_symbol,
-                move_record.to,
-                promotion.map_or("".to_string(), |p| match p {
-                    PieceType::Queen => "=Q",
-                    PieceType::Rook => "=R",
-                    PieceType::Bishop => "=B",
-                    PieceType::Knight => "=N",
-                    _ => "",
-                }.to_string())
-            );
-        }
-        
-        self.meta_data.move_list.push(move_record);
+        self.meta_data.moves.add_move(move_record);
     }
     
 }
\ No newline at end of file
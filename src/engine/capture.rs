@@ -2,47 +2,28 @@ use crate::engine::{board::CastleType, Board, ChessPiece, PieceType};
 
 impl Board{
 
-    pub fn filter_capture_moves(&self, piece: &ChessPiece, moves: &Vec<(u8,u8)>) -> Vec<(u8,u8)> {
+    pub fn filter_capture_moves(&self, piece: &ChessPiece, moves: &[(u8,u8)]) -> Vec<(u8,u8)> {
         
         moves.iter().filter(|pos| {
             match self.squares[pos.0 as usize][pos.1 as usize] {
                 Some(target) => {
                     //special checks for pawn
                     if piece.kind == PieceType::Pawn{
-                        if piece.position.1 != pos.1 {
-                            true
-                        }else {
-                            false
-                        }
+                        piece.position.1 != pos.1
                     } else if piece.kind == PieceType::King{
                         if piece.color == target.color {
-                            if target.kind == PieceType::Rook{
-                                true
-                            }else {false}
-                            
+                            target.kind == PieceType::Rook
                         }else{
-                            if piece.position.1.abs_diff(target.position.1) > 1{
-                                false
-                            }
-                            else {true}
+                            piece.position.1.abs_diff(target.position.1) <= 1
                         }
                     }
                     else {
-                        if piece.color != target.color {true} else {false}
+                        piece.color != target.color
                     }
-                    
-                    
                 },
                 None => {
                     //keep the pawn invalid captures for en passant
-                    if piece.kind == PieceType::Pawn{
-                        if piece.position.1 != pos.1 {
-                            true
-                        }else {
-                            false
-                        }
-                    } 
-                    else {false}
+                    piece.kind == PieceType::Pawn && piece.position.1 != pos.1
                 }  
             }
         }).cloned().collect()
@@ -61,24 +42,10 @@ pub fn legalize_capture_moves(&self, piece: &ChessPiece, capture_moves: Vec<(u8,
                 } 
                 None => {
                     //capture square is empty so we check for en passant
-                    match self.squares[piece.position.0 as usize][mv.1 as usize] {
-                        Some(adjacent_piece) => {
-                            match self.en_passant_target {
-                                Some(target_pos) => {
-                                    if adjacent_piece.position == target_pos {
-                                
-                                if !self.simulate_move(piece, &mv){
-                                    valid_capture_moves.push(mv);    
-                                }
-                            }
-                                }
-                                _=> {}
-                            }
-                            
-                        }
-                        None => {
-                            continue;
-                        }
+                    if let (Some(adjacent_piece), Some(target_pos)) = (self.squares[piece.position.0 as usize][mv.1 as usize], self.en_passant_target)
+                        && adjacent_piece.position == target_pos
+                        && !self.simulate_move(piece, &mv) {
+                        valid_capture_moves.push(mv);
                     }
                 }
                 
@@ -88,31 +55,17 @@ pub fn legalize_capture_moves(&self, piece: &ChessPiece, capture_moves: Vec<(u8,
         else if piece.kind == PieceType::King {
             for mv in capture_moves{
                 match self.squares[mv.0 as usize][mv.1 as usize] {
-                    Some(p) => {
-                        if p.kind == PieceType::Rook  {
-                            if p.color == piece.color {
-                                
-                                
-                                if mv.1.abs_diff(piece.position.1) == 4 && self.can_castle(CastleType::QueenSide, p.color) {
-                                    valid_capture_moves.push(mv);
-                                }
-                                if mv.1.abs_diff(piece.position.1) == 3 && self.can_castle(CastleType::KingSide, p.color){
-                                    valid_capture_moves.push(mv);
-                                }
-                                
-                            }
-                            
-                            
-                            else {
-                                if !self.simulate_move(piece, &mv){
-                                    valid_capture_moves.push(mv);    
-                                }    
-                            }
+                    Some(p) if p.kind == PieceType::Rook && p.color == piece.color => {
+                        if mv.1 == 0 && self.can_castle(CastleType::QueenSide, p.color) {
+                            valid_capture_moves.push(mv);
+                        }
+                        if mv.1 == 7 && self.can_castle(CastleType::KingSide, p.color){
+                            valid_capture_moves.push(mv);
                         }
-                        else {
-                            if !self.simulate_move(piece, &mv){
-                                valid_capture_moves.push(mv);    
-                            }
+                    }
+                    Some(_) => {
+                        if !self.simulate_move(piece, &mv){
+                            valid_capture_moves.push(mv);    
                         }
                     }
                     None => {
@@ -1,29 +1,48 @@
-use std::fs::Metadata;
+use std::fmt;
 
 use crate::engine::{board::{BoardMetaData, BoardState}, Board, ChessPiece, PieceColor, PieceType};
 
+#[derive(Debug)]
 pub enum FenError{
-    InvalidChar(char)
+    InvalidChar(char),
+    MissingField(&'static str),
+    InvalidField(&'static str),
 }
 
-pub fn fen_parser(fen: &String) -> Result<Board, FenError>{
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::InvalidChar(c) => write!(f, "invalid character '{}' in FEN", c),
+            FenError::MissingField(field) => write!(f, "FEN is missing the {} field", field),
+            FenError::InvalidField(field) => write!(f, "FEN has an invalid {} field", field),
+        }
+    }
+}
+
+pub fn fen_parser(fen: &str) -> Result<Board, FenError>{
     // Implementation goes here
     let mut pieces = Vec::<ChessPiece>::new();
-    // split into exactly six fields
+    // split into the six fields, the two clocks are optional so EPD positions load too
     let parts: Vec<&str> = fen.split_whitespace().collect();
-    let board_representation    = parts[0];
-    let to_move                 = parts[1];
-    let castling_rights         = parts[2];
-    let en_passant_targets      = parts[3];
-    let halfmove_clock: u32     = parts[4].parse().unwrap();
-    let fullmove_number: u32    = parts[5].parse().unwrap();
+    let board_representation    = *parts.first().ok_or(FenError::MissingField("piece placement"))?;
+    let to_move                 = *parts.get(1).ok_or(FenError::MissingField("side to move"))?;
+    let castling_rights         = *parts.get(2).ok_or(FenError::MissingField("castling"))?;
+    let en_passant_targets      = *parts.get(3).ok_or(FenError::MissingField("en passant"))?;
+    let halfmove_clock: u32     = parts.get(4).unwrap_or(&"0").parse().map_err(|_| FenError::InvalidField("halfmove clock"))?;
+    let fullmove_number: u32    = parts.get(5).unwrap_or(&"1").parse().map_err(|_| FenError::InvalidField("fullmove number"))?;
+    if to_move != "w" && to_move != "b" {
+        return Err(FenError::InvalidField("side to move"));
+    }
+    if board_representation.split("/").count() != 8 {
+        return Err(FenError::InvalidField("piece placement"));
+    }
     let fen_files : Vec<&str> = board_representation.split("/").collect();
-    let mut i: u8 = 0;
-    for file in fen_files {
+    for (i, file) in fen_files.into_iter().enumerate() {
+        let i = i as u8;
         let mut j: u8 =0;
         for elem in file.chars(){
-            if elem.is_numeric(){
-                j+=(elem.to_digit(10).unwrap() as u8);
+            if let Some(empty) = elem.to_digit(10) {
+                j+=empty as u8;
 
             }
             else{
@@ -40,8 +59,11 @@ pub fn fen_parser(fen: &String) -> Result<Board, FenError>{
                     'K' => {(PieceType::King, PieceColor::White, (i, j), 0)}
                     'Q' => {(PieceType::Queen, PieceColor::White, (i, j), 0)}
                     'P' => {(PieceType::Pawn, PieceColor::White, (i, j), 0)}
-                    (c) => return Err(FenError::InvalidChar(c))
+                    c => return Err(FenError::InvalidChar(c))
                 };
+                if j > 7 {
+                    return Err(FenError::InvalidField("piece placement"));
+                }
                 pieces.push(
                     ChessPiece { 
                         kind, 
@@ -52,7 +74,6 @@ pub fn fen_parser(fen: &String) -> Result<Board, FenError>{
                 j+=1;
             }
         }
-        i+=1;
     }
     let mut board: [[Option<ChessPiece>; 8]; 8] = [[None; 8]; 8];
     for piece in pieces {
@@ -66,28 +87,33 @@ pub fn fen_parser(fen: &String) -> Result<Board, FenError>{
         } else {
             // e.g. "e3" → file 'e', rank '3'
             let bytes = en_passant_targets.as_bytes();
-            let file = bytes[0].wrapping_sub(b'a');          // 'a' → 0, …, 'h' → 7
-            let rank_digit = bytes[1].wrapping_sub(b'0');    // '1' → 1, …, '8' → 8
-            let row = 8 - rank_digit;                        // convert chess‐rank to 0–7
-            Some((row as u8, file as u8))
+            if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+                return Err(FenError::InvalidField("en passant"));
+            }
+            let file = bytes[0] - b'a';          // 'a' → 0, …, 'h' → 7
+            let rank_digit = bytes[1] - b'0';    // '1' → 1, …, '8' → 8
+            let row = 8 - rank_digit;            // convert chess‐rank to 0–7
+            // the board tracks the pawn that just double-pushed, which stands one rank past the target square
+            let pawn_row = if to_move == "w" { row + 1 } else { row.wrapping_sub(1) };
+            if pawn_row > 7 { None } else { Some((pawn_row, file)) }
         }
     };
     Ok(Board { squares: board, turn: 
         if to_move == "w" {PieceColor::White} else {PieceColor::Black}, 
-        white_big_castle: if castling_rights.contains("Q") {true} else {false}, 
-        black_big_castle: if castling_rights.contains("q") {true} else {false},
-        white_small_castle: if castling_rights.contains("K") {true} else {false}, 
-        black_small_castle: if castling_rights.contains("k") {true} else {false}, 
+        white_big_castle: castling_rights.contains("Q"), 
+        black_big_castle: castling_rights.contains("q"),
+        white_small_castle: castling_rights.contains("K"), 
+        black_small_castle: castling_rights.contains("k"), 
         halfmove_clock,
         fullmove_number,
-        en_passant_target: en_passant_target,
+        en_passant_target,
         state: BoardState::default(),
-        meta_data: BoardMetaData::default(),
+        meta_data: BoardMetaData { starting_position: fen.to_owned(), ..BoardMetaData::default() },
     })
 }
 
-impl ToString for Board {
-    fn to_string(&self) -> String {
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut board_string = "".to_owned();
         let to_move: &str = if self.turn ==PieceColor::White {"w"} else {"b"};
         let mut castleing_rights = 
@@ -95,7 +121,7 @@ impl ToString for Board {
             if self.white_big_castle {"Q"} else {""} +
             if self.black_small_castle {"k"} else {""} +
             if self.black_big_castle {"q"} else {""};
-        if castleing_rights == ""{castleing_rights = "-".to_owned()}
+        if castleing_rights.is_empty(){castleing_rights = "-".to_owned()}
         for i in 0..8 {
             let mut empty_squares = 0;
             for j in 0..8 {
@@ -131,17 +157,17 @@ impl ToString for Board {
 
         }
         board_string = board_string.trim_end_matches("/").to_string();
-        let en_passant = "-"; // placeholder if no en-passant target
-        return board_string
-            + " "
-            + &to_move
-            + " "
-            + &castleing_rights
-            + " "
-            + en_passant
-            + " "
-            + &self.halfmove_clock.to_string()
-            + " "
-            + &self.fullmove_number.to_string();
+        let en_passant = match self.en_passant_target {
+            // the target square is the one the double-pushed pawn skipped over
+            Some((row, file)) => {
+                match row {
+                    3 => format!("{}6", (b'a' + file) as char),
+                    4 => format!("{}3", (b'a' + file) as char),
+                    _ => "-".to_owned(),
+                }
+            }
+            None => "-".to_owned(),
+        };
+        write!(f, "{} {} {} {} {} {}", board_string, to_move, castleing_rights, en_passant, self.halfmove_clock, self.fullmove_number)
     }
 }
\ No newline at end of file
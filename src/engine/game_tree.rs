@@ -0,0 +1,245 @@
+use crate::engine::{board::MoveStruct, Board};
+
+/// A position of the game and the move that led to it.
+#[derive(Clone)]
+pub struct GameNode {
+    /// Move into this position; empty for the start position.
+    pub record: MoveStruct,
+    pub parent: Option<usize>,
+    /// Moves played from here: the main line continues with the first, the others are
+    /// variations in order of importance.
+    pub children: Vec<usize>,
+}
+
+/// All moves tried in a game, as a tree of positions below the start position. Nodes are
+/// kept in an arena and addressed by index; deleted variations stay in it unreachable.
+#[derive(Clone)]
+pub struct GameTree {
+    nodes: Vec<GameNode>,
+    /// Node the board shows.
+    current: usize,
+}
+
+impl Default for GameTree {
+    fn default() -> Self {
+        GameTree { nodes: vec![GameNode { record: MoveStruct::default(), parent: None, children: Vec::new() }], current: GameTree::ROOT }
+    }
+}
+
+impl GameTree {
+    /// The start position.
+    pub const ROOT: usize = 0;
+
+    pub fn node(&self, id: usize) -> &GameNode {
+        &self.nodes[id]
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Moves the current node, e.g. to view an earlier position. Unknown nodes are ignored.
+    pub fn set_current(&mut self, id: usize) {
+        if id < self.nodes.len() && self.is_reachable(id) {
+            self.current = id;
+        }
+    }
+
+    /// Plays `record` from the current node and makes its position current. A move that
+    /// was already played from here is reused with what is known about it; a new one becomes
+    /// the main line when the position has no moves yet, a variation otherwise.
+    pub fn add_move(&mut self, record: MoveStruct) -> usize {
+        let existing = self.nodes[self.current].children.iter().copied().find(|child| self.nodes[*child].record.uci == record.uci);
+        let id = existing.unwrap_or_else(|| {
+            self.nodes.push(GameNode { record, parent: Some(self.current), children: Vec::new() });
+            let id = self.nodes.len() - 1;
+            self.nodes[self.current].children.push(id);
+            id
+        });
+        self.current = id;
+        id
+    }
+
+    /// Nodes from the first move to `id`, the way the game went to reach it.
+    pub fn path(&self, id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            path.push(node);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Moves leading to the current position.
+    pub fn line(&self) -> Vec<&MoveStruct> {
+        self.path(self.current).into_iter().map(|id| &self.nodes[id].record).collect()
+    }
+
+    /// Number of moves leading to the current position.
+    pub fn ply(&self) -> usize {
+        self.path(self.current).len()
+    }
+
+    /// Move `index` of the line leading to the current position.
+    pub fn line_move_mut(&mut self, index: usize) -> Option<&mut MoveStruct> {
+        let id = *self.path(self.current).get(index)?;
+        Some(&mut self.nodes[id].record)
+    }
+
+    /// Move into the current position, `None` at the start.
+    pub fn current_move_mut(&mut self) -> Option<&mut MoveStruct> {
+        let current = self.current;
+        self.nodes[current].parent?;
+        Some(&mut self.nodes[current].record)
+    }
+
+    /// Nodes of the main line after `id`, following the first move of each position.
+    pub fn mainline_from(&self, id: usize) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut node = id;
+        while let Some(&next) = self.nodes[node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    /// Moves of the main line of the whole game.
+    pub fn mainline(&self) -> Vec<&MoveStruct> {
+        self.mainline_from(GameTree::ROOT).into_iter().map(|id| &self.nodes[id].record).collect()
+    }
+
+    /// Alternatives to the move into `id`: its siblings after it when it is the main move.
+    pub fn variations(&self, id: usize) -> &[usize] {
+        match self.nodes[id].parent {
+            Some(parent) if self.nodes[parent].children.first() == Some(&id) => &self.nodes[parent].children[1..],
+            _ => &[],
+        }
+    }
+
+    /// First move of the variation `id` is in, or of the game for the main line.
+    pub fn line_start(&self, id: usize) -> usize {
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            if parent == GameTree::ROOT || self.nodes[parent].children.first() != Some(&node) {
+                break;
+            }
+            node = parent;
+        }
+        node
+    }
+
+    /// Moves a variation one place up among its siblings; the first place makes it the main
+    /// line. Returns whether it moved.
+    pub fn promote(&mut self, id: usize) -> bool {
+        let Some((parent, index)) = self.sibling_index(id) else { return false };
+        if index == 0 {
+            return false;
+        }
+        self.nodes[parent].children.swap(index, index - 1);
+        true
+    }
+
+    /// Makes the line leading to `id` the main line, putting each of its moves before the
+    /// alternatives, e.g. when a game went on differently after a takeback.
+    pub fn promote_line(&mut self, id: usize) {
+        for node in self.path(id) {
+            while self.promote(node) {}
+        }
+    }
+
+    /// Moves a line one place down among its siblings. Returns whether it moved.
+    pub fn demote(&mut self, id: usize) -> bool {
+        let Some((parent, index)) = self.sibling_index(id) else { return false };
+        if index + 1 >= self.nodes[parent].children.len() {
+            return false;
+        }
+        self.nodes[parent].children.swap(index, index + 1);
+        true
+    }
+
+    /// Removes the move into `id` with everything played after it. The current position
+    /// goes back to the parent when it was in the removed part.
+    pub fn delete(&mut self, id: usize) -> bool {
+        let Some((parent, index)) = self.sibling_index(id) else { return false };
+        let current_removed = self.path(self.current).contains(&id);
+        self.nodes[parent].children.remove(index);
+        if current_removed {
+            self.current = parent;
+        }
+        true
+    }
+
+    /// Parent of `id` and its place among the parent's children.
+    fn sibling_index(&self, id: usize) -> Option<(usize, usize)> {
+        let parent = self.nodes.get(id)?.parent?;
+        let index = self.nodes[parent].children.iter().position(|child| *child == id)?;
+        Some((parent, index))
+    }
+
+    /// Whether `id` is still connected to the start position.
+    fn is_reachable(&self, id: usize) -> bool {
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            if !self.nodes[parent].children.contains(&node) {
+                return false;
+            }
+            node = parent;
+        }
+        node == GameTree::ROOT
+    }
+}
+
+impl Board {
+    /// Shows the position of node `id` of the game: the board is set up again from the start
+    /// position with the captured pieces of that line, and the game tree is kept.
+    pub fn goto(&mut self, id: usize) {
+        let tree = &self.meta_data.moves;
+        let moves: Vec<String> = tree.path(id).into_iter().map(|node| tree.node(node).record.uci.clone()).collect();
+        let mut board = Board::from(&self.meta_data.starting_position);
+        for uci in &moves {
+            if board.play_uci_move(uci).is_err() {
+                break;
+            }
+        }
+        board.meta_data = self.meta_data.clone();
+        board.meta_data.moves.set_current(id);
+        board.state.pov = self.state.pov;
+        *self = board;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{engine::{pgn::PgnGame, Board}, etc::DEFAULT_FEN};
+
+    fn mainline(board: &Board) -> Vec<String> {
+        board.meta_data.moves.mainline().iter().map(|mv| mv.uci.clone()).collect()
+    }
+
+    #[test]
+    fn move_after_takeback_becomes_the_main_line() {
+        let mut board = Board::from(&DEFAULT_FEN.to_string());
+        for uci in ["e2e4", "e7e5", "g1f3"] {
+            board.play_uci_move(uci).unwrap();
+        }
+        // take back 2. Nf3 and 1... e5, then play 1... c5 2. Nf3 instead
+        let tree = &board.meta_data.moves;
+        let first = tree.path(tree.current())[0];
+        board.goto(first);
+        for uci in ["c7c5", "g1f3"] {
+            board.play_uci_move(uci).unwrap();
+        }
+        assert_eq!(mainline(&board), ["e2e4", "e7e5", "g1f3"]);
+
+        let tree = &mut board.meta_data.moves;
+        tree.promote_line(tree.current());
+        assert_eq!(mainline(&board), ["e2e4", "c7c5", "g1f3"]);
+        let pgn = PgnGame::from_game(&board.meta_data);
+        assert_eq!(pgn.moves(), ["e2e4", "c7c5", "g1f3"]);
+        assert_eq!(pgn.line[1].variations.len(), 1);
+        assert_eq!(pgn.line[1].variations[0][0].uci, "e7e5");
+    }
+}
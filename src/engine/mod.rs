@@ -1,4 +1,5 @@
 pub mod board;
+pub mod game_tree;
 pub mod piece;
 pub mod fen;
 pub mod move_gen;
@@ -7,6 +8,10 @@ pub mod quiet;
 pub mod simulate;
 pub mod uci;
 pub mod san;
+pub mod problem;
+pub mod pgn;
+pub mod openings;
+pub mod score;
 
 
 pub use board::Board;
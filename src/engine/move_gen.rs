@@ -3,6 +3,8 @@ use crate::engine::{board::CastleType, Board, ChessPiece, PieceColor, PieceType}
 
 pub enum VerticalDirection {Up, Down}
 pub enum HorizontalDirection{Left, Right}
+/// Quiet moves and captures of a piece, as target squares.
+pub type LegalMoves = (Vec<(u8, u8)>, Vec<(u8, u8)>);
 #[derive(Debug)]
 pub enum MoveError{
     IllegalMove,
@@ -62,7 +64,9 @@ impl Board{
                 all_moves
             }
             PieceType::Pawn => {
-                let dep = if piece.times_moved ==0 {2} else {1};
+                // double push only from the starting rank, so pawns placed by a FEN still behave
+                let start_rank = if piece.color == PieceColor::White {6} else {1};
+                let dep = if piece.position.0 == start_rank {2} else {1};
                 all_moves.extend(self.get_file_moves(piece, dep, VerticalDirection::Up));
                 for h in [(VerticalDirection::Up,HorizontalDirection::Left),
                 (VerticalDirection::Up,HorizontalDirection::Right)] {
@@ -79,7 +83,7 @@ impl Board{
         self.state.capture_moves = Some(captures);
     }
 
-    pub fn get_legal_moves(&mut self, piece: &ChessPiece) -> (Vec<(u8, u8)>, Vec<(u8, u8)>) {
+    pub fn get_legal_moves(&mut self, piece: &ChessPiece) -> LegalMoves {
         let moves = self.get_all_moves(piece);
         
         let quiet = self.filter_quiet_moves(piece, &moves);
@@ -88,15 +92,14 @@ impl Board{
         let captures = self.filter_capture_moves(piece, &moves);
         let captures = self.legalize_capture_moves(piece, captures);
          
-        return (quiet, captures)
+        (quiet, captures)
 
     }
     
     pub fn lega_capture_moves(&self, piece: &ChessPiece) -> Vec<(u8,u8)>{
         let moves = self.get_all_moves(piece);        
         let captures = self.filter_capture_moves(piece, &moves);
-        let captures = self.legalize_capture_moves(piece, captures);
-        return captures
+        self.legalize_capture_moves(piece, captures)
     }
 
 
@@ -107,7 +110,7 @@ impl Board{
         depth: u8,
         dir: (VerticalDirection, HorizontalDirection),
     ) -> Vec<(u8, u8)> {
-        let (mut dr, mut dc) = match dir {
+        let (dr, dc) = match dir {
             (VerticalDirection::Up,    HorizontalDirection::Left)  => (if piece.color == PieceColor::White {-1} else {1}, -1),
             (VerticalDirection::Up,    HorizontalDirection::Right) => (if piece.color == PieceColor::White {-1} else {1},  1),
             (VerticalDirection::Down,  HorizontalDirection::Left)  => (if piece.color == PieceColor::White {1} else {-1}, -1),
@@ -144,7 +147,7 @@ impl Board{
 
     pub fn get_rank_moves(&self, piece: &ChessPiece, depth: u8, h: HorizontalDirection) -> Vec<(u8, u8)> {
         let mut moves:Vec<(u8, u8)> = Vec::new();
-        let mut  dir: i8 = match h {
+        let dir: i8 = match h {
             HorizontalDirection::Left => -1,
             HorizontalDirection::Right => 1,
         };
@@ -186,7 +189,7 @@ impl Board{
     }
     pub fn get_file_moves(&self, piece: &ChessPiece, depth: u8, h: VerticalDirection) -> Vec<(u8, u8)> {
         let mut moves:Vec<(u8, u8)> = Vec::new();
-        let mut dir: i8 = match h {
+        let dir: i8 = match h {
             VerticalDirection::Down => if piece.color == PieceColor::White {1} else {-1},
             VerticalDirection::Up => if piece.color == PieceColor::White {-1} else {1},
         };
@@ -235,13 +238,8 @@ impl Board{
                 let mut attacks = Vec::new();
                 let (r, c) = (piece.position.0 as i8, piece.position.1 as i8);
                 
-                // Pawns attack diagonally - use directions with POV logic
-                let mut direction = if piece.color == PieceColor::White { 1 } else { -1 };
-                
-                // Apply POV logic
-                if piece.color != self.state.pov {
-                    direction *= -1;
-                }
+                // Pawns attack diagonally towards the opponent, regardless of the board pov
+                let direction = if piece.color == PieceColor::White { -1 } else { 1 };
                 
                 let attack_rank = r + direction;
                 
@@ -255,71 +253,86 @@ impl Board{
                 }
                 attacks
             }
+            PieceType::King => {
+                // the king's rank moves reach its own rooks for castling, it only attacks its neighbours
+                let mut attacks = Vec::new();
+                let (r, c) = (piece.position.0 as i8, piece.position.1 as i8);
+                for dr in -1..=1 {
+                    for dc in -1..=1 {
+                        if (dr, dc) == (0, 0) { continue; }
+                        if (0..8).contains(&(r + dr)) && (0..8).contains(&(c + dc)) {
+                            attacks.push(((r + dr) as u8, (c + dc) as u8));
+                        }
+                    }
+                }
+                attacks
+            }
             _ => {
-                // For other pieces, use existing move generation which already has POV logic
                 self.get_all_moves(piece)
             }
             }
         }
         pub fn can_castle(&self, castle_side: CastleType, color: PieceColor) -> bool{
             //first we check if pieces are in designated position 
-            let (rook_pos, king_pos) = match castle_side {
-                CastleType::KingSide => {
-                    match color{
-                        PieceColor::Black => {
-                            if !self.black_small_castle { return false;}
-                            ((0, 7), (0,4))
-                        }
-                        PieceColor::White =>{
-                            if !self.white_small_castle { return false;}
-                            
-                            ((7, 7), (7, 4))
-                        }
-                    }        }
-                CastleType::QueenSide => {
-                    match color{
-                        PieceColor::Black => {
-                            if !self.black_big_castle { return false;}
-                            
-                            ((0,0), (0, 4))
-                        }
-                        PieceColor::White =>{
-                            if !self.white_big_castle { return false;}
-                            
-                            ((7, 7), (7, 4))
-                        }
-                    }
-                }
+            let home_rank = match color {
+                PieceColor::White => 7,
+                PieceColor::Black => 0,
             };
-            let (way, travel)  =match castle_side {
+            let (rook_pos, king_pos) = match castle_side {
                 CastleType::KingSide => {
-                    (HorizontalDirection::Right, 2)
+                    let allowed = match color {
+                        PieceColor::Black => self.black_small_castle,
+                        PieceColor::White => self.white_small_castle,
+                    };
+                    if !allowed { return false; }
+                    ((home_rank, 7), (home_rank, 4))
                 }
                 CastleType::QueenSide => {
-                    (HorizontalDirection::Left, 3)
+                    let allowed = match color {
+                        PieceColor::Black => self.black_big_castle,
+                        PieceColor::White => self.white_big_castle,
+                    };
+                    if !allowed { return false; }
+                    ((home_rank, 0), (home_rank, 4))
                 }
             };
             let king = match self.squares[king_pos.0 as usize][king_pos.1 as usize] {
-                Some(piece) => {
-                if piece.times_moved == 0 { piece } else { return false; }
-                }
-                None => { return false; }
+                Some(piece) if piece.kind == PieceType::King && piece.color == color && piece.times_moved == 0 => piece,
+                _ => { return false; }
             };
-            let rook = match self.squares[rook_pos.0 as usize][rook_pos.1 as usize] {
-                Some(piece) => {
-                if piece.times_moved == 0 { piece } else { return false; }
-                }
-                None => { return false; }
+            match self.squares[rook_pos.0 as usize][rook_pos.1 as usize] {
+                Some(piece) if piece.kind == PieceType::Rook && piece.color == color && piece.times_moved == 0 => {}
+                _ => { return false; }
             };
-            let travel_squares = self.get_rank_moves(&king, travel, way);
-            for sqaure in travel_squares{
-                match self.squares[sqaure.0 as usize][sqaure.1 as usize] {
-                    Some(_) => return false,
-                    None=> {
-                        if self.simulate_move(&king, &sqaure) {return false;}
-                    }
-                }
+            // castling out of check is not allowed
+            if self.is_in_check(color) { return false; }
+            // every square between king and rook must be empty
+            let (low, high) = (king_pos.1.min(rook_pos.1) + 1, king_pos.1.max(rook_pos.1));
+            for file in low..high {
+                if self.squares[home_rank as usize][file as usize].is_some() { return false; }
+            }
+            // the king may not pass through or land on an attacked square
+            let king_path: [u8; 2] = match castle_side {
+                CastleType::KingSide => [5, 6],
+                CastleType::QueenSide => [3, 2],
+            };
+            for file in king_path {
+                if self.simulate_move(&king, &(home_rank, file)) {return false;}
             }
             true
         }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Board;
+
+    #[test]
+    fn a_rook_next_to_the_king_side_does_not_castle() {
+        // the a1 rook went to b1, three files from the king like a king side rook
+        let mut board = Board::from(&"r3k2r/8/8/8/8/8/8/1R2K2R w Kkq - 0 1".to_string());
+        let moves = board.legal_moves();
+        assert!(moves.contains(&"e1g1".to_string()));
+        assert!(!moves.contains(&"e1c1".to_string()));
+    }
+}
@@ -0,0 +1,96 @@
+/// Well-known openings by their first moves in SAN. The longest one a game starts with names
+/// its opening.
+const OPENINGS: &[(&str, &str)] = &[
+    ("e4", "King's Pawn Game"),
+    ("e4 e5", "Open Game"),
+    ("e4 e5 Nf3", "King's Knight Opening"),
+    ("e4 e5 Nf3 Nc6", "King's Knight Opening"),
+    ("e4 e5 Nf3 Nc6 Bb5", "Ruy Lopez"),
+    ("e4 e5 Nf3 Nc6 Bb5 a6", "Ruy Lopez, Morphy Defense"),
+    ("e4 e5 Nf3 Nc6 Bb5 Nf6", "Ruy Lopez, Berlin Defense"),
+    ("e4 e5 Nf3 Nc6 Bc4", "Italian Game"),
+    ("e4 e5 Nf3 Nc6 Bc4 Bc5", "Italian Game, Giuoco Piano"),
+    ("e4 e5 Nf3 Nc6 Bc4 Bc5 b4", "Evans Gambit"),
+    ("e4 e5 Nf3 Nc6 Bc4 Nf6", "Two Knights Defense"),
+    ("e4 e5 Nf3 Nc6 d4", "Scotch Game"),
+    ("e4 e5 Nf3 Nc6 Nc3", "Three Knights Game"),
+    ("e4 e5 Nf3 Nc6 Nc3 Nf6", "Four Knights Game"),
+    ("e4 e5 Nf3 Nf6", "Petrov's Defense"),
+    ("e4 e5 Nf3 d6", "Philidor Defense"),
+    ("e4 e5 f4", "King's Gambit"),
+    ("e4 e5 f4 exf4", "King's Gambit Accepted"),
+    ("e4 e5 Nc3", "Vienna Game"),
+    ("e4 e5 Bc4", "Bishop's Opening"),
+    ("e4 e5 d4 exd4 c3", "Danish Gambit"),
+    ("e4 c5", "Sicilian Defense"),
+    ("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6", "Sicilian Defense, Najdorf Variation"),
+    ("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6", "Sicilian Defense, Dragon Variation"),
+    ("e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6 Nc3 e5", "Sicilian Defense, Sveshnikov Variation"),
+    ("e4 c5 Nf3 e6", "Sicilian Defense, French Variation"),
+    ("e4 c5 c3", "Sicilian Defense, Alapin Variation"),
+    ("e4 c5 Nc3", "Sicilian Defense, Closed"),
+    ("e4 c5 d4 cxd4 c3", "Sicilian Defense, Smith-Morra Gambit"),
+    ("e4 e6", "French Defense"),
+    ("e4 e6 d4 d5 e5", "French Defense, Advance Variation"),
+    ("e4 e6 d4 d5 exd5", "French Defense, Exchange Variation"),
+    ("e4 e6 d4 d5 Nc3 Bb4", "French Defense, Winawer Variation"),
+    ("e4 e6 d4 d5 Nd2", "French Defense, Tarrasch Variation"),
+    ("e4 c6", "Caro-Kann Defense"),
+    ("e4 c6 d4 d5 e5", "Caro-Kann Defense, Advance Variation"),
+    ("e4 c6 d4 d5 exd5 cxd5", "Caro-Kann Defense, Exchange Variation"),
+    ("e4 d5", "Scandinavian Defense"),
+    ("e4 Nf6", "Alekhine's Defense"),
+    ("e4 d6", "Pirc Defense"),
+    ("e4 g6", "Modern Defense"),
+    ("e4 Nc6", "Nimzowitsch Defense"),
+    ("d4", "Queen's Pawn Game"),
+    ("d4 d5", "Closed Game"),
+    ("d4 d5 c4", "Queen's Gambit"),
+    ("d4 d5 c4 dxc4", "Queen's Gambit Accepted"),
+    ("d4 d5 c4 e6", "Queen's Gambit Declined"),
+    ("d4 d5 c4 c6", "Slav Defense"),
+    ("d4 d5 c4 e6 Nc3 c6 Nf3 Nf6", "Semi-Slav Defense"),
+    ("d4 d5 c4 Nc6", "Chigorin Defense"),
+    ("d4 d5 c4 e5", "Albin Countergambit"),
+    ("d4 d5 Bf4", "London System"),
+    ("d4 d5 Nf3 Nf6 Bf4", "London System"),
+    ("d4 Nf6 Nf3 d5 Bf4", "London System"),
+    ("d4 Nf6 Bf4", "London System"),
+    ("d4 Nf6 Bg5", "Trompowsky Attack"),
+    ("d4 Nf6", "Indian Defense"),
+    ("d4 Nf6 c4 e6 Nc3 Bb4", "Nimzo-Indian Defense"),
+    ("d4 Nf6 c4 e6 Nf3 b6", "Queen's Indian Defense"),
+    ("d4 Nf6 c4 e6 g3", "Catalan Opening"),
+    ("d4 Nf6 c4 g6", "King's Indian Defense"),
+    ("d4 Nf6 c4 g6 Nc3 d5", "Grünfeld Defense"),
+    ("d4 Nf6 c4 c5 d5 b5", "Benko Gambit"),
+    ("d4 Nf6 c4 c5 d5 e6", "Benoni Defense"),
+    ("d4 Nf6 c4 e5", "Budapest Gambit"),
+    ("d4 f5", "Dutch Defense"),
+    ("d4 e6 c4 Bb4+", "Bogo-Indian Defense"),
+    ("d4 Nf6 c4 e6 Nf3 Bb4+", "Bogo-Indian Defense"),
+    ("c4", "English Opening"),
+    ("c4 e5", "English Opening, Reversed Sicilian"),
+    ("c4 c5", "English Opening, Symmetrical Variation"),
+    ("Nf3", "Réti Opening"),
+    ("Nf3 d5 c4", "Réti Opening"),
+    ("f4", "Bird's Opening"),
+    ("b3", "Nimzo-Larsen Attack"),
+    ("g3", "King's Fianchetto Opening"),
+    ("b4", "Polish Opening"),
+    ("Nc3", "Van Geet Opening"),
+];
+
+/// Name of the opening of a game from the standard start position, given its moves in
+/// SAN. Check and mate marks are ignored.
+pub fn opening_name<S: AsRef<str>>(moves: &[S]) -> Option<&'static str> {
+    let moves: Vec<&str> = moves.iter().map(|mv| mv.as_ref().trim_end_matches(['+', '#'])).collect();
+    OPENINGS
+        .iter()
+        .filter(|(line, _)| {
+            let line: Vec<&str> = line.split(' ').map(|mv| mv.trim_end_matches('+')).collect();
+            moves.starts_with(&line)
+        })
+        .max_by_key(|(line, _)| line.split(' ').count())
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,819 @@
+use std::{fmt, mem, time::Duration};
+
+use crate::{engine::{board::{BoardMetaData, GameResult, MoveStruct}, game_tree::GameTree, score::Score, uci::{parse_uci_move, UciPosition}, Board, PieceColor}, etc::DEFAULT_FEN, game::controller::LostBy};
+
+/// Tags every PGN game starts with, in the order the standard requires.
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Longest line of move text written to a PGN file.
+pub const PGN_LINE_WIDTH: usize = 80;
+
+/// One game of a PGN file: its tags and moves.
+#[derive(Clone, Debug, Default)]
+pub struct PgnGame {
+    /// Tags in file order, without SetUp and FEN which live in `start_fen`.
+    pub tags: Vec<(String, String)>,
+    /// `None` when the game starts from the standard position.
+    pub start_fen: Option<String>,
+    /// Main line, with the variations branching off its moves.
+    pub line: Vec<PgnMove>,
+    /// "1-0", "0-1", "1/2-1/2" or "*".
+    pub result: String,
+}
+
+/// A move of a PGN game with everything written around it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PgnMove {
+    pub uci: String,
+    /// Numeric annotation glyphs: $1 or "!" is 1, "?" 2, "!!" 3, "??" 4, "!?" 5, "?!" 6.
+    pub nags: Vec<u8>,
+    /// Comment in front of the move, as at the start of a game or variation.
+    pub comment_before: String,
+    pub comment: MoveComment,
+    /// Lines played instead of this move.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(uci: impl Into<String>) -> PgnMove {
+        PgnMove { uci: uci.into(), ..PgnMove::default() }
+    }
+}
+
+/// Something in a PGN text that could not be read. Lines and columns count from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnError {
+    /// Game of the file the error is in, counting from 1.
+    pub game: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "game {}, line {}, column {}: {}", self.game, self.line, self.column, self.message)
+    }
+}
+
+/// Clock comments of a move: `[%clk]`, the time left after it, and `[%emt]`, the time it took.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveTime {
+    pub clock: Option<Duration>,
+    pub spent: Option<Duration>,
+}
+
+/// What the comment after a move says: its clock and `[%eval]` commands and any text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveComment {
+    pub time: MoveTime,
+    pub eval: Option<Score>,
+    pub text: String,
+}
+
+impl MoveComment {
+    /// Takes over the commands of a comment, adding its text to what was already read.
+    fn read(&mut self, comment: &str) {
+        let time = &mut self.time;
+        time.clock = comment_command(comment, "clk").and_then(parse_pgn_duration).or(time.clock);
+        time.spent = comment_command(comment, "emt").and_then(parse_pgn_duration).or(time.spent);
+        self.eval = comment_command(comment, "eval").and_then(parse_pgn_eval).or(self.eval);
+        let text = strip_commands(comment);
+        if !text.is_empty() {
+            if !self.text.is_empty() {
+                self.text.push(' ');
+            }
+            self.text += &text;
+        }
+    }
+
+    /// "{[%eval 0.35] [%clk 0:04:58] [%emt 0:00:02] text}", `None` when there is nothing to say.
+    fn to_pgn(&self) -> Option<String> {
+        let times = [("clk", self.time.clock), ("emt", self.time.spent)]
+            .into_iter()
+            .filter_map(|(name, time)| Some(format!("[%{} {}]", name, pgn_duration(time?))));
+        let mut parts: Vec<String> = self.eval.map(|eval| format!("[%eval {}]", pgn_eval(eval))).into_iter().chain(times).collect();
+        if !self.text.is_empty() {
+            // a comment ends at the first closing brace
+            parts.push(self.text.replace('}', ")"));
+        }
+        (!parts.is_empty()).then(|| format!("{{{}}}", parts.join(" ")))
+    }
+}
+
+/// `%eval` value: pawns from White's side, "0.35" or "-1.20", or "#3" / "#-3" for a mate.
+pub fn pgn_eval(score: Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("{:.2}", cp as f32 / 100.0),
+        Score::Mate(moves) => format!("#{}", moves),
+    }
+}
+
+pub fn parse_pgn_eval(text: &str) -> Option<Score> {
+    match text.trim().strip_prefix('#') {
+        Some(moves) => moves.parse().ok().map(Score::Mate),
+        None => text.trim().parse::<f32>().ok().map(|pawns| Score::Cp((pawns * 100.0).round() as i32)),
+    }
+}
+
+/// "h:mm:ss" as clock comments use it, with tenths when there are any: "0:00:02.5".
+pub fn pgn_duration(time: Duration) -> String {
+    let tenths = time.as_millis() / 100;
+    let text = format!("{}:{:02}:{:02}", tenths / 36_000, tenths / 600 % 60, tenths / 10 % 60);
+    match tenths % 10 {
+        0 => text,
+        fraction => format!("{}.{}", text, fraction),
+    }
+}
+
+/// Reads "h:mm:ss", "mm:ss" or plain seconds, each with optional decimals.
+pub fn parse_pgn_duration(text: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in text.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// Argument of a `[%name ...]` command in a comment.
+fn comment_command<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+    let start = comment.find(&format!("[%{} ", name))? + name.len() + 3;
+    let end = start + comment[start..].find(']')?;
+    Some(comment[start..end].trim())
+}
+
+/// The text of a comment without its `[%...]` commands.
+fn strip_commands(comment: &str) -> String {
+    let mut text = comment.to_string();
+    while let Some(start) = text.find("[%") {
+        let end = text[start..].find(']').map_or(text.len(), |end| start + end + 1);
+        text.replace_range(start..end, " ");
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value,
+            None => self.tags.push((name.to_string(), value)),
+        }
+    }
+
+    /// The game recorded in `meta`, with evaluations, clocks and comments after its moves.
+    pub fn from_game(meta: &BoardMetaData) -> PgnGame {
+        let mut game = PgnGame {
+            start_fen: (meta.starting_position != DEFAULT_FEN).then(|| meta.starting_position.clone()),
+            line: meta.moves.node(GameTree::ROOT).children.first().map_or_else(Vec::new, |first| tree_line(&meta.moves, *first)),
+            result: meta.result.pgn().to_string(),
+            ..PgnGame::default()
+        };
+        // the date is kept as "2024-05-01 18:30:00"
+        let (date, time) = meta.date.split_once(' ').unwrap_or((&meta.date, ""));
+        game.set_tag("Event", "Casual game");
+        game.set_tag("Site", "Koch");
+        game.set_tag("Date", date.replace('-', "."));
+        game.set_tag("Round", "-");
+        game.set_tag("White", meta.white_player_name.clone());
+        game.set_tag("Black", meta.black_player_name.clone());
+        if !time.is_empty() {
+            game.set_tag("Time", time);
+        }
+        for (tag, elo) in [("WhiteElo", meta.white_player_elo), ("BlackElo", meta.black_player_elo)] {
+            if elo > 0 {
+                game.set_tag(tag, elo.to_string());
+            }
+        }
+        game.set_tag("TimeControl", meta.time_control.clone());
+        let termination = match (&meta.result, &meta.termination) {
+            (_, LostBy::Abandoned) => "abandoned",
+            (GameResult::Unfinished, _) => "unterminated",
+            (_, LostBy::Timeout) => "time forfeit",
+            _ => "normal",
+        };
+        game.set_tag("Termination", termination);
+        game
+    }
+
+    /// The moves of `meta` leading to its current position, without the variations. The
+    /// result is only kept when that is the end of the main line.
+    pub fn from_game_line(meta: &BoardMetaData) -> PgnGame {
+        let mut game = PgnGame::from_game(meta);
+        let tree = &meta.moves;
+        let path = tree.path(tree.current());
+        if path != tree.mainline_from(GameTree::ROOT) {
+            game.result = GameResult::Unfinished.pgn().to_string();
+            game.set_tag("Termination", "unterminated");
+        }
+        game.line = path.into_iter().map(|id| pgn_move(&tree.node(id).record)).collect();
+        game
+    }
+
+    /// Main line in UCI notation.
+    pub fn moves(&self) -> Vec<String> {
+        self.line.iter().map(|mv| mv.uci.clone()).collect()
+    }
+
+    pub fn position(&self) -> UciPosition {
+        UciPosition { start_fen: self.start_fen.clone(), moves: self.moves() }
+    }
+
+    /// The game played out on a board, with its variations in the game tree and the tags,
+    /// comments and annotations in the meta data. The board shows the end of the main line.
+    pub fn to_board(&self) -> Board {
+        let mut board = Board::from(&self.start_fen.clone().unwrap_or_else(|| DEFAULT_FEN.to_string()));
+        play_line(&mut board, &self.line);
+        let mut final_position = board.clone();
+        let mated = final_position.is_in_check(board.turn) && final_position.legal_moves().is_empty();
+        let tag = |name: &str| self.tag(name).unwrap_or("?").to_string();
+        let meta = &mut board.meta_data;
+        meta.white_player_name = tag("White");
+        meta.black_player_name = tag("Black");
+        meta.white_player_elo = tag("WhiteElo").parse().unwrap_or(0);
+        meta.black_player_elo = tag("BlackElo").parse().unwrap_or(0);
+        // kept as "2024-05-01 18:30:00" like the games played here
+        meta.date = format!("{} {}", tag("Date").replace('.', "-"), self.tag("Time").unwrap_or_default()).trim().to_string();
+        meta.time_control = self.tag("TimeControl").unwrap_or("-").to_string();
+        meta.result = match self.result.as_str() {
+            "1-0" => GameResult::WhiteWin,
+            "0-1" => GameResult::BlackWin,
+            "1/2-1/2" => GameResult::Draw,
+            _ => GameResult::Unfinished,
+        };
+        meta.termination = match (&meta.result, self.tag("Termination")) {
+            (_, Some("time forfeit")) => LostBy::Timeout,
+            (_, Some("abandoned")) => LostBy::Abandoned,
+            (GameResult::WhiteWin | GameResult::BlackWin, _) if mated => LostBy::Checkmate,
+            (GameResult::WhiteWin | GameResult::BlackWin, _) => LostBy::Resignation,
+            _ => LostBy::Draw,
+        };
+        board
+    }
+
+    /// Writes the game as PGN: Seven Tag Roster first, SetUp/FEN for other start positions,
+    /// then numbered SAN moves with their annotations, comments and variations, wrapped at
+    /// [`PGN_LINE_WIDTH`] columns.
+    pub fn to_pgn(&self) -> String {
+        let mut text = String::new();
+        for name in SEVEN_TAG_ROSTER {
+            let value = match (name, self.tag(name)) {
+                ("Result", _) => self.result.clone(),
+                (_, Some(value)) => value.to_string(),
+                ("Date", None) => "????.??.??".to_string(),
+                (_, None) => "?".to_string(),
+            };
+            text += &format!("[{} \"{}\"]\n", name, escape_tag(&value));
+        }
+        if let Some(fen) = &self.start_fen {
+            text += "[SetUp \"1\"]\n";
+            text += &format!("[FEN \"{}\"]\n", escape_tag(fen));
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) {
+                text += &format!("[{} \"{}\"]\n", name, escape_tag(value));
+            }
+        }
+        text.push('\n');
+
+        let board = UciPosition { start_fen: self.start_fen.clone(), moves: Vec::new() }.board();
+        let mut tokens = Vec::new();
+        line_tokens(&board, &self.line, &mut tokens);
+        tokens.push(self.result.clone());
+        text += &wrap_tokens(&tokens, PGN_LINE_WIDTH);
+        text.push('\n');
+        text
+    }
+}
+
+/// Plays `line` and its variations on `board`, which ends up after the line's last move.
+fn play_line(board: &mut Board, line: &[PgnMove]) {
+    for mv in line {
+        let before = board.meta_data.moves.current();
+        if board.play_uci_move(&mv.uci).is_err() {
+            return;
+        }
+        if let Some(record) = board.meta_data.moves.current_move_mut() {
+            record.time = mv.comment.time;
+            record.evaluation = mv.comment.eval;
+            record.comment = mv.comment.text.clone();
+            record.nags = mv.nags.clone();
+            record.comment_before = mv.comment_before.clone();
+        }
+        if mv.variations.is_empty() {
+            continue;
+        }
+        let after = board.meta_data.moves.current();
+        for variation in &mv.variations {
+            board.goto(before);
+            play_line(board, variation);
+        }
+        board.goto(after);
+    }
+}
+
+/// The line of the game tree starting with the move into `first`, following the main
+/// moves, with the alternatives to each of them.
+fn tree_line(tree: &GameTree, first: usize) -> Vec<PgnMove> {
+    let mut line = Vec::new();
+    for id in std::iter::once(first).chain(tree.mainline_from(first)) {
+        line.push(PgnMove {
+            variations: tree.variations(id).iter().map(|variation| tree_line(tree, *variation)).collect(),
+            ..pgn_move(&tree.node(id).record)
+        });
+    }
+    line
+}
+
+/// A recorded move with its annotations and comments.
+fn pgn_move(record: &MoveStruct) -> PgnMove {
+    PgnMove {
+        nags: record.nags.clone(),
+        comment_before: record.comment_before.clone(),
+        comment: MoveComment { time: record.time, eval: record.evaluation, text: record.comment.clone() },
+        ..PgnMove::new(record.uci.clone())
+    }
+}
+
+/// Move text of `line` played from `board`. A black move gets its number again after a
+/// comment or variation interrupted the line.
+fn line_tokens(board: &Board, line: &[PgnMove], tokens: &mut Vec<String>) {
+    let mut board = board.clone();
+    let mut renumber = true;
+    for mv in line {
+        if !mv.comment_before.is_empty() {
+            tokens.push(format!("{{{}}}", mv.comment_before.replace('}', ")")));
+            renumber = true;
+        }
+        if board.turn == PieceColor::White {
+            tokens.push(format!("{}.", board.fullmove_number));
+        } else if renumber {
+            tokens.push(format!("{}...", board.fullmove_number));
+        }
+        renumber = false;
+        tokens.push(board.encode_san_move(&mv.uci).unwrap_or_else(|| mv.uci.clone()));
+        tokens.extend(mv.nags.iter().map(|nag| format!("${}", nag)));
+        if let Some(comment) = mv.comment.to_pgn() {
+            tokens.push(comment);
+            renumber = true;
+        }
+        for variation in &mv.variations {
+            let start = tokens.len();
+            line_tokens(&board, variation, tokens);
+            if let Some(last) = tokens.get_mut(start..).and_then(<[String]>::last_mut) {
+                last.push(')');
+                tokens[start].insert(0, '(');
+                renumber = true;
+            }
+        }
+        if let Some((from, to, promotion)) = parse_uci_move(&mv.uci) {
+            board.apply_move(from, to, promotion);
+        }
+    }
+}
+
+pub fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Joins tokens with spaces, starting a new line before a token would pass `width`.
+pub fn wrap_tokens(tokens: &[String], width: usize) -> String {
+    let mut text = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > width {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        text += token;
+        line_len += token.len();
+    }
+    text
+}
+
+/// Reads every game of a PGN text, with comments, annotations and variations. A game with
+/// an error is kept up to it and the rest of its moves skipped; the errors say where they
+/// are.
+pub fn parse_pgn(text: &str) -> (Vec<PgnGame>, Vec<PgnError>) {
+    let mut games = Vec::new();
+    let mut errors = Vec::new();
+    let mut reader = GameReader::default();
+    for (token, line, column) in pgn_tokens(text) {
+        match token {
+            PgnToken::Tag(name, value) => {
+                if reader.in_movetext() {
+                    games.push(mem::take(&mut reader).finish(None));
+                }
+                match name.as_str() {
+                    "FEN" => reader.game.start_fen = Some(value),
+                    "SetUp" => {}
+                    _ => reader.game.set_tag(&name, value),
+                }
+            }
+            PgnToken::Result(result) => {
+                if reader.in_movetext() || !reader.game.tags.is_empty() {
+                    games.push(mem::take(&mut reader).finish(Some(result)));
+                }
+            }
+            _ if reader.broken => {}
+            token => {
+                if let Err(message) = reader.read(token) {
+                    errors.push(PgnError { game: games.len() + 1, line, column, message });
+                    reader.broken = true;
+                }
+            }
+        }
+    }
+    if reader.in_movetext() || !reader.game.tags.is_empty() {
+        games.push(reader.finish(None));
+    }
+    (games, errors)
+}
+
+/// The games of a PGN text as far as they could be read, see [`parse_pgn`].
+pub fn read_pgn(text: &str) -> Vec<PgnGame> {
+    parse_pgn(text).0
+}
+
+enum PgnToken {
+    /// A [Name "value"] tag pair.
+    Tag(String, String),
+    /// The text of a {brace} or ; rest of line comment.
+    Comment(String),
+    /// "(", opening a variation.
+    Open,
+    /// ")", closing a variation.
+    Close,
+    /// A $12 annotation glyph.
+    Nag(u8),
+    /// "1-0", "0-1", "1/2-1/2" or "*".
+    Result(String),
+    /// A move, maybe with its number in front and annotations after it, or a move number.
+    Word(String),
+    /// Text that cannot be read, with the reason.
+    Bad(String),
+}
+
+/// Splits a PGN text into tokens with the line and column they start at. Lines starting
+/// with `%` are skipped.
+fn pgn_tokens(text: &str) -> Vec<(PgnToken, usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    // moves past `count` characters, keeping the position up to date
+    let advance = |i: &mut usize, line: &mut usize, column: &mut usize, count: usize| {
+        for _ in 0..count {
+            if chars.get(*i) == Some(&'\n') {
+                *line += 1;
+                *column = 1;
+            } else {
+                *column += 1;
+            }
+            *i += 1;
+        }
+    };
+    let rest_of_line = |from: usize| chars[from..].iter().position(|c| *c == '\n').map_or(chars.len(), |end| from + end);
+    while i < chars.len() {
+        let c = chars[i];
+        let (start_line, start_column) = (line, column);
+        let (token, length) = match c {
+            '%' if column == 1 => {
+                let length = rest_of_line(i) - i;
+                advance(&mut i, &mut line, &mut column, length);
+                continue;
+            }
+            c if c.is_whitespace() => {
+                advance(&mut i, &mut line, &mut column, 1);
+                continue;
+            }
+            '[' => {
+                let (tag, length) = read_tag(&chars[i..]);
+                (tag.map_or_else(PgnToken::Bad, |(name, value)| PgnToken::Tag(name, value)), length)
+            }
+            '{' => match chars[i..].iter().position(|c| *c == '}') {
+                Some(end) => (PgnToken::Comment(chars[i + 1..i + end].iter().collect()), end + 1),
+                None => (PgnToken::Bad("comment is not closed with }".to_string()), chars.len() - i),
+            },
+            ';' => {
+                let end = rest_of_line(i);
+                (PgnToken::Comment(chars[i + 1..end].iter().collect()), end - i)
+            }
+            '(' => (PgnToken::Open, 1),
+            ')' => (PgnToken::Close, 1),
+            _ => {
+                let length = chars[i..].iter().position(|c| c.is_whitespace() || "{}()[];".contains(*c)).unwrap_or(chars.len() - i).max(1);
+                let word: String = chars[i..i + length].iter().collect();
+                let token = match word.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => PgnToken::Result(word),
+                    _ => match word.strip_prefix('$') {
+                        Some(number) => number.parse().map_or_else(|_| PgnToken::Bad(format!("{} is not an annotation glyph", word)), PgnToken::Nag),
+                        None => PgnToken::Word(word),
+                    },
+                };
+                (token, length)
+            }
+        };
+        tokens.push((token, start_line, start_column));
+        advance(&mut i, &mut line, &mut column, length);
+    }
+    tokens
+}
+
+/// Reads the tag pair `chars` starts with. Returns it, or why it cannot be read, and how
+/// many characters it takes.
+fn read_tag(chars: &[char]) -> (Result<(String, String), String>, usize) {
+    let Some(end) = chars.iter().position(|c| *c == ']' || *c == '\n') else {
+        return (Err("tag is not closed with ]".to_string()), chars.len());
+    };
+    let name: String = chars[1..].iter().take_while(|c| c.is_ascii_alphanumeric() || **c == '_').collect();
+    let mut rest = chars[1 + name.len()..].iter().enumerate().skip_while(|(_, c)| **c == ' ' || **c == '\t');
+    if name.is_empty() || rest.next().map(|(_, c)| *c) != Some('"') {
+        return (Err("tag should look like [Name \"value\"]".to_string()), end + 1);
+    }
+    let mut value = String::new();
+    let mut escaped = false;
+    for (offset, c) in rest {
+        let at = 1 + name.len() + offset;
+        match c {
+            '\n' => break,
+            c if escaped => {
+                value.push(*c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            '"' => {
+                // the closing bracket may only be preceded by blanks
+                let close = chars[at + 1..].iter().position(|c| *c != ' ' && *c != '\t').map(|skip| at + 1 + skip);
+                return match close {
+                    Some(close) if chars[close] == ']' => (Ok((name, value)), close + 1),
+                    _ => (Err("tag is not closed with ]".to_string()), end + 1),
+                };
+            }
+            c => value.push(*c),
+        }
+    }
+    (Err("tag value is not closed with \"".to_string()), end + 1)
+}
+
+/// Move annotations written after a move, for glyphs $1 to $6.
+const MOVE_SUFFIXES: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+/// Annotation glyph of a "!?" style suffix.
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    MOVE_SUFFIXES.iter().position(|s| *s == suffix).map(|index| index as u8 + 1)
+}
+
+/// "!?" style suffix of a move annotation glyph, `None` for the glyphs that have none.
+pub fn nag_suffix(nag: u8) -> Option<&'static str> {
+    MOVE_SUFFIXES.get((nag as usize).checked_sub(1)?).copied()
+}
+
+/// A line of moves while it is read.
+struct OpenLine {
+    moves: Vec<PgnMove>,
+    /// Position after the moves so far.
+    board: Board,
+    /// Position before the last move, where its variations start.
+    before_last: Board,
+    /// Comment read before the next move.
+    comment: String,
+}
+
+impl OpenLine {
+    fn new(board: Board) -> OpenLine {
+        OpenLine { moves: Vec::new(), before_last: board.clone(), board, comment: String::new() }
+    }
+}
+
+/// Builds one game out of its tokens.
+#[derive(Default)]
+struct GameReader {
+    game: PgnGame,
+    /// The main line first, then each variation that is open.
+    lines: Vec<OpenLine>,
+    /// An error stopped the game; tokens up to its result are skipped.
+    broken: bool,
+}
+
+impl GameReader {
+    fn in_movetext(&self) -> bool {
+        !self.lines.is_empty()
+    }
+
+    fn read(&mut self, token: PgnToken) -> Result<(), String> {
+        if let PgnToken::Bad(message) = token {
+            return Err(message);
+        }
+        if self.lines.is_empty() {
+            let board = UciPosition { start_fen: self.game.start_fen.clone(), moves: Vec::new() }.board();
+            self.lines.push(OpenLine::new(board));
+        }
+        let depth = self.lines.len();
+        let line = &mut self.lines[depth - 1];
+        match token {
+            PgnToken::Comment(text) => match line.moves.last_mut() {
+                Some(mv) if line.comment.is_empty() => mv.comment.read(&text),
+                _ => {
+                    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !line.comment.is_empty() && !text.is_empty() {
+                        line.comment.push(' ');
+                    }
+                    line.comment += &text;
+                }
+            },
+            PgnToken::Nag(nag) => line.moves.last_mut().ok_or("annotation glyph before the first move")?.nags.push(nag),
+            PgnToken::Open => {
+                if line.moves.is_empty() {
+                    return Err("variation before the first move".to_string());
+                }
+                let board = line.before_last.clone();
+                self.lines.push(OpenLine::new(board));
+            }
+            PgnToken::Close => {
+                if depth == 1 {
+                    return Err(") without a variation to close".to_string());
+                }
+                self.close_variation();
+            }
+            PgnToken::Word(word) => {
+                // "12", "12.", "12..." and "12.e4" carry a move number, "0-0" does not
+                let number = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let san = match &word[number..] {
+                    rest if number > 0 && (rest.is_empty() || rest.starts_with('.')) => rest.trim_start_matches('.'),
+                    _ => word.as_str(),
+                };
+                let suffix_start = san.trim_end_matches(['!', '?']).len();
+                let (san, suffix) = san.split_at(suffix_start);
+                let nag = match suffix {
+                    "" => None,
+                    suffix => Some(suffix_nag(suffix).ok_or(format!("{} is not an annotation", suffix))?),
+                };
+                if san.is_empty() {
+                    // a move number, or an annotation standing on its own
+                    if let Some(nag) = nag {
+                        line.moves.last_mut().ok_or("annotation before the first move")?.nags.push(nag);
+                    }
+                    return Ok(());
+                }
+                let uci = line.board.decode_san_move(san).ok_or_else(|| format!("{} is not a legal move here", san))?;
+                line.before_last = line.board.clone();
+                if let Some((from, to, promotion)) = parse_uci_move(&uci) {
+                    line.board.apply_move(from, to, promotion);
+                }
+                line.moves.push(PgnMove { nags: nag.into_iter().collect(), comment_before: mem::take(&mut line.comment), ..PgnMove::new(uci) });
+            }
+            PgnToken::Tag(..) | PgnToken::Result(_) | PgnToken::Bad(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Hangs the innermost variation onto the move it replaces.
+    fn close_variation(&mut self) {
+        let Some(variation) = self.lines.pop() else { return };
+        let parent = self.lines.last_mut().and_then(|line| line.moves.last_mut());
+        if let Some(parent) = parent && !variation.moves.is_empty() {
+            parent.variations.push(variation.moves);
+        }
+    }
+
+    /// The game with the variations left open closed, ended by `result` or else its Result tag.
+    fn finish(mut self, result: Option<String>) -> PgnGame {
+        while self.lines.len() > 1 {
+            self.close_variation();
+        }
+        if let Some(main) = self.lines.pop() {
+            self.game.line = main.moves;
+        }
+        self.game.result = result.or_else(|| self.game.tag("Result").map(str::to_string)).unwrap_or_else(|| "*".to_string());
+        self.game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game whose first move has a clock, an eval, text and the variation 1. d4 d5, and
+    /// whose 1... e5 has two glyphs and a variation holding a variation of its own.
+    fn annotated_game() -> PgnGame {
+        let mut game = PgnGame { result: "1-0".to_string(), ..PgnGame::default() };
+        game.set_tag("Event", "Club \"open\" championship");
+        game.set_tag("White", "Anna");
+        game.set_tag("Black", "Bert");
+        game.set_tag("TimeControl", "300+2");
+        let comment = MoveComment {
+            time: MoveTime { clock: Some(Duration::from_millis(298_500)), spent: Some(Duration::from_secs(2)) },
+            eval: Some(Score::Cp(35)),
+            text: "best by test".to_string(),
+        };
+        let e4 = PgnMove {
+            nags: vec![1],
+            comment_before: "The main line".to_string(),
+            comment,
+            variations: vec![vec![PgnMove { comment_before: "or".to_string(), ..PgnMove::new("d2d4") }, PgnMove::new("d7d5")]],
+            ..PgnMove::new("e2e4")
+        };
+        let sicilian = vec![PgnMove::new("c7c5"), PgnMove { variations: vec![vec![PgnMove::new("b1c3")]], ..PgnMove::new("g1f3") }];
+        let e5 = PgnMove { nags: vec![5, 14], variations: vec![sicilian], ..PgnMove::new("e7e5") };
+        let mate = PgnMove { comment: MoveComment { eval: Some(Score::Mate(-3)), ..MoveComment::default() }, ..PgnMove::new("b8c6") };
+        game.line = vec![e4, e5, PgnMove::new("g1f3"), mate];
+        game
+    }
+
+    fn read_one(text: &str) -> PgnGame {
+        let (mut games, errors) = parse_pgn(text);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(games.len(), 1);
+        games.remove(0)
+    }
+
+    #[test]
+    fn export_and_import_keep_everything() {
+        let game = annotated_game();
+        let text = game.to_pgn();
+        assert!(text.contains("[Event \"Club \\\"open\\\" championship\"]"), "{}", text);
+        assert!(text.contains("{[%eval 0.35] [%clk 0:04:58.5] [%emt 0:00:02] best by test}"), "{}", text);
+        assert!(text.contains("({or} 1. d4 d5) 1...\ne5 $5 $14 (1... c5 2. Nf3 (2. Nc3)) 2. Nf3"), "{}", text);
+        assert!(text.lines().all(|line| line.len() <= PGN_LINE_WIDTH));
+
+        let read = read_one(&text);
+        assert_eq!(read.line, game.line);
+        assert_eq!(read.result, "1-0");
+        assert_eq!(read.start_fen, None);
+        for name in ["Event", "White", "Black", "TimeControl"] {
+            assert_eq!(read.tag(name), game.tag(name), "{}", name);
+        }
+        // a second round gives the same text
+        assert_eq!(read.to_pgn(), text);
+    }
+
+    #[test]
+    fn export_and_import_keep_the_start_position() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40";
+        let game = PgnGame { start_fen: Some(fen.to_string()), line: vec![PgnMove::new("e8d7"), PgnMove::new("e2e4")], result: "*".to_string(), ..PgnGame::default() };
+        let text = game.to_pgn();
+        assert!(text.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]"), "{}", text);
+        assert!(text.contains("40... Kd7 41. e4 *"), "{}", text);
+        let read = read_one(&text);
+        assert_eq!(read.start_fen.as_deref(), Some(fen));
+        assert_eq!(read.moves(), ["e8d7", "e2e4"]);
+    }
+
+    #[test]
+    fn import_reads_suffixes_line_comments_and_escape_lines() {
+        let text = "[Event \"?\"]\n\n\
+            1. e4!? e5?? ; the rest of this line is a comment (not a variation)\n\
+            %this line is skipped 1. d4\n\
+            2.Qh5 $14 {a brace comment\nover two lines} Nc6 3. Bc4 Nf6?? 4. Qxf7# 1-0\n";
+        let game = read_one(text);
+        assert_eq!(game.moves(), ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"]);
+        let nags: Vec<&[u8]> = game.line.iter().map(|mv| mv.nags.as_slice()).collect();
+        assert_eq!(nags, [&[5][..], &[4], &[14], &[], &[], &[4], &[]]);
+        assert_eq!(game.line[1].comment.text, "the rest of this line is a comment (not a variation)");
+        assert_eq!(game.line[2].comment.text, "a brace comment over two lines");
+        assert!(game.line[1].variations.is_empty());
+        assert_eq!(game.result, "1-0");
+
+        // suffixes are written back as glyphs, which read the same
+        assert_eq!(read_one(&game.to_pgn()).line, game.line);
+    }
+
+    #[test]
+    fn several_games_in_one_file() {
+        let first = annotated_game();
+        let second = PgnGame { line: vec![PgnMove::new("d2d4"), PgnMove::new("g8f6")], result: "1/2-1/2".to_string(), ..PgnGame::default() };
+        let text = format!("{}\n{}", first.to_pgn(), second.to_pgn());
+        let (games, errors) = parse_pgn(&text);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].line, first.line);
+        assert_eq!((games[1].moves(), games[1].result.as_str()), (second.moves(), "1/2-1/2"));
+    }
+
+    #[test]
+    fn errors_point_at_the_game_line_and_column() {
+        let text = "[Event \"first\"]\n\n1. e4 e5 1-0\n\n\
+            [Event \"second\"]\n\n1. d4 d5\n2. c4 Ke6 3. Nc3 0-1\n\n\
+            [Event \"third\"]\n\n1. Nf3 {open *\n";
+        let (games, errors) = parse_pgn(text);
+        assert_eq!(games.len(), 3);
+        assert_eq!(
+            errors,
+            [
+                PgnError { game: 2, line: 8, column: 7, message: "Ke6 is not a legal move here".to_string() },
+                PgnError { game: 3, line: 12, column: 8, message: "comment is not closed with }".to_string() },
+            ]
+        );
+        // the broken game keeps the moves before the error and its result
+        assert_eq!(games[1].moves(), ["d2d4", "d7d5", "c2c4"]);
+        assert_eq!(games[1].result, "0-1");
+        assert_eq!(games[2].moves(), ["g1f3"]);
+    }
+}
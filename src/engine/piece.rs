@@ -30,23 +30,23 @@ impl Default for ChessPiece {
         }
     }
 }
-impl ToString for PieceColor {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for PieceColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            PieceColor::White => "White".to_owned(),
-            PieceColor::Black => "Black".to_owned(),
+            PieceColor::White => f.write_str("White"),
+            PieceColor::Black => f.write_str("Black"),
         }
     }
 }
-impl ToString for PieceType {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for PieceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            PieceType::King => "King".to_owned(),
-            PieceType::Queen => "Queen".to_owned(),
-            PieceType::Bishop => "Bishop".to_owned(),
-            PieceType::Knight => "Knight".to_owned(),
-            PieceType::Pawn => "Pawn".to_owned(),
-            PieceType::Rook => "Rook".to_owned(),
+            PieceType::King => f.write_str("King"),
+            PieceType::Queen => f.write_str("Queen"),
+            PieceType::Bishop => f.write_str("Bishop"),
+            PieceType::Knight => f.write_str("Knight"),
+            PieceType::Pawn => f.write_str("Pawn"),
+            PieceType::Rook => f.write_str("Rook"),
         
         }
     }
@@ -0,0 +1,453 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::engine::{fen::{fen_parser, FenError}, uci::parse_uci_move, Board, PieceColor};
+
+/// What a composed problem asks for, parsed from strings such as "#2", "h#3", "h#2.5" or "s#2".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stipulation {
+    /// White to play and mate in `n` moves against any defence.
+    Direct(u32),
+    /// Both sides cooperate so that White mates Black after `plies` single moves.
+    /// Black starts unless the stipulation has a half move (h#2.5), then White does.
+    Help { plies: u32 },
+    /// White to play and force Black to deliver mate in `n` moves.
+    SelfMate(u32),
+}
+
+#[derive(Debug)]
+pub enum ProblemError {
+    Fen(FenError),
+    Stipulation(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for ProblemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemError::Fen(e) => write!(f, "{}", e),
+            ProblemError::Stipulation(s) => write!(f, "unknown stipulation \"{}\"", s),
+            ProblemError::IllegalMove(s) => write!(f, "cannot play {} here", s),
+        }
+    }
+}
+
+impl std::str::FromStr for Stipulation {
+    type Err = ProblemError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim().to_ascii_lowercase();
+        let error = || ProblemError::Stipulation(s.to_string());
+        let (kind, count) = text.split_once('#').ok_or_else(error)?;
+        match kind {
+            "" => count.parse().ok().filter(|n| *n > 0).map(Stipulation::Direct).ok_or_else(error),
+            "s" => count.parse().ok().filter(|n| *n > 0).map(Stipulation::SelfMate).ok_or_else(error),
+            "h" => {
+                let (whole, half) = match count.split_once('.') {
+                    Some((whole, "5")) => (whole, 1),
+                    Some(_) => return Err(error()),
+                    None => (count, 0),
+                };
+                let moves: u32 = whole.parse().map_err(|_| error())?;
+                if moves == 0 && half == 0 {
+                    return Err(error());
+                }
+                Ok(Stipulation::Help { plies: moves * 2 + half })
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Stipulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stipulation::Direct(n) => write!(f, "#{}", n),
+            Stipulation::SelfMate(n) => write!(f, "s#{}", n),
+            Stipulation::Help { plies } if plies % 2 == 1 => write!(f, "h#{}.5", plies / 2),
+            Stipulation::Help { plies } => write!(f, "h#{}", plies / 2),
+        }
+    }
+}
+
+/// One way to fulfil the stipulation, in SAN.
+/// For helpmates `moves` is the whole cooperative line; for direct mates and selfmates it
+/// holds the key, and `variations` lists the play after it, one line per defence.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub moves: Vec<String>,
+    pub variations: Vec<Vec<String>>,
+}
+
+pub struct SolveReport {
+    pub stipulation: Stipulation,
+    pub start_fen: String,
+    pub solutions: Vec<Solution>,
+    /// False when the search stopped at its solution limit, so there may be more.
+    pub complete: bool,
+}
+
+impl SolveReport {
+    /// A problem is sound when it has exactly one solution (one key for direct and selfmates).
+    pub fn is_sound(&self) -> bool {
+        self.solutions.len() == 1
+    }
+
+    /// Every solution past the first; for a problem meant to have a single solution these are cooks.
+    pub fn cooks(&self) -> &[Solution] {
+        if self.solutions.len() > 1 { &self.solutions[1..] } else { &[] }
+    }
+}
+
+impl fmt::Display for SolveReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board = Board::from(&self.start_fen);
+        if self.solutions.is_empty() {
+            return writeln!(f, "{}: no solution", self.stipulation);
+        }
+        if !self.complete {
+            writeln!(f, "Search stopped after {} solutions", self.solutions.len())?;
+        }
+        for (i, solution) in self.solutions.iter().enumerate() {
+            let label = if i == 0 { "Solution".to_string() } else { format!("Cook {}", i) };
+            writeln!(f, "{}: {}", label, numbered_line(board.turn, board.fullmove_number, &solution.moves))?;
+            // variations start with the defender, right after the key
+            let after_key = match board.turn {
+                PieceColor::White => (PieceColor::Black, board.fullmove_number),
+                PieceColor::Black => (PieceColor::White, board.fullmove_number + 1),
+            };
+            for variation in &solution.variations {
+                writeln!(f, "    {}", numbered_line(after_key.0, after_key.1, variation))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats SAN moves with move numbers, e.g. "1...Kd5 2.Qc4+ Kxc4".
+pub fn numbered_line(first_mover: PieceColor, first_number: u32, moves: &[String]) -> String {
+    let mut text = String::new();
+    let mut number = first_number;
+    let mut color = first_mover;
+    for (i, san) in moves.iter().enumerate() {
+        match color {
+            PieceColor::White => {
+                text += &format!("{}{}.{}", if i > 0 { " " } else { "" }, number, san);
+                color = PieceColor::Black;
+            }
+            PieceColor::Black => {
+                if i == 0 {
+                    text += &format!("{}...{}", number, san);
+                } else {
+                    text += &format!(" {}", san);
+                }
+                color = PieceColor::White;
+                number += 1;
+            }
+        }
+    }
+    text
+}
+
+/// Solves the problem given by `fen` and a stipulation string, returning every solution.
+pub fn solve(fen: &str, stipulation: &str) -> Result<SolveReport, ProblemError> {
+    solve_with_limit(fen, stipulation, None)
+}
+
+/// Like [`solve`], but gives up once `limit` solutions are found. A limit of 2 stops at the
+/// first cook, which is all it takes to tell a sound problem from a cooked one.
+pub fn solve_with_limit(fen: &str, stipulation: &str, limit: Option<usize>) -> Result<SolveReport, ProblemError> {
+    let stipulation: Stipulation = stipulation.parse()?;
+    let mut board = fen_parser(fen).map_err(ProblemError::Fen)?;
+    board.turn = match stipulation {
+        Stipulation::Help { plies } if plies % 2 == 0 => PieceColor::Black,
+        _ => PieceColor::White,
+    };
+    let start_fen = board.to_string();
+
+    let goal = if matches!(stipulation, Stipulation::SelfMate(_)) { Goal::SelfMate } else { Goal::Mate };
+    let mut search = Search { goal, limit, found: 0, help: HashMap::new(), forced: HashMap::new() };
+    let mut solutions = Vec::new();
+    match stipulation {
+        Stipulation::Help { plies } => {
+            for line in search.help_lines(&board, plies)?.iter() {
+                solutions.push(Solution { moves: to_san(&board, line)?, variations: Vec::new() });
+            }
+        }
+        Stipulation::Direct(n) | Stipulation::SelfMate(n) => {
+            for key in board.legal_moves() {
+                if search.limit_reached() {
+                    break;
+                }
+                let mut after_key = play(&board, &key)?;
+                if search.fulfils_after(&mut after_key, n)? {
+                    search.found += 1;
+                    let mut variations = Vec::new();
+                    for line in search.play_after_key(&mut after_key, n)? {
+                        variations.push(to_san(&after_key, &line)?);
+                    }
+                    solutions.push(Solution { moves: to_san(&board, &[key])?, variations });
+                }
+            }
+        }
+    }
+    let complete = !search.limit_reached();
+    Ok(SolveReport { stipulation, start_fen, solutions, complete })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Goal {
+    /// White mates Black.
+    Mate,
+    /// Black is forced to mate White.
+    SelfMate,
+}
+
+/// State shared by one solve: the solution count so far and what is already known about
+/// positions reached through different move orders.
+struct Search {
+    goal: Goal,
+    limit: Option<usize>,
+    found: usize,
+    /// Helpmate lines from a position, by position and plies left.
+    help: HashMap<(String, u32), Rc<Vec<Vec<String>>>>,
+    /// Whether White, to move, reaches the goal, by position and moves left.
+    forced: HashMap<(String, u32), bool>,
+}
+
+impl Search {
+    fn limit_reached(&self) -> bool {
+        self.limit.is_some_and(|limit| self.found >= limit)
+    }
+
+    /// Collects every cooperative line of `plies` moves that ends with White mating Black.
+    fn help_lines(&mut self, board: &Board, plies: u32) -> Result<Rc<Vec<Vec<String>>>, ProblemError> {
+        let key = (position_key(board), plies);
+        if let Some(lines) = self.help.get(&key) {
+            self.found += lines.len();
+            return Ok(lines.clone());
+        }
+        let mut lines = Vec::new();
+        for uci in board.clone().legal_moves() {
+            if self.limit_reached() {
+                break;
+            }
+            let mut next = play(board, &uci)?;
+            if plies == 1 {
+                // the mating move has to give check, which is far cheaper to test than the replies
+                if next.turn == PieceColor::Black && next.is_in_check(PieceColor::Black) && next.legal_moves().is_empty() {
+                    self.found += 1;
+                    lines.push(vec![uci]);
+                }
+                continue;
+            }
+            for tail in self.help_lines(&next, plies - 1)?.iter() {
+                let mut line = vec![uci.clone()];
+                line.extend(tail.iter().cloned());
+                lines.push(line);
+            }
+        }
+        let lines = Rc::new(lines);
+        // a search cut short by the limit leaves the lines incomplete, they must not be reused
+        if !self.limit_reached() {
+            self.help.insert(key, lines.clone());
+        }
+        Ok(lines)
+    }
+
+    /// White to move: can White reach the goal within `n` moves?
+    fn fulfils(&mut self, board: &mut Board, n: u32) -> Result<bool, ProblemError> {
+        let key = (position_key(board), n);
+        if let Some(&known) = self.forced.get(&key) {
+            return Ok(known);
+        }
+        let mut result = false;
+        for uci in board.legal_moves() {
+            if self.fulfils_after(&mut play(board, &uci)?, n)? {
+                result = true;
+                break;
+            }
+        }
+        self.forced.insert(key, result);
+        Ok(result)
+    }
+
+    /// Black to move right after a White move: does that move reach the goal within `n` moves
+    /// whatever Black replies? Stops at the first defence that refutes it.
+    fn fulfils_after(&mut self, board: &mut Board, n: u32) -> Result<bool, ProblemError> {
+        // a last move that mates has to give check, skip the rest without generating replies
+        if self.goal == Goal::Mate && n == 1 && !board.is_in_check(PieceColor::Black) {
+            return Ok(false);
+        }
+        let defences = board.legal_moves();
+        if defences.is_empty() {
+            // Black is mated or stalemated, only a mate counts and only for a direct mate
+            return Ok(self.goal == Goal::Mate && board.is_in_check(PieceColor::Black));
+        }
+        if self.goal == Goal::Mate && n == 1 {
+            return Ok(false);
+        }
+        for uci in defences {
+            let mut next = play(board, &uci)?;
+            let holds = match self.goal {
+                Goal::Mate => self.fulfils(&mut next, n - 1)?,
+                Goal::SelfMate => is_mated(&mut next) || (n > 1 && self.fulfils(&mut next, n - 1)?),
+            };
+            if !holds {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// After a working key, one line per Black defence showing how White carries on.
+    fn play_after_key(&mut self, board: &mut Board, n: u32) -> Result<Vec<Vec<String>>, ProblemError> {
+        let mut lines = Vec::new();
+        for defence in board.legal_moves() {
+            let mut next = play(board, &defence)?;
+            if is_mated(&mut next) || n == 1 {
+                lines.push(vec![defence]);
+                continue;
+            }
+            let mut continuation = None;
+            for uci in next.legal_moves() {
+                let mut after = play(&next, &uci)?;
+                if self.fulfils_after(&mut after, n - 1)? {
+                    continuation = Some((uci, after));
+                    break;
+                }
+            }
+            match continuation {
+                Some((uci, mut after)) => {
+                    let rest = self.play_after_key(&mut after, n - 1)?;
+                    if rest.is_empty() {
+                        lines.push(vec![defence.clone(), uci.clone()]);
+                    }
+                    for tail in rest {
+                        let mut line = vec![defence.clone(), uci.clone()];
+                        line.extend(tail);
+                        lines.push(line);
+                    }
+                }
+                None => lines.push(vec![defence]),
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// The FEN without its move clocks, so the same position reached at another move number matches.
+fn position_key(board: &Board) -> String {
+    let fen = board.to_string();
+    match fen.rsplitn(3, ' ').nth(2) {
+        Some(position) => position.to_string(),
+        None => fen,
+    }
+}
+
+fn play(board: &Board, uci: &str) -> Result<Board, ProblemError> {
+    let illegal = || ProblemError::IllegalMove(uci.to_string());
+    let (from, to, promotion) = parse_uci_move(uci).ok_or_else(illegal)?;
+    if board.squares[from.0 as usize][from.1 as usize].is_none() {
+        return Err(illegal());
+    }
+    let mut next = board.clone();
+    next.apply_move(from, to, promotion);
+    Ok(next)
+}
+
+fn is_mated(board: &mut Board) -> bool {
+    board.is_in_check(board.turn) && board.legal_moves().is_empty()
+}
+
+fn to_san(board: &Board, line: &[String]) -> Result<Vec<String>, ProblemError> {
+    let mut board = board.clone();
+    let mut moves = Vec::new();
+    for uci in line {
+        moves.push(board.encode_san_move(uci).unwrap_or_else(|| uci.clone()));
+        board = play(&board, uci)?;
+    }
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{play, solve, solve_with_limit, ProblemError, Stipulation};
+    use crate::engine::Board;
+
+    fn keys(fen: &str, stipulation: &str) -> Vec<String> {
+        let report = solve(fen, stipulation).unwrap();
+        report.solutions.iter().map(|solution| solution.moves[0].clone()).collect()
+    }
+
+    #[test]
+    fn stipulations_parse_and_print_back() {
+        for text in ["#2", "h#3", "h#2.5", "h#0.5", "s#1"] {
+            let stipulation: Stipulation = text.parse().unwrap();
+            assert_eq!(stipulation.to_string(), text);
+        }
+        assert_eq!("h#2.5".parse::<Stipulation>().unwrap(), Stipulation::Help { plies: 5 });
+        for text in ["", "#0", "h#0", "h#2.3", "x#2", "#two"] {
+            assert!(text.parse::<Stipulation>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sound_mate_in_two() {
+        let report = solve("k7/8/2K5/8/8/8/8/1R6 w - - 0 1", "#2").unwrap();
+        assert!(report.is_sound());
+        assert!(report.complete);
+        assert_eq!(report.solutions[0].moves, ["Kc7"]);
+        assert_eq!(report.solutions[0].variations, [["Ka7", "Ra1#"]]);
+    }
+
+    #[test]
+    fn cooked_mate_in_two() {
+        let keys = keys("k7/8/1K6/8/8/8/8/2R5 w - - 0 1", "#2");
+        assert_eq!(keys.len(), 7);
+        assert!(keys.contains(&"Kc7".to_string()));
+        // a mate in one also mates in two
+        assert!(keys.contains(&"Rc8#".to_string()));
+    }
+
+    #[test]
+    fn sound_helpmate_in_two() {
+        let report = solve("8/k1p5/4p3/8/1K6/1R6/8/8 w - - 0 1", "h#2").unwrap();
+        assert!(report.is_sound());
+        assert_eq!(report.solutions[0].moves, ["Ka6", "Kc5", "Ka5", "Ra3#"]);
+    }
+
+    #[test]
+    fn cooked_helpmate_in_two() {
+        let fen = "6rk/6p1/8/8/8/8/8/K5R1 w - - 0 1";
+        let report = solve(fen, "h#2").unwrap();
+        assert_eq!(report.solutions.len(), 72);
+        assert!(report.complete);
+
+        let report = solve_with_limit(fen, "h#2", Some(2)).unwrap();
+        assert_eq!(report.solutions.len(), 2);
+        assert!(!report.complete);
+        assert!(!report.is_sound());
+    }
+
+    #[test]
+    fn sound_selfmate_in_two() {
+        // the bishop on d6 stops 1.Qd1+ Rxd1 until it has moved out of the way with check
+        let report = solve("3r4/8/3B4/3Q4/N7/8/p5PP/k6K w - - 0 1", "s#2").unwrap();
+        assert!(report.is_sound());
+        assert_eq!(report.solutions[0].moves, ["Be5+"]);
+        assert_eq!(report.solutions[0].variations, [["Kb1", "Qd1+", "Rxd1#"]]);
+    }
+
+    #[test]
+    fn cooked_selfmate_in_two() {
+        assert_eq!(keys("3r4/8/8/N2Q4/N7/8/p5PP/k6K w - - 0 1", "s#2"), ["Nb3+", "Qd1+"]);
+    }
+
+    #[test]
+    fn playing_an_unreadable_move_is_an_error() {
+        let board = Board::from(&"k7/8/2K5/8/8/8/8/1R6 w - - 0 1".to_string());
+        assert!(matches!(play(&board, "b1b9"), Err(ProblemError::IllegalMove(_))));
+        assert!(matches!(play(&board, "d4d5"), Err(ProblemError::IllegalMove(_))));
+        assert!(play(&board, "b1b2").is_ok());
+    }
+}
@@ -1,16 +1,11 @@
 use crate::engine::{Board, ChessPiece, PieceType};
 
 impl Board{
-pub fn filter_quiet_moves(&self, piece: &ChessPiece, moves:&Vec<(u8,u8)>) -> Vec<(u8,u8)>{
+pub fn filter_quiet_moves(&self, piece: &ChessPiece, moves:&[(u8,u8)]) -> Vec<(u8,u8)>{
     moves.iter().filter(|pos| {
       match self.squares[pos.0 as usize][pos.1 as usize] {
         None => {
-           if piece.kind == PieceType::Pawn{
-               if piece.position.1 == pos.1 {
-                true }
-                else { false}
-            } 
-            else {true }
+            piece.kind != PieceType::Pawn || piece.position.1 == pos.1
         
         },
         _ => false,
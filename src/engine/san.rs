@@ -1,7 +1,229 @@
-use crate::engine::Board;
+use crate::engine::{uci::parse_uci_move, Board, ChessPiece, PieceColor, PieceType};
 
 impl Board {
-    pub fn encode_san_move(&self) {
+    /// Encodes a legal move given in UCI notation (e.g. "g1f3") as SAN (e.g. "Nf3"),
+    /// using the position before the move is played.
+    pub fn encode_san_move(&self, uci: &str) -> Option<String> {
+        let (from, to, promotion) = parse_uci_move(uci)?;
+        let piece = self.squares[from.0 as usize][from.1 as usize]?;
+        let is_capture = match self.squares[to.0 as usize][to.1 as usize] {
+            Some(target) => target.color != piece.color,
+            None => piece.kind == PieceType::Pawn && from.1 != to.1,
+        };
+        let mut after = self.clone();
+        after.apply_move(from, to, promotion);
+        Some(self.san_body(&piece, from, to, promotion, is_capture) + after.check_suffix(piece.color))
+    }
+
+    /// Builds the SAN of a move that has already been played on this board, the moved piece
+    /// standing on `to`. Used when recording moves, where only the resulting position is left.
+    pub fn san_after_move(&self, from: (u8, u8), to: (u8, u8), promotion: Option<PieceType>, is_capture: bool) -> String {
+        let mut piece = match self.squares[to.0 as usize][to.1 as usize] {
+            Some(p) => p,
+            None => return String::new(),
+        };
+        let suffix = self.check_suffix(piece.color);
+        if piece.kind == PieceType::King && from.1.abs_diff(to.1) == 2 {
+            let castle = if to.1 > from.1 { "O-O" } else { "O-O-O" };
+            return castle.to_string() + suffix;
+        }
+        // put the piece back to see which of its siblings could have made the same move
+        let mut before = self.clone();
+        if promotion.is_some() {
+            piece.kind = PieceType::Pawn;
+        }
+        piece.position = from;
+        before.squares[to.0 as usize][to.1 as usize] = None;
+        before.squares[from.0 as usize][from.1 as usize] = Some(piece);
+        before.turn = piece.color;
+        before.san_body(&piece, from, to, promotion, is_capture) + suffix
+    }
+
+    fn san_body(&self, piece: &ChessPiece, from: (u8, u8), to: (u8, u8), promotion: Option<PieceType>, is_capture: bool) -> String {
+        if piece.kind == PieceType::King {
+            let onto_own_rook = matches!(self.squares[to.0 as usize][to.1 as usize], Some(t) if t.color == piece.color);
+            if from.1.abs_diff(to.1) == 2 || onto_own_rook {
+                return if to.1 > from.1 { "O-O".to_string() } else { "O-O-O".to_string() };
+            }
+        }
+        let mut san = String::new();
+        match piece.kind {
+            PieceType::Pawn => {
+                if is_capture {
+                    san.push(file_char(from.1));
+                }
+            }
+            kind => {
+                san.push(piece_letter(kind));
+                san += &self.disambiguation(piece, from, to);
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san += &square_name(to);
+        if let Some(kind) = promotion {
+            san.push('=');
+            san.push(piece_letter(kind));
+        }
+        san
+    }
+
+    /// File, rank or full square of `from` when another piece of the same kind can also reach `to`.
+    fn disambiguation(&self, piece: &ChessPiece, from: (u8, u8), to: (u8, u8)) -> String {
+        let rivals: Vec<ChessPiece> = self
+            .squares
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|p| p.kind == piece.kind && p.color == piece.color && p.position != from)
+            .cloned()
+            .collect();
+        if rivals.is_empty() {
+            return String::new();
+        }
+        let mut board = self.clone();
+        let rivals: Vec<ChessPiece> = rivals
+            .into_iter()
+            .filter(|rival| {
+                let (quiet, captures) = board.get_legal_moves(rival);
+                quiet.contains(&to) || captures.contains(&to)
+            })
+            .collect();
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|p| p.position.1 != from.1) {
+            file_char(from.1).to_string()
+        } else if rivals.iter().all(|p| p.position.0 != from.0) {
+            (8 - from.0).to_string()
+        } else {
+            square_name(from)
+        }
+    }
+
+    /// Finds the legal move written in SAN (e.g. "Nbd7", "exd5", "e8=Q+", "O-O") and returns it
+    /// in UCI notation. Check marks, annotations and a missing "=" before the promotion piece
+    /// are accepted.
+    pub fn decode_san_move(&self, san: &str) -> Option<String> {
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+        let mut board = self.clone();
+        let legal = board.legal_moves();
+        if text == "O-O" || text == "O-O-O" {
+            let king_side = text == "O-O";
+            return legal.into_iter().find(|uci| {
+                let Some((from, to, _)) = parse_uci_move(uci) else { return false };
+                matches!(self.squares[from.0 as usize][from.1 as usize], Some(p) if p.kind == PieceType::King)
+                    && from.1.abs_diff(to.1) == 2
+                    && (to.1 > from.1) == king_side
+            });
+        }
+        let mut chars: Vec<char> = text.chars().filter(|c| *c != 'x' && *c != '=' && *c != ':').collect();
+        let kind = match chars.first() {
+            Some('N') => PieceType::Knight,
+            Some('B') => PieceType::Bishop,
+            Some('R') => PieceType::Rook,
+            Some('Q') => PieceType::Queen,
+            Some('K') => PieceType::King,
+            Some('P') => PieceType::Pawn,
+            Some(_) => {
+                chars.insert(0, 'P');
+                PieceType::Pawn
+            }
+            None => return None,
+        };
+        let promotion = match chars.last() {
+            Some('N') | Some('n') => Some(PieceType::Knight),
+            Some('B') | Some('b') if chars.len() > 3 && chars[chars.len() - 2].is_ascii_digit() => Some(PieceType::Bishop),
+            Some('R') | Some('r') => Some(PieceType::Rook),
+            Some('Q') | Some('q') => Some(PieceType::Queen),
+            _ => None,
+        };
+        if promotion.is_some() {
+            chars.pop();
+        }
+        if chars.len() < 3 {
+            return None;
+        }
+        let destination: String = chars[chars.len() - 2..].iter().collect();
+        let hint: Vec<char> = chars[1..chars.len() - 2].to_vec();
+        let candidates: Vec<String> = legal
+            .into_iter()
+            .filter(|uci| {
+                let Some((from, _, promo)) = parse_uci_move(uci) else { return false };
+                let Some(piece) = self.squares[from.0 as usize][from.1 as usize] else { return false };
+                let from_name = square_name(from);
+                piece.kind == kind
+                    && uci[2..4] == destination
+                    && promo == promotion
+                    && hint.iter().all(|c| from_name.contains(*c))
+            })
+            .collect();
+        match candidates.as_slice() {
+            [uci] => Some(uci.clone()),
+            _ => None,
+        }
+    }
+
+    /// SAN of a line of UCI moves played from this position, such as an engine's principal
+    /// variation. Stops at the first move that is not legal.
+    pub fn san_line(&self, line: &[String]) -> Vec<String> {
+        let mut board = self.clone();
+        let mut sans = Vec::new();
+        for uci in line {
+            let Some(san) = board.encode_san_move(uci) else { break };
+            if board.play_uci_move(uci).is_err() {
+                break;
+            }
+            sans.push(san);
+        }
+        sans
+    }
+
+    /// "+" or "#" when the side that just moved (`mover`) gives check or mate.
+    pub fn check_suffix(&self, mover: PieceColor) -> &'static str {
+        let opponent = match mover {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        if !self.is_in_check(opponent) {
+            return "";
+        }
+        let mut board = self.clone();
+        board.turn = opponent;
+        if board.legal_moves().is_empty() { "#" } else { "+" }
+    }
+}
 
+pub fn piece_letter(kind: PieceType) -> char {
+    match kind {
+        PieceType::Pawn => 'P',
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Rook => 'R',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
     }
-}
\ No newline at end of file
+}
+
+/// SAN with figurines instead of piece letters: "Nf3" becomes "♘f3".
+pub fn figurine(san: &str) -> String {
+    san.chars()
+        .map(|c| match c {
+            'K' => '♔',
+            'Q' => '♕',
+            'R' => '♖',
+            'B' => '♗',
+            'N' => '♘',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn file_char(file: u8) -> char {
+    (b'a' + file) as char
+}
+
+/// Algebraic name of a (rank, file) board square, e.g. (7, 4) -> "e1".
+pub fn square_name(square: (u8, u8)) -> String {
+    format!("{}{}", file_char(square.1), 8 - square.0)
+}
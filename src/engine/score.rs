@@ -0,0 +1,134 @@
+use std::fmt;
+
+use crate::{engine::PieceColor, game::uci_client::InfoScore};
+
+/// Centipawns a mate counts for when scores are compared or drawn.
+pub const MATE_CP: i32 = 100_000;
+
+/// Engine evaluation from White's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// Centipawns, positive when White is better.
+    Cp(i32),
+    /// Mate in this many moves, positive when White mates.
+    Mate(i32),
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Score::Cp(0)
+    }
+}
+
+impl Score {
+    /// Converts a score relative to the side to move, as engines report it. A mate in 0
+    /// means the game is already over and has no score.
+    pub fn from_side_to_move(score: InfoScore, turn: PieceColor) -> Option<Score> {
+        let sign = if turn == PieceColor::White { 1 } else { -1 };
+        match score {
+            InfoScore::Cp(cp) => Some(Score::Cp(sign * cp)),
+            InfoScore::Mate(0) => None,
+            InfoScore::Mate(moves) => Some(Score::Mate(sign * moves)),
+        }
+    }
+
+    /// Centipawns, with a mate worth more than any advantage and a quicker mate worth more.
+    pub fn centipawns(&self) -> i32 {
+        match *self {
+            Score::Cp(cp) => cp,
+            Score::Mate(moves) => moves.signum() * (MATE_CP - moves.abs()),
+        }
+    }
+
+    /// The same score seen from `color`'s side.
+    pub fn for_color(&self, color: PieceColor) -> Score {
+        match (color, *self) {
+            (PieceColor::White, score) => score,
+            (PieceColor::Black, Score::Cp(cp)) => Score::Cp(-cp),
+            (PieceColor::Black, Score::Mate(moves)) => Score::Mate(-moves),
+        }
+    }
+}
+
+/// "+0.35" in pawns, or "M3" / "-M3" for a mate by White / Black.
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Score::Cp(cp) => write!(f, "{:+.2}", cp as f32 / 100.0),
+            Score::Mate(moves) if moves < 0 => write!(f, "-M{}", -moves),
+            Score::Mate(moves) => write!(f, "M{}", moves),
+        }
+    }
+}
+
+/// Centipawns at which the win curve of the model crosses 50%.
+const WDL_MODEL_CENTER: f32 = 190.0;
+/// Centipawns over which the model's win chance rises by a factor of e.
+const WDL_MODEL_SPREAD: f32 = 230.0;
+
+/// Win/draw/loss chances in permille from White's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wdl {
+    pub win: u32,
+    pub draw: u32,
+    pub loss: u32,
+}
+
+impl Default for Wdl {
+    fn default() -> Self {
+        Wdl::from_score(Score::default())
+    }
+}
+
+impl Wdl {
+    /// Converts the `wdl` of an info line, which is for the side to move.
+    pub fn from_side_to_move((win, draw, loss): (u32, u32, u32), turn: PieceColor) -> Wdl {
+        match turn {
+            PieceColor::White => Wdl { win, draw, loss },
+            PieceColor::Black => Wdl { win: loss, draw, loss: win },
+        }
+    }
+
+    /// Estimate for engines that do not report WDL. Win and loss follow logistic curves,
+    /// fitted so the expected score matches the Lichess win-percent curve with about 40%
+    /// draws in an equal position. A mate is a certain result.
+    pub fn from_score(score: Score) -> Wdl {
+        match score {
+            Score::Mate(moves) if moves > 0 => Wdl { win: 1000, draw: 0, loss: 0 },
+            Score::Mate(_) => Wdl { win: 0, draw: 0, loss: 1000 },
+            Score::Cp(cp) => {
+                let curve = |cp: f32| 1.0 / (1.0 + ((WDL_MODEL_CENTER - cp) / WDL_MODEL_SPREAD).exp());
+                let win = (curve(cp as f32) * 1000.0).round() as u32;
+                let loss = (curve(-cp as f32) * 1000.0).round() as u32;
+                Wdl { win, draw: 1000u32.saturating_sub(win + loss), loss }
+            }
+        }
+    }
+
+    /// Points White can expect from the position, between 0 and 1.
+    pub fn expected_score(&self) -> f32 {
+        let total = (self.win + self.draw + self.loss).max(1) as f32;
+        (self.win as f32 + self.draw as f32 / 2.0) / total
+    }
+
+    /// Percentages of win, draw and loss, for showing to the user.
+    pub fn percentages(&self) -> (f32, f32, f32) {
+        let total = (self.win + self.draw + self.loss).max(1) as f32;
+        (self.win as f32 * 100.0 / total, self.draw as f32 * 100.0 / total, self.loss as f32 * 100.0 / total)
+    }
+}
+
+/// "?!", "?" or "??" for a move by `mover` that lost expected score between the positions
+/// before and after it.
+pub fn annotation(before: Wdl, after: Wdl, mover: PieceColor) -> Option<&'static str> {
+    let mut lost = before.expected_score() - after.expected_score();
+    if mover == PieceColor::Black {
+        lost = -lost;
+    }
+    match lost {
+        l if l >= 0.3 => Some("??"),
+        l if l >= 0.2 => Some("?"),
+        l if l >= 0.1 => Some("?!"),
+        _ => None,
+    }
+}
@@ -3,46 +3,23 @@ use crate::engine::{Board, ChessPiece, PieceColor, PieceType};
 impl Board{
     pub fn simulate_move(& self, piece: &ChessPiece, new_pos: &(u8, u8)) -> bool {
         let old_pos = piece.position;
-        let mut board = self.clone();
-        board.squares[old_pos.0 as usize][old_pos.1 as usize] = None;
-        board.squares[new_pos.0 as usize][new_pos.1 as usize] = Some(*piece);
-        let res = board.is_in_check(piece.color);
-        board.squares[old_pos.0 as usize][old_pos.1 as usize] = Some(*piece);
-        board.squares[new_pos.0 as usize][new_pos.1 as usize] = None;
-        return res;
-        
+        // only the squares are needed, copying them is much cheaper than cloning the board
+        let mut squares = self.squares;
+        // an en passant capture also removes the pawn standing next to us
+        if piece.kind == PieceType::Pawn && old_pos.1 != new_pos.1 && squares[new_pos.0 as usize][new_pos.1 as usize].is_none() {
+            squares[old_pos.0 as usize][new_pos.1 as usize] = None;
+        }
+        squares[old_pos.0 as usize][old_pos.1 as usize] = None;
+        squares[new_pos.0 as usize][new_pos.1 as usize] = Some(*piece);
+        in_check(&squares, piece.color)
      }
     pub fn is_in_check(&self, color: PieceColor) -> bool {
-        // Find king position
-        let mut king_pos = None;
-        for (r, row) in self.squares.iter().enumerate() {
-            for (c, square) in row.iter().enumerate() {
-                if let Some(piece) = square {
-                    if piece.kind == PieceType::King && piece.color == color {
-                        king_pos = Some((r as u8, c as u8));
-                        break;
-                    }
-                }
-            }
-            if king_pos.is_some() { break; }
-        }
-        
-        if let Some(king_position) = king_pos {
-            // Check if any enemy piece attacks the king square
-            for row in &self.squares {
-                for square in row {
-                    if let Some(piece) = square {
-                        if piece.color != color {
-                            let attacks = self.get_attack_squares(piece);
-                            if attacks.contains(&king_position) {
-                                return true;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        false
+        in_check(&self.squares, color)
+    }
+
+    /// Whether a piece of `by` attacks `square`.
+    pub fn is_attacked(&self, square: (u8, u8), by: PieceColor) -> bool {
+        attacked(&self.squares, square, by)
     }
     
     
@@ -51,7 +28,7 @@ impl Board{
         if !self.is_in_check(self.turn){
             return false;
         }else{
-            let squares = self.squares.clone();
+            let squares = self.squares;
             for rank in squares{
                 for file in rank{
                     match  file {
@@ -74,7 +51,7 @@ impl Board{
         if self.is_in_check(self.turn){
             return false;
         }else {
-            let squares = self.squares.clone();
+            let squares = self.squares;
             for rank in squares{
                 for file in rank{
                     match  file {
@@ -94,6 +71,210 @@ impl Board{
         stale
     }
     pub fn has_lost(&mut self) -> bool{
-        return self.is_chackmate() || self.is_stale_mate() || self.halfmove_clock == 50;
+        self.is_chackmate() || self.is_stale_mate() || self.halfmove_clock >= 100
     }
-}
\ No newline at end of file
+
+    /// Neither side can ever mate: bare kings, or a single bishop or knight left on the board.
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minors = 0;
+        for piece in self.squares.iter().flatten().flatten() {
+            match piece.kind {
+                PieceType::King => {}
+                PieceType::Bishop | PieceType::Knight => minors += 1,
+                _ => return false,
+            }
+        }
+        minors <= 1
+    }
+
+    /// Whether `color` has the material to mate at all, which a win on time needs: more than
+    /// a lone king, and a single minor piece only when the other side has pieces that could
+    /// block its own king in.
+    pub fn can_mate(&self, color: PieceColor) -> bool {
+        let mut minors = 0;
+        let mut defenders = false;
+        for piece in self.squares.iter().flatten().flatten() {
+            match (piece.color == color, piece.kind) {
+                (_, PieceType::King) => {}
+                (true, PieceType::Bishop | PieceType::Knight) => minors += 1,
+                (true, _) => return true,
+                (false, _) => defenders = true,
+            }
+        }
+        minors >= 2 || (minors == 1 && defenders)
+    }
+
+    /// Piece placement, side to move, castling rights and en passant square: the part of the
+    /// FEN that has to match for positions to count as repeated.
+    pub fn position_key(&self) -> String {
+        self.to_string().split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Plays a move without any legality checks or move recording.
+    /// Handles castling (king moving two files or onto its own rook), en passant and promotion
+    /// (defaulting to a queen), and keeps castling rights, clocks and the side to move up to date.
+    pub fn apply_move(&mut self, from: (u8, u8), to: (u8, u8), promotion: Option<PieceType>) {
+        let mut piece = match self.squares[from.0 as usize][from.1 as usize] {
+            Some(p) => p,
+            None => return,
+        };
+        let target = self.squares[to.0 as usize][to.1 as usize];
+        let is_castle = piece.kind == PieceType::King
+            && (from.1.abs_diff(to.1) == 2 || matches!(target, Some(t) if t.color == piece.color));
+        self.update_castling_rights(from, to);
+
+        if is_castle {
+            let king_side = to.1 > from.1;
+            let rook_from = (from.0, if king_side {7} else {0});
+            let king_to = (from.0, if king_side {6} else {2});
+            let rook_to = (from.0, if king_side {5} else {3});
+            let rook = self.squares[rook_from.0 as usize][rook_from.1 as usize].take();
+            self.squares[from.0 as usize][from.1 as usize] = None;
+            piece.position = king_to;
+            piece.times_moved += 1;
+            self.squares[king_to.0 as usize][king_to.1 as usize] = Some(piece);
+            if let Some(mut rook) = rook {
+                rook.position = rook_to;
+                rook.times_moved += 1;
+                self.squares[rook_to.0 as usize][rook_to.1 as usize] = Some(rook);
+            }
+            self.en_passant_target = None;
+            self.halfmove_clock += 1;
+        } else {
+            let mut is_capture = target.is_some();
+            if piece.kind == PieceType::Pawn && from.1 != to.1 && target.is_none() {
+                // en passant, the captured pawn sits beside the moving one
+                self.squares[from.0 as usize][to.1 as usize] = None;
+                is_capture = true;
+            }
+            self.en_passant_target = if piece.kind == PieceType::Pawn && from.0.abs_diff(to.0) == 2 {
+                Some(to)
+            } else {
+                None
+            };
+            self.halfmove_clock = if piece.kind == PieceType::Pawn || is_capture {
+                0
+            } else {
+                self.halfmove_clock + 1
+            };
+            if piece.kind == PieceType::Pawn && (to.0 == 0 || to.0 == 7) {
+                piece.kind = promotion.unwrap_or(PieceType::Queen);
+            }
+            piece.position = to;
+            piece.times_moved += 1;
+            self.squares[from.0 as usize][from.1 as usize] = None;
+            self.squares[to.0 as usize][to.1 as usize] = Some(piece);
+        }
+        if piece.color == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.change_turn();
+    }
+
+    /// Clears the castling rights lost by moving from `from` to `to`:
+    /// any king move, or a rook leaving (or being captured on) its corner.
+    pub fn update_castling_rights(&mut self, from: (u8, u8), to: (u8, u8)) {
+        match self.squares[from.0 as usize][from.1 as usize] {
+            Some(piece) if piece.kind == PieceType::King => match piece.color {
+                PieceColor::White => {
+                    self.white_big_castle = false;
+                    self.white_small_castle = false;
+                }
+                PieceColor::Black => {
+                    self.black_big_castle = false;
+                    self.black_small_castle = false;
+                }
+            },
+            _ => {}
+        }
+        for square in [from, to] {
+            match square {
+                (7, 0) => self.white_big_castle = false,
+                (7, 7) => self.white_small_castle = false,
+                (0, 0) => self.black_big_castle = false,
+                (0, 7) => self.black_small_castle = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// All legal moves for the side to move, in UCI notation.
+    /// Castling is reported as the king's two-square move (e.g. "e1g1") and
+    /// every promotion piece gets its own entry.
+    pub fn legal_moves(&mut self) -> Vec<String> {
+        let mut moves = Vec::new();
+        let squares = self.squares;
+        for piece in squares.iter().flatten().flatten() {
+            if piece.color != self.turn {
+                continue;
+            }
+            let (quiet, captures) = self.get_legal_moves(piece);
+            for to in quiet.into_iter().chain(captures) {
+                let from = piece.position;
+                match self.squares[to.0 as usize][to.1 as usize] {
+                    Some(target) if piece.kind == PieceType::King && target.color == piece.color => {
+                        let file = if to.1 > from.1 {6} else {2};
+                        moves.push(self.encode_uci_move(from, (from.0, file), None));
+                    }
+                    _ if piece.kind == PieceType::Pawn && (to.0 == 0 || to.0 == 7) => {
+                        for kind in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                            moves.push(self.encode_uci_move(from, to, Some(kind)));
+                        }
+                    }
+                    _ => moves.push(self.encode_uci_move(from, to, None)),
+                }
+            }
+        }
+        moves
+    }
+}
+
+type Squares = [[Option<ChessPiece>; 8]; 8];
+
+/// Whether the king of `color` is attacked on `squares`.
+fn in_check(squares: &Squares, color: PieceColor) -> bool {
+    // the square counts, a piece being tried out elsewhere still has its old position
+    let king = (0..64u8).map(|i| (i / 8, i % 8)).find(|&(r, c)| {
+        matches!(squares[r as usize][c as usize], Some(p) if p.kind == PieceType::King && p.color == color)
+    });
+    let enemy = match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
+    king.is_some_and(|king| attacked(squares, king, enemy))
+}
+
+/// Whether a piece of `by` attacks `square`, looking outwards from the square for knights,
+/// kings, pawns and the first piece along each line.
+fn attacked(squares: &Squares, square: (u8, u8), by: PieceColor) -> bool {
+    const KNIGHT: [(i8, i8); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+    const LINES: [(i8, i8); 8] = [(0, 1), (0, -1), (1, 0), (-1, 0), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+    let (r, c) = (square.0 as i8, square.1 as i8);
+    let attacker = |r: i8, c: i8, kinds: &[PieceType]| {
+        (0..8).contains(&r) && (0..8).contains(&c)
+            && matches!(squares[r as usize][c as usize], Some(p) if p.color == by && kinds.contains(&p.kind))
+    };
+    if KNIGHT.iter().any(|(dr, dc)| attacker(r + dr, c + dc, &[PieceType::Knight])) {
+        return true;
+    }
+    if LINES.iter().any(|(dr, dc)| attacker(r + dr, c + dc, &[PieceType::King])) {
+        return true;
+    }
+    // pawns capture towards the opponent: a white pawn stands one rank lower on the board
+    let pawn_rank = if by == PieceColor::White { r + 1 } else { r - 1 };
+    if attacker(pawn_rank, c - 1, &[PieceType::Pawn]) || attacker(pawn_rank, c + 1, &[PieceType::Pawn]) {
+        return true;
+    }
+    LINES.iter().any(|&(dr, dc)| {
+        let sliders: &[PieceType] = if dr == 0 || dc == 0 { &[PieceType::Rook, PieceType::Queen] } else { &[PieceType::Bishop, PieceType::Queen] };
+        let (mut rr, mut cc) = (r + dr, c + dc);
+        while (0..8).contains(&rr) && (0..8).contains(&cc) {
+            if squares[rr as usize][cc as usize].is_some() {
+                return attacker(rr, cc, sliders);
+            }
+            rr += dr;
+            cc += dc;
+        }
+        false
+    })
+}
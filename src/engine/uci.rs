@@ -1,83 +1,90 @@
-use std::collections::HashMap;
+use crate::{engine::{move_gen::MoveError, Board, PieceColor, PieceType}, etc::DEFAULT_FEN};
 
-use crate::engine::{Board, PieceColor, PieceType};
+/// A move as (from, to, promotion) board coordinates.
+pub type UciMove = ((u8, u8), (u8, u8), Option<PieceType>);
 
-impl Board{
-
-    pub fn decode_uci_move(&mut self, uci_move: String) -> Option<((u8, u8), (u8, u8))> {
-        let uci_move = uci_move.as_str();
+/// Splits a UCI move such as "e7e8q" into board coordinates and the promotion piece.
+pub fn parse_uci_move(uci: &str) -> Option<UciMove> {
+    let bytes = uci.as_bytes();
+    if bytes.len() < 4 || bytes.len() > 5 {
+        return None;
+    }
+    let square = |file: u8, rank: u8| -> Option<(u8, u8)> {
+        if (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank) {
+            Some((8 - (rank - b'0'), file - b'a'))
+        } else {
+            None
+        }
+    };
+    let from = square(bytes[0], bytes[1])?;
+    let to = square(bytes[2], bytes[3])?;
+    let promotion = match bytes.get(4) {
+        None => None,
+        Some(b'q') | Some(b'Q') => Some(PieceType::Queen),
+        Some(b'r') | Some(b'R') => Some(PieceType::Rook),
+        Some(b'b') | Some(b'B') => Some(PieceType::Bishop),
+        Some(b'n') | Some(b'N') => Some(PieceType::Knight),
+        Some(_) => return None,
+    };
+    Some((from, to, promotion))
+}
 
-        // Mapping for algebraic notation to board coordinates
-        let file_map: HashMap<char, u8> = [
-            ('a', 0), ('b', 1), ('c', 2), ('d', 3),
-            ('e', 4), ('f', 5), ('g', 6), ('h', 7),
-        ].iter().cloned().collect();
+/// A position the way engines want it: where the game started and the moves played since.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UciPosition {
+    /// `None` for the standard starting position.
+    pub start_fen: Option<String>,
+    pub moves: Vec<String>,
+}
 
-        // Parse standard coordinates from UCI move
-        let chars: Vec<char> = uci_move.chars().collect();
-        let from_file = file_map[&chars[0]];
-        let from_rank = 8 - (chars[1].to_digit(10).unwrap() as u8);
-        let to_file   = file_map[&chars[2]];
-        let to_rank   = 8 - (chars[3].to_digit(10).unwrap() as u8);
-        let from = (from_rank, from_file);
-        let to = (to_rank, to_file);
-        
-        // 1) Handle promotions: e.g. "e7e8q"
-        if uci_move.len() == 5 {
-            // Execute the move first
-            let result = self.move_piece(from, to);
-            if result.is_err() {
-                return None;
+impl UciPosition {
+    /// Rebuilds the position on a board, without recording the moves.
+    /// Moves that do not parse are skipped.
+    pub fn board(&self) -> Board {
+        let mut board = Board::from(&self.start_fen.clone().unwrap_or_else(|| DEFAULT_FEN.to_string()));
+        for uci in &self.moves {
+            if let Some((from, to, promotion)) = parse_uci_move(uci) {
+                board.apply_move(from, to, promotion);
             }
-            
-            // Then handle the promotion
-            let promotion_char = chars[4];
-            let promotion_piece = match promotion_char {
-                'q' | 'Q' => PieceType::Queen,
-                'r' | 'R' => PieceType::Rook,
-                'b' | 'B' => PieceType::Bishop,
-                'n' | 'N' => PieceType::Knight,
-                _ => panic!("Invalid promotion piece: {}", promotion_char),
-            };
-            
-            // Apply the promotion
-            self.promote_pawn(to, promotion_piece);
-            
-            // Mark that promotion is handled
-            self.state.promtion_pending = None;
-            
-            return Some((from, to));
         }
+        board
+    }
+}
 
-        // 2) Handle castling (no changes needed to your castling code)
-        match uci_move {
-            "e1g1" => {
-                let king_pos = (7, 4);
-                let rook_pos = (7, 7);
-                self.execute_castle(king_pos, rook_pos);
-                None
-            }
-            "e1c1" => {
-                let king_pos = (7, 4);
-                let rook_pos = (7, 0);
-                self.execute_castle(king_pos, rook_pos);
-                None
-            }
-            "e8g8" => {
-                let king_pos = (0, 4);
-                let rook_pos = (0, 7);
-                self.execute_castle(king_pos, rook_pos);
-                None
-            }
-            "e8c8" => {
-                let king_pos = (0, 4);
-                let rook_pos = (0, 0);
-                self.execute_castle(king_pos, rook_pos);
-                None
-            }
-            // 3) Normal move (no changes needed)
-            _ => Some((from, to))
+impl Board{
+
+    /// The game so far as a start position plus UCI moves.
+    pub fn uci_position(&self) -> UciPosition {
+        let start = &self.meta_data.starting_position;
+        UciPosition {
+            start_fen: if start == DEFAULT_FEN { None } else { Some(start.clone()) },
+            moves: self.meta_data.moves.line().iter().map(|mv| mv.uci.clone()).collect(),
+        }
+    }
+
+    /// Plays a move given in UCI notation after checking it against the legal moves,
+    /// recording it in the move list like a move made on the board.
+    pub fn play_uci_move(&mut self, uci: &str) -> Result<(), MoveError> {
+        let (from, to, promotion) = parse_uci_move(uci).ok_or(MoveError::IllegalMove)?;
+        let normalized = self.encode_uci_move(from, to, promotion);
+        if !self.legal_moves().contains(&normalized) {
+            return Err(MoveError::IllegalMove);
+        }
+        let piece = self.squares[from.0 as usize][from.1 as usize].ok_or(MoveError::IllegalMove)?;
+        if piece.kind == PieceType::King && from.1.abs_diff(to.1) == 2 {
+            let rook_pos = (from.0, if to.1 > from.1 {7} else {0});
+            return self.execute_castle(from, rook_pos).map_err(|_| MoveError::IllegalMove);
+        }
+        let is_capture = self.squares[to.0 as usize][to.1 as usize].is_some()
+            || (piece.kind == PieceType::Pawn && from.1 != to.1);
+        self.move_piece(from, to)?;
+        if let Some((pos, old_pos)) = self.state.promtion_pending {
+            let kind = promotion.unwrap_or(PieceType::Queen);
+            self.promote_pawn(pos, kind);
+            self.state.promtion_pending = None;
+            self.record_move(old_pos, pos, Some(kind), is_capture);
         }
+        Ok(())
     }
 
     /// Encodes a move from board coordinates to UCI format (e.g., "e2e4" or "a7a8q")
@@ -1,7 +1,21 @@
+use std::{fs, path::{Path, PathBuf}};
+
 use crate::engine::PieceColor;
 
 
 // make sure this file is included by your lib.rs/bin.rs via `mod etc;`
 pub const DEFAULT_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 pub const DEFAULT_STARTING: PieceColor = PieceColor::White;
-pub const STOCKFISH_ELO: u32 = 2500;
+/// UCI engine started for PvE games and the evaluation bar.
+pub const ENGINE_PATH: &str = "/usr/bin/stockfish";
+
+/// Folder for everything the app saves (engine settings, profiles, games).
+/// Uses `$HOME/.koch`, or `data/` next to the sources when there is no home directory.
+pub fn data_dir() -> PathBuf {
+    let dir = match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".koch"),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("data"),
+    };
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
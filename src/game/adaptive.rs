@@ -0,0 +1,175 @@
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{etc::data_dir, game::uci_client::{InfoScore, SearchResult}};
+
+/// Rating a new player starts from.
+pub const START_RATING: f64 = 1500.0;
+/// Range of `UCI_Elo` the adaptive opponent can be set to.
+pub const MIN_ENGINE_ELO: u32 = 1320;
+pub const MAX_ENGINE_ELO: u32 = 3190;
+/// Candidate moves the adaptive opponent looks at (`MultiPV`).
+pub const CANDIDATE_LINES: u32 = 4;
+/// Games that count as "recent" when deciding how much to hold back.
+const RECENT_GAMES: usize = 5;
+
+/// One finished game against the adaptive opponent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub date: String,
+    pub engine_elo: u32,
+    /// 1 for a win, 0.5 for a draw, 0 for a loss, from the player's side.
+    pub score: f32,
+    /// Mean move accuracy in percent, when enough moves were measured.
+    pub accuracy: Option<f32>,
+}
+
+/// The player's estimated level and results, saved as `profile.json` in the data folder.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub rating: f64,
+    pub games: Vec<GameRecord>,
+}
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        Self { rating: START_RATING, games: Vec::new() }
+    }
+}
+
+/// Win chance in percent for the side with `cp` centipawns, the curve Lichess uses for accuracy.
+pub fn win_percent(cp: i32) -> f32 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * cp as f32).exp()) - 1.0)
+}
+
+/// Accuracy in percent of a move that took the mover's win chance from `before` to `after`.
+pub fn move_accuracy(before: f32, after: f32) -> f32 {
+    let loss = (before - after).max(0.0);
+    (103.1668 * (-0.04354 * loss).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+/// Centipawns for blending and accuracy, with mates counted as a large advantage.
+pub fn score_cp(score: InfoScore) -> i32 {
+    match score {
+        InfoScore::Cp(cp) => cp,
+        InfoScore::Mate(moves) if moves > 0 => 1000,
+        InfoScore::Mate(_) => -1000,
+    }
+}
+
+impl PlayerProfile {
+    fn file() -> PathBuf {
+        data_dir().join("profile.json")
+    }
+
+    pub fn load() -> Self {
+        fs::read_to_string(Self::file())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(Self::file(), json) {
+                    eprintln!("could not save profile: {}", e);
+                }
+            }
+            Err(e) => eprintln!("could not save profile: {}", e),
+        }
+    }
+
+    /// Average score over the last few games, 0.5 without history.
+    pub fn recent_score(&self) -> f32 {
+        let recent: Vec<f32> = self.games.iter().rev().take(RECENT_GAMES).map(|g| g.score).collect();
+        if recent.is_empty() { 0.5 } else { recent.iter().sum::<f32>() / recent.len() as f32 }
+    }
+
+    /// Engine rating for the next game: the player's estimate, so games come out about even.
+    pub fn suggested_engine_elo(&self) -> u32 {
+        (self.rating.round() as u32).clamp(MIN_ENGINE_ELO, MAX_ENGINE_ELO)
+    }
+
+    /// How willing the engine is to play a weaker candidate in a won position, 0 to 1.
+    /// A player who has been losing gets more help.
+    pub fn blend_factor(&self) -> f32 {
+        (1.0 - self.recent_score()).clamp(0.0, 1.0)
+    }
+
+    /// Updates the rating with the Elo formula against `engine_elo`, then pulls it a little
+    /// towards the level suggested by the move accuracy.
+    pub fn record_game(&mut self, engine_elo: u32, score: f32, accuracy: Option<f32>) {
+        if engine_elo > 0 {
+            let expected = 1.0 / (1.0 + 10f64.powf((engine_elo as f64 - self.rating) / 400.0));
+            let k = if self.games.len() < 10 { 40.0 } else { 20.0 };
+            self.rating += k * (score as f64 - expected);
+        }
+        if let Some(accuracy) = accuracy {
+            // roughly 1500 at 60% accuracy and 2250 at 90%
+            let accuracy_rating = 25.0 * accuracy as f64;
+            self.rating = 0.85 * self.rating + 0.15 * accuracy_rating;
+        }
+        self.rating = self.rating.clamp(100.0, 3500.0);
+        self.games.push(GameRecord {
+            date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            engine_elo,
+            score,
+            accuracy,
+        });
+        self.save();
+    }
+}
+
+/// Move picking and accuracy tracking of the adaptive opponent during one game,
+/// kept by the engine worker.
+pub struct MoveBlender {
+    /// From the player's profile, see [`PlayerProfile::blend_factor`].
+    pub blend_factor: f32,
+    /// Engine's score after its own last move, from its side.
+    expected: Option<i32>,
+}
+
+impl MoveBlender {
+    pub fn new(blend_factor: f32) -> Self {
+        Self { blend_factor, expected: None }
+    }
+
+    pub fn reset(&mut self) {
+        self.expected = None;
+    }
+
+    /// Accuracy of the player's last move, judged by how much the engine's score grew
+    /// since the engine's previous move. `None` before the engine has moved.
+    pub fn player_accuracy(&self, result: &SearchResult) -> Option<f32> {
+        let expected = self.expected?;
+        let now = score_cp(result.main_line()?.score?);
+        Some(move_accuracy(win_percent(-expected), win_percent(-now)))
+    }
+
+    /// Picks the move to play from a multi-PV search. When the engine is clearly winning it
+    /// may choose a weaker line that still keeps it from losing, more often the further ahead
+    /// it is and the worse the player has been doing.
+    pub fn pick(&mut self, result: &SearchResult) -> String {
+        let scored: Vec<(String, i32)> = result
+            .lines
+            .iter()
+            .filter_map(|line| Some((line.pv.first()?.clone(), score_cp(line.score?))))
+            .collect();
+        let Some(&(_, best)) = scored.first() else {
+            self.expected = None;
+            return result.best_move.clone();
+        };
+        let mut choice = (result.best_move.clone(), best);
+        let chance = ((best - 100) as f32 / 300.0).clamp(0.0, 1.0) * self.blend_factor;
+        if chance > 0.0 && rand::random::<f32>() < chance {
+            // the weakest line that still keeps a small edge
+            if let Some((mv, cp)) = scored.iter().filter(|(_, cp)| *cp >= 50 && *cp >= best - 300).min_by_key(|(_, cp)| *cp) {
+                choice = (mv.clone(), *cp);
+            }
+        }
+        self.expected = Some(choice.1);
+        choice.0
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use crate::{engine::{score::{Score, Wdl}, uci::UciPosition}, game::{engine_pool::{EngineLease, EnginePool, Priority}, uci_client::{GoParams, SearchControl, SearchInfo}}};
+
+/// Lines shown unless the user picks another number.
+pub const DEFAULT_LINES: u32 = 3;
+/// Most lines the analysis can be asked for.
+pub const MAX_LINES: u32 = 8;
+/// Depth an analysis searches to unless it runs until stopped.
+pub const ANALYSIS_DEPTH: u32 = 22;
+
+/// One of the engine's best lines for the analyzed position.
+#[derive(Clone, Debug)]
+pub struct AnalysisLine {
+    /// Rank of the line, 1 for the best.
+    pub multipv: u32,
+    pub depth: u32,
+    pub score: Score,
+    pub wdl: Wdl,
+    /// Principal variation in UCI, as the engine sent it.
+    pub uci: Vec<String>,
+    /// The same moves in SAN, up to the first one that is not legal.
+    pub san: Vec<String>,
+}
+
+/// Latest search statistics, kept across info lines since engines leave out what did not change.
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    /// Transposition table use in permille.
+    pub hashfull: Option<u32>,
+    pub tbhits: Option<u64>,
+    pub time_ms: Option<u64>,
+}
+
+impl SearchStats {
+    /// Takes over every statistic `info` reports. Returns whether anything was reported.
+    fn update(&mut self, info: &SearchInfo) -> bool {
+        let before = (self.depth, self.seldepth, self.nodes, self.nps, self.hashfull, self.tbhits, self.time_ms);
+        self.depth = info.depth.or(self.depth);
+        self.seldepth = info.seldepth.or(self.seldepth);
+        self.nodes = info.nodes.or(self.nodes);
+        self.nps = info.nps.or(self.nps);
+        self.hashfull = info.hashfull.or(self.hashfull);
+        self.tbhits = info.tbhits.or(self.tbhits);
+        self.time_ms = info.time_ms.or(self.time_ms);
+        before != (self.depth, self.seldepth, self.nodes, self.nps, self.hashfull, self.tbhits, self.time_ms)
+    }
+}
+
+pub enum AnalysisEvent {
+    Line(AnalysisLine),
+    Stats(SearchStats),
+    /// The search reached its depth or was stopped.
+    Finished,
+}
+
+pub struct AnalysisRequest {
+    pub position: UciPosition,
+    pub lines: u32,
+    /// Search until stopped or replaced instead of to [`ANALYSIS_DEPTH`].
+    pub infinite: bool,
+}
+
+enum AnalysisCmd {
+    Analyze(AnalysisRequest),
+    Stop,
+}
+
+/// Analysis engine running on its own thread. A new request replaces the running one, so
+/// the analysis can simply be sent every position the user looks at.
+pub struct Analyzer {
+    commands: Sender<(u64, AnalysisCmd)>,
+    events: Receiver<(u64, AnalysisEvent)>,
+    /// Id of the newest request; events of replaced ones are dropped.
+    current: u64,
+}
+
+impl Analyzer {
+    pub fn start(pool: EnginePool) -> Analyzer {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let _ = std::thread::Builder::new().name("analysis".to_string()).spawn(move || {
+            analysis_thread(pool, command_rx, event_tx);
+        });
+        Analyzer { commands, events, current: 0 }
+    }
+
+    /// Stops the running analysis, if any, and analyzes `request` instead.
+    pub fn analyze(&mut self, request: AnalysisRequest) {
+        self.current += 1;
+        let _ = self.commands.send((self.current, AnalysisCmd::Analyze(request)));
+    }
+
+    /// Stops the analysis and gives the engine back to the pool.
+    pub fn stop(&mut self) {
+        self.current += 1;
+        let _ = self.commands.send((self.current, AnalysisCmd::Stop));
+    }
+
+    /// Events of the current request that arrived since the last call.
+    pub fn poll(&self) -> Vec<AnalysisEvent> {
+        self.events.try_iter().filter(|(id, _)| *id == self.current).map(|(_, event)| event).collect()
+    }
+}
+
+fn analysis_thread(pool: EnginePool, commands: Receiver<(u64, AnalysisCmd)>, events: Sender<(u64, AnalysisEvent)>) {
+    // kept while requests follow each other, so the engine keeps its hash
+    let mut lease: Option<EngineLease> = None;
+    let mut multipv = 1;
+    let mut next = None;
+    loop {
+        let (id, request) = match next.take() {
+            Some(request) => request,
+            None => match commands.recv() {
+                Ok((id, AnalysisCmd::Analyze(request))) => (id, request),
+                Ok((_, AnalysisCmd::Stop)) => continue,
+                // the app is gone
+                Err(_) => break,
+            },
+        };
+        if lease.is_none() {
+            // the pool already told the user when no engine could be started
+            let Ok(engine) = pool.lease(Priority::Analysis) else {
+                let _ = events.send((id, AnalysisEvent::Finished));
+                continue;
+            };
+            lease = Some(engine);
+            multipv = 1;
+        }
+        let Some(engine) = lease.as_mut() else { continue };
+        if request.lines != multipv {
+            let _ = engine.set_option("MultiPV", &request.lines.to_string());
+            multipv = request.lines;
+        }
+        let params = if request.infinite { GoParams { infinite: true, ..Default::default() } } else { GoParams::depth(ANALYSIS_DEPTH) };
+        let board = request.position.board();
+        let mut stats = SearchStats::default();
+        let _ = engine.set_position(&request.position).and_then(|_| engine.search(&params, &mut |info| {
+            match commands.try_recv() {
+                Ok((id, AnalysisCmd::Analyze(request))) => {
+                    next = Some((id, request));
+                    return SearchControl::Stop;
+                }
+                Ok((_, AnalysisCmd::Stop)) | Err(TryRecvError::Disconnected) => return SearchControl::Stop,
+                Err(TryRecvError::Empty) => {}
+            }
+            if stats.update(info) {
+                let _ = events.send((id, AnalysisEvent::Stats(stats.clone())));
+            }
+            let score = info.score.and_then(|score| Score::from_side_to_move(score, board.turn));
+            if let (Some(score), false) = (score, info.pv.is_empty()) {
+                let wdl = info.wdl.map_or_else(|| Wdl::from_score(score), |wdl| Wdl::from_side_to_move(wdl, board.turn));
+                let line = AnalysisLine {
+                    multipv: info.multipv.unwrap_or(1),
+                    depth: info.depth.unwrap_or(0),
+                    score,
+                    wdl,
+                    uci: info.pv.clone(),
+                    san: board.san_line(&info.pv),
+                };
+                let _ = events.send((id, AnalysisEvent::Line(line)));
+            }
+            SearchControl::Continue
+        }));
+        let _ = events.send((id, AnalysisEvent::Finished));
+        if next.is_none() {
+            // hand the engine back until the next request
+            drop(lease.take());
+        }
+    }
+}
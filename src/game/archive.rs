@@ -0,0 +1,108 @@
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::{board::BoardMetaData, openings::opening_name, pgn::{parse_pgn, PgnGame}, Board, PieceColor}, etc::{data_dir, DEFAULT_FEN}};
+
+/// A game kept in the archive: its PGN, with what the History screen lists taken out of it
+/// when it was saved.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedGame {
+    /// "2024-05-01 18:30:00"
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    /// The other side for a game played here, both players otherwise.
+    pub opponent: String,
+    /// "1-0", "0-1", "1/2-1/2" or "*".
+    pub result: String,
+    pub opening: String,
+    /// Moves of the main line, counting one for White and Black.
+    pub moves: usize,
+    pub pgn: String,
+}
+
+impl ArchivedGame {
+    /// The game in `meta`, played by `player` when it was played here.
+    pub fn from_game(meta: &BoardMetaData, player: Option<PieceColor>) -> ArchivedGame {
+        let opponent = match player {
+            Some(PieceColor::White) => meta.black_player_name.clone(),
+            Some(PieceColor::Black) => meta.white_player_name.clone(),
+            None => format!("{} - {}", meta.white_player_name, meta.black_player_name),
+        };
+        let mainline = meta.moves.mainline();
+        let opening = if meta.starting_position != DEFAULT_FEN {
+            "Set-up position"
+        } else {
+            let sans: Vec<&str> = mainline.iter().map(|mv| mv.san.as_str()).collect();
+            opening_name(&sans).unwrap_or("-")
+        };
+        ArchivedGame {
+            date: meta.date.clone(),
+            white: meta.white_player_name.clone(),
+            black: meta.black_player_name.clone(),
+            opponent,
+            result: meta.result.pgn().to_string(),
+            opening: opening.to_string(),
+            moves: mainline.len().div_ceil(2),
+            pgn: PgnGame::from_game(meta).to_pgn(),
+        }
+    }
+
+    /// The game read back from its PGN, showing the end of the main line.
+    pub fn to_board(&self) -> Option<Board> {
+        parse_pgn(&self.pgn).0.first().map(PgnGame::to_board)
+    }
+}
+
+/// Every game finished or abandoned here and every game imported, oldest first, saved as
+/// `archive.json` in the data folder.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GameArchive {
+    pub games: Vec<ArchivedGame>,
+}
+
+impl GameArchive {
+    fn file() -> PathBuf {
+        data_dir().join("archive.json")
+    }
+
+    /// The saved archive. One that cannot be read is kept aside as `archive.json.bak`
+    /// instead of being overwritten by the next save.
+    pub fn load() -> Self {
+        let Ok(json) = fs::read_to_string(Self::file()) else { return Self::default() };
+        match serde_json::from_str(&json) {
+            Ok(archive) => archive,
+            Err(e) => {
+                eprintln!("could not read the game archive: {}", e);
+                let _ = fs::rename(Self::file(), data_dir().join("archive.json.bak"));
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(Self::file(), json) {
+                    eprintln!("could not save the game archive: {}", e);
+                }
+            }
+            Err(e) => eprintln!("could not save the game archive: {}", e),
+        }
+    }
+
+    /// Adds games and saves the archive.
+    pub fn add(&mut self, games: impl IntoIterator<Item = ArchivedGame>) {
+        self.games.extend(games);
+        self.save();
+    }
+
+    /// Removes game `index` and saves the archive.
+    pub fn remove(&mut self, index: usize) {
+        if index < self.games.len() {
+            self.games.remove(index);
+            self.save();
+        }
+    }
+}
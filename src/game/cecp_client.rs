@@ -0,0 +1,588 @@
+use std::{collections::HashMap, io::{BufRead, BufReader, Write}, process::{Child, ChildStdin, Command, Stdio}, sync::mpsc::{self, Receiver, RecvTimeoutError}, thread, time::{Duration, Instant}};
+
+use crate::{engine::{uci::{parse_uci_move, UciPosition}, Board, PieceColor}, game::{adaptive::{MAX_ENGINE_ELO, MIN_ENGINE_ELO}, chess_engine::ChessEngine, engine_registry::{OptionKind, UciOption}, strength::{depth_for_elo, depth_for_skill}, uci_client::{record_line, EngineError, GoParams, InfoScore, SearchControl, SearchInfo, SearchResult, HANDSHAKE_TIMEOUT, SEARCH_POLL}}};
+
+/// How long an engine that does not send `feature done=0` gets to list its features.
+const FEATURE_WAIT: Duration = Duration::from_secs(2);
+
+/// CECP mate scores are sent as 100000 + moves to mate.
+const MATE_SCORE: i32 = 100000;
+
+/// `sd` for a search without a depth limit; the engine keeps the last `sd` it was sent.
+const UNLIMITED_DEPTH: u32 = 99;
+
+/// Seconds per move (`st`) for searches limited by depth or nodes only, which CECP has no
+/// command for; well inside the time the supervisor gives such a search.
+const UNTIMED_MOVE_SECONDS: u64 = 60;
+
+/// UCI strength options the client takes for engines that do not declare them, playing
+/// weaker through a depth limit.
+const LIMIT_STRENGTH: &str = "UCI_LimitStrength";
+const ELO: &str = "UCI_Elo";
+const SKILL_LEVEL: &str = "Skill Level";
+
+/// An XBoard/CECP engine running as a child process, driven through the same calls as a
+/// [`crate::game::uci_client::UciClient`]. Moves are exchanged in coordinate notation;
+/// SAN in thinking output is converted back to UCI moves.
+pub struct CecpClient {
+    pub name: String,
+    /// Every feature the engine announced, e.g. "setboard" -> "1".
+    pub features: HashMap<String, String>,
+    /// Values of the `feature option="..."` declarations.
+    pub option_lines: Vec<String>,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    /// Game the engine currently holds, including the moves it played itself.
+    position: Option<UciPosition>,
+    analyzing: bool,
+    ping: u32,
+    /// Strength set through the UCI options, see [`LIMIT_STRENGTH`].
+    limit_strength: bool,
+    elo: u32,
+    skill: u32,
+}
+
+/// Splits the rest of a `feature` line into key/value pairs, unquoting values.
+pub fn parse_features(text: &str) -> Vec<(String, String)> {
+    let mut features = Vec::new();
+    let mut rest = text.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        features.push((key.trim().to_string(), value.to_string()));
+        rest = after.trim_start();
+    }
+    features
+}
+
+/// Parses a thinking line ("ply score time nodes pv...") into the UCI info structure.
+/// `board` is the searched position, used to turn the principal variation into UCI moves.
+pub fn parse_thinking(line: &str, board: &Board) -> Option<SearchInfo> {
+    let mut tokens = line.split_whitespace();
+    let depth: u32 = tokens.next()?.trim_end_matches(|c: char| !c.is_ascii_digit()).parse().ok()?;
+    let score: i32 = tokens.next()?.parse().ok()?;
+    let centiseconds: u64 = tokens.next()?.parse().ok()?;
+    let nodes: u64 = tokens.next()?.parse().ok()?;
+    let score = if score.abs() >= MATE_SCORE {
+        InfoScore::Mate(score.signum() * (score.abs() - MATE_SCORE))
+    } else {
+        InfoScore::Cp(score)
+    };
+    let time_ms = centiseconds * 10;
+    let mut info = SearchInfo {
+        depth: Some(depth),
+        score: Some(score),
+        nodes: Some(nodes),
+        time_ms: Some(time_ms),
+        nps: (nodes * 1000).checked_div(time_ms),
+        ..SearchInfo::default()
+    };
+    let mut board = board.clone();
+    // some engines put selective depth, speed or tablebase hits before the pv
+    for token in tokens.skip_while(|t| t.parse::<u64>().is_ok()) {
+        // drop a move number such as "12." or "12..." in front of the move
+        let token = token.rsplit('.').next().unwrap_or(token);
+        if token.is_empty() {
+            continue;
+        }
+        let uci = match parse_uci_move(token) {
+            Some(_) => token.to_string(),
+            None => match board.decode_san_move(token) {
+                Some(uci) => uci,
+                None => break,
+            },
+        };
+        if let Some((from, to, promotion)) = parse_uci_move(&uci) {
+            board.apply_move(from, to, promotion);
+        }
+        info.pv.push(uci);
+    }
+    Some(info)
+}
+
+impl CecpClient {
+    /// Starts the engine at `path` and negotiates protocol version 2 features.
+    pub fn spawn(path: &str, args: &[String]) -> Result<Self, EngineError> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(EngineError::Spawn)?;
+        let stdin = child.stdin.take().ok_or(EngineError::Exited)?;
+        let stdout = child.stdout.take().ok_or(EngineError::Exited)?;
+        let (tx, rx) = mpsc::channel();
+        let _ = thread::Builder::new().name("cecp_reader".to_string()).spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if tx.send(line.trim().to_string()).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut client = CecpClient {
+            name: path.to_string(),
+            features: HashMap::new(),
+            option_lines: Vec::new(),
+            child,
+            stdin,
+            lines: rx,
+            position: None,
+            analyzing: false,
+            ping: 0,
+            limit_strength: false,
+            elo: MAX_ENGINE_ELO,
+            skill: 20,
+        };
+        client.handshake()?;
+        Ok(client)
+    }
+
+    fn handshake(&mut self) -> Result<(), EngineError> {
+        self.send("xboard")?;
+        self.send("protover 2")?;
+        let mut deadline = Instant::now() + FEATURE_WAIT;
+        'features: loop {
+            let line = match self.recv_until(deadline, "protover") {
+                Ok(line) => line,
+                // protocol version 1 engines never answer
+                Err(EngineError::Timeout(_)) => break,
+                Err(e) => return Err(e),
+            };
+            let Some(rest) = line.strip_prefix("feature ") else { continue };
+            for (key, value) in parse_features(rest) {
+                match key.as_str() {
+                    "done" if value == "0" => deadline = Instant::now() + HANDSHAKE_TIMEOUT,
+                    "done" => break 'features,
+                    // moves go out in coordinate notation
+                    "san" if value == "1" => {
+                        self.send("rejected san")?;
+                        continue;
+                    }
+                    "myname" => self.name = value.clone(),
+                    "option" => self.option_lines.push(value.clone()),
+                    _ => {}
+                }
+                self.send(&format!("accepted {}", key))?;
+                self.features.insert(key, value);
+            }
+        }
+        // thinking output on, pondering off
+        self.send("post")?;
+        self.send("easy")?;
+        self.send("new")?;
+        self.send("force")?;
+        self.sync()
+    }
+
+    fn feature(&self, name: &str) -> bool {
+        self.features.get(name).is_some_and(|v| v == "1")
+    }
+
+    fn declared_options(&self) -> Vec<UciOption> {
+        self.option_lines.iter().filter_map(|line| UciOption::parse_cecp(line)).collect()
+    }
+
+    /// Depth limit that makes the engine play at the strength set through the UCI options.
+    fn strength_depth(&self) -> Option<u32> {
+        if self.limit_strength {
+            Some(depth_for_elo(self.elo))
+        } else if self.skill < 20 {
+            Some(depth_for_skill(self.skill))
+        } else {
+            None
+        }
+    }
+
+    /// Engines answer commands they do not know, such as an option they lack, with "Error";
+    /// that only costs the setting, so it is logged and the engine carries on.
+    fn warn(&self, line: &str) {
+        eprintln!("{}: {}", self.name, line);
+    }
+
+    fn recv_until(&self, deadline: Instant, waiting_for: &'static str) -> Result<String, EngineError> {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(left) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(EngineError::Timeout(waiting_for)),
+            Err(RecvTimeoutError::Disconnected) => Err(EngineError::Exited),
+        }
+    }
+
+    /// Stops a running search once. Analysis has no move to wait for, so it ends right away
+    /// with the first move of its line and `true` is returned; a normal search answers the
+    /// "?" with a move.
+    fn stop_search(&mut self, result: &mut SearchResult, stopping: &mut bool) -> Result<bool, EngineError> {
+        if self.analyzing {
+            self.stop()?;
+            result.best_move = result.main_line().and_then(|l| l.pv.first().cloned()).unwrap_or_else(|| "(none)".to_string());
+            return Ok(true);
+        }
+        if !*stopping {
+            *stopping = true;
+            self.stop()?;
+        }
+        Ok(false)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{}", command).map_err(EngineError::Io)?;
+        self.stdin.flush().map_err(EngineError::Io)
+    }
+
+    /// Waits until the engine has handled everything sent so far, using `ping` when the
+    /// engine supports it. Reports moves the engine refused.
+    fn sync(&mut self) -> Result<(), EngineError> {
+        self.sync_within(HANDSHAKE_TIMEOUT)
+    }
+
+    fn sync_within(&mut self, timeout: Duration) -> Result<(), EngineError> {
+        if !self.feature("ping") {
+            return Ok(());
+        }
+        self.ping += 1;
+        let pong = format!("pong {}", self.ping);
+        self.send(&format!("ping {}", self.ping))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = self.recv_until(deadline, "ping")?;
+            if line == pong {
+                return Ok(());
+            }
+            if line.starts_with("Illegal move") {
+                return Err(EngineError::Rejected(line));
+            }
+            if line.starts_with("Error") {
+                self.warn(&line);
+            }
+        }
+    }
+
+    fn side_to_move(&self) -> PieceColor {
+        match &self.position {
+            Some(position) => position.board().turn,
+            None => PieceColor::White,
+        }
+    }
+
+    /// Sends the time control part of `params` as `level`, `time` and `otim`; false when
+    /// `params` has no clock.
+    fn send_clock(&mut self, params: &GoParams) -> Result<bool, EngineError> {
+        let white = self.side_to_move() == PieceColor::White;
+        let (own, other, increment) = if white {
+            (params.wtime, params.btime, params.winc)
+        } else {
+            (params.btime, params.wtime, params.binc)
+        };
+        let Some(own) = own else { return Ok(false) };
+        let base_seconds = own / 1000;
+        self.send(&format!(
+            "level {} {}:{:02} {}",
+            params.movestogo.unwrap_or(0),
+            base_seconds / 60,
+            base_seconds % 60,
+            increment.unwrap_or(0) / 1000
+        ))?;
+        // both clocks are sent in centiseconds
+        self.send(&format!("time {}", own / 10))?;
+        self.send(&format!("otim {}", other.unwrap_or(own) / 10))?;
+        Ok(true)
+    }
+}
+
+impl ChessEngine for CecpClient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The declared options, plus the UCI strength options the client stands in for.
+    fn options(&self) -> Vec<UciOption> {
+        let mut options = self.declared_options();
+        let emulated = [
+            UciOption { name: LIMIT_STRENGTH.to_string(), kind: OptionKind::Check, default: "false".to_string() },
+            UciOption { name: ELO.to_string(), kind: OptionKind::Spin { min: MIN_ENGINE_ELO as i64, max: MAX_ENGINE_ELO as i64 }, default: MAX_ENGINE_ELO.to_string() },
+            UciOption { name: SKILL_LEVEL.to_string(), kind: OptionKind::Spin { min: 0, max: 20 }, default: "20".to_string() },
+        ];
+        for option in emulated {
+            if !options.iter().any(|declared| declared.name == option.name) {
+                options.push(option);
+            }
+        }
+        options
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), EngineError> {
+        if !self.declared_options().iter().any(|option| option.name == name) {
+            let number = value.and_then(|v| v.parse().ok());
+            match (name, number) {
+                (LIMIT_STRENGTH, _) => {
+                    self.limit_strength = value == Some("true");
+                    return Ok(());
+                }
+                (ELO, Some(elo)) => {
+                    self.elo = elo;
+                    return Ok(());
+                }
+                (SKILL_LEVEL, Some(level)) => {
+                    self.skill = level;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        match value {
+            Some("true") => self.send(&format!("option {}=1", name)),
+            Some("false") => self.send(&format!("option {}=0", name)),
+            Some(value) => self.send(&format!("option {}={}", name, value)),
+            None => self.send(&format!("option {}", name)),
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), EngineError> {
+        self.send("new")?;
+        self.send("force")?;
+        self.position = None;
+        self.sync()
+    }
+
+    fn set_position(&mut self, position: &UciPosition) -> Result<(), EngineError> {
+        // when the game only went on, the engine just needs the new moves
+        let known = match &self.position {
+            Some(current) if current.start_fen == position.start_fen && position.moves.starts_with(&current.moves) => current.moves.len(),
+            _ => {
+                self.send("new")?;
+                self.send("force")?;
+                if let Some(fen) = &position.start_fen {
+                    if !self.feature("setboard") {
+                        return Err(EngineError::Unsupported("setboard"));
+                    }
+                    self.send(&format!("setboard {}", fen))?;
+                }
+                0
+            }
+        };
+        self.send("force")?;
+        let usermove = self.feature("usermove");
+        for uci in &position.moves[known..] {
+            if usermove {
+                self.send(&format!("usermove {}", uci))?;
+            } else {
+                self.send(uci)?;
+            }
+        }
+        self.position = Some(position.clone());
+        self.sync()
+    }
+
+    fn search(&mut self, params: &GoParams, timeout: Option<Duration>, on_info: &mut dyn FnMut(&SearchInfo) -> SearchControl) -> Result<SearchResult, EngineError> {
+        let board = match &self.position {
+            Some(position) => position.board(),
+            None => UciPosition { start_fen: None, moves: Vec::new() }.board(),
+        };
+        let infinite = params.infinite && self.features.get("analyze").is_none_or(|v| v != "0");
+        if infinite {
+            self.send("analyze")?;
+            self.analyzing = true;
+        } else {
+            // sd and st stay in force until changed, so every search sends both
+            let depth = match (params.depth, self.strength_depth()) {
+                (Some(depth), Some(cap)) => depth.min(cap),
+                (depth, cap) => depth.or(cap).unwrap_or(UNLIMITED_DEPTH),
+            };
+            self.send(&format!("sd {}", depth))?;
+            let timed = self.send_clock(params)?;
+            match params.movetime {
+                Some(movetime) => self.send(&format!("st {}", movetime.div_ceil(1000).max(1)))?,
+                None if !timed => self.send(&format!("st {}", UNTIMED_MOVE_SECONDS))?,
+                None => {}
+            }
+            self.send("go")?;
+        }
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut result = SearchResult::default();
+        let mut stopping = false;
+        loop {
+            let wait = deadline.map_or(SEARCH_POLL, |deadline| deadline.saturating_duration_since(Instant::now()).min(SEARCH_POLL));
+            let line = match self.lines.recv_timeout(wait) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    // analysis has no move of its own, it ends when time is up
+                    if self.analyzing {
+                        self.stop_search(&mut result, &mut stopping)?;
+                        return Ok(result);
+                    }
+                    return Err(EngineError::Timeout("the search"));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // let the caller stop an engine that has gone quiet
+                    if on_info(&SearchInfo::default()) == SearchControl::Stop && self.stop_search(&mut result, &mut stopping)? {
+                        return Ok(result);
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Exited),
+            };
+            if let Some(mv) = line.strip_prefix("move ") {
+                let mv = mv.trim();
+                let uci = match parse_uci_move(mv) {
+                    Some(_) => mv.to_string(),
+                    None => board.decode_san_move(mv).ok_or_else(|| EngineError::Rejected(line.clone()))?,
+                };
+                // the engine played the move on its own board, keep ours in step
+                if let Some(position) = &mut self.position {
+                    position.moves.push(uci.clone());
+                }
+                self.send("force")?;
+                result.best_move = uci;
+                return Ok(result);
+            }
+            if line == "resign" || line.starts_with("1-0") || line.starts_with("0-1") || line.starts_with("1/2-1/2") {
+                self.send("force")?;
+                result.best_move = "(none)".to_string();
+                return Ok(result);
+            }
+            if line.starts_with("Illegal move") {
+                return Err(EngineError::Rejected(line));
+            }
+            if line.starts_with("Error") {
+                self.warn(&line);
+                continue;
+            }
+            if let Some(info) = parse_thinking(&line, &board) {
+                record_line(&mut result.lines, &info);
+                if on_info(&info) == SearchControl::Stop && self.stop_search(&mut result, &mut stopping)? {
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), EngineError> {
+        if self.analyzing {
+            self.analyzing = false;
+            self.send("exit")
+        } else {
+            self.send("?")
+        }
+    }
+
+    /// Without `ping` the engine can only be checked for having exited.
+    fn is_ready(&mut self, timeout: Duration) -> Result<(), EngineError> {
+        if !self.is_alive() {
+            return Err(EngineError::Exited);
+        }
+        self.sync_within(timeout)
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Asks the engine to quit, killing it if it does not exit promptly.
+    fn quit(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for CecpClient {
+    fn drop(&mut self) {
+        self.quit();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    /// An XBoard engine in a shell script: it logs every command it gets, refuses options it
+    /// was not told about and moves e7e5 whenever it is asked to.
+    const FAKE_ENGINE: &str = r#"
+while read -r line; do
+    echo "$line" >> "$1"
+    case "$line" in
+        "protover 2") echo 'feature myname="Fake XBoard" ping=1 setboard=1 done=1' ;;
+        ping*) echo "pong ${line#ping }" ;;
+        option*) echo "Error (unknown command): $line" ;;
+        go)
+            echo "4 25 10 1500 e5 Nf3"
+            echo "move e7e5" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    fn fake_engine(test: &str) -> (CecpClient, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("koch-fake-cecp-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("engine.sh");
+        let log = dir.join("commands.log");
+        fs::write(&script, FAKE_ENGINE).unwrap();
+        let _ = fs::remove_file(&log);
+        let args = [script.display().to_string(), log.display().to_string()];
+        (CecpClient::spawn("sh", &args).unwrap(), log)
+    }
+
+    fn sent_commands(mut client: CecpClient, log: &PathBuf) -> Vec<String> {
+        client.quit();
+        fs::read_to_string(log).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn search(client: &mut CecpClient, params: &GoParams) -> SearchResult {
+        let position = UciPosition { start_fen: None, moves: vec!["e2e4".to_string()] };
+        client.set_position(&position).unwrap();
+        client.search(params, Some(Duration::from_secs(10)), &mut |_| SearchControl::Continue).unwrap()
+    }
+
+    #[test]
+    fn strength_becomes_a_depth_limit() {
+        let (mut client, log) = fake_engine("strength");
+        assert!(client.options().iter().any(|option| option.name == ELO));
+        client.set_option(LIMIT_STRENGTH, Some("true")).unwrap();
+        client.set_option(ELO, Some("1500")).unwrap();
+        let result = search(&mut client, &GoParams { movetime: Some(1000), ..Default::default() });
+        assert_eq!(result.best_move, "e7e5");
+        assert_eq!(result.main_line().unwrap().pv, ["e7e5", "g1f3"]);
+
+        let commands = sent_commands(client, &log);
+        assert!(!commands.iter().any(|c| c.starts_with("option")), "{:?}", commands);
+        let go = commands.iter().position(|c| c == "go").unwrap();
+        assert_eq!(commands[go - 2..go], [format!("sd {}", depth_for_elo(1500)), "st 1".to_string()]);
+    }
+
+    #[test]
+    fn refused_option_does_not_fail_the_search() {
+        let (mut client, log) = fake_engine("refused");
+        client.set_option("MultiPV", Some("3")).unwrap();
+        assert_eq!(search(&mut client, &GoParams::depth(6)).best_move, "e7e5");
+        assert!(sent_commands(client, &log).contains(&"option MultiPV=3".to_string()));
+    }
+
+    #[test]
+    fn limits_of_one_search_do_not_carry_over() {
+        let (mut client, log) = fake_engine("reset");
+        search(&mut client, &GoParams::depth(6));
+        search(&mut client, &GoParams { movetime: Some(2500), ..Default::default() });
+
+        let commands = sent_commands(client, &log);
+        let limits: Vec<&str> = commands.iter().filter(|c| c.starts_with("sd ") || c.starts_with("st ")).map(String::as_str).collect();
+        assert_eq!(limits, ["sd 6".to_string(), format!("st {}", UNTIMED_MOVE_SECONDS), format!("sd {}", UNLIMITED_DEPTH), "st 3".to_string()]);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::uci::UciPosition, game::{cecp_client::CecpClient, engine_registry::UciOption, uci_client::{EngineError, UciClient, GoParams, SearchControl, SearchInfo, SearchResult}}};
+
+/// Protocol an engine binary speaks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Protocol {
+    #[default]
+    Uci,
+    /// XBoard / Chess Engine Communication Protocol version 2.
+    Cecp,
+}
+
+/// What the game and the evaluator need from an engine, whatever protocol it speaks.
+/// Positions, moves, limits and search output all use the UCI types.
+pub trait ChessEngine: Send {
+    fn name(&self) -> &str;
+    /// Options the engine declared while starting up.
+    fn options(&self) -> Vec<UciOption>;
+    fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), EngineError>;
+    fn new_game(&mut self) -> Result<(), EngineError>;
+    fn set_position(&mut self, position: &UciPosition) -> Result<(), EngineError>;
+    /// Searches the last position set and blocks until the engine picks a move,
+    /// handing every thinking line to `on_info`, which can end the search early.
+    fn search(&mut self, params: &GoParams, timeout: Option<Duration>, on_info: &mut dyn FnMut(&SearchInfo) -> SearchControl) -> Result<SearchResult, EngineError>;
+    fn stop(&mut self) -> Result<(), EngineError>;
+    /// Checks that an idle engine still answers, waiting at most `timeout`.
+    fn is_ready(&mut self, timeout: Duration) -> Result<(), EngineError>;
+    fn is_alive(&mut self) -> bool;
+    fn quit(&mut self);
+}
+
+/// Starts an engine binary with the client for its protocol.
+pub fn spawn_engine(protocol: Protocol, path: &str, args: &[String]) -> Result<Box<dyn ChessEngine>, EngineError> {
+    Ok(match protocol {
+        Protocol::Uci => Box::new(UciClient::spawn(path, args)?),
+        Protocol::Cecp => Box::new(CecpClient::spawn(path, args)?),
+    })
+}
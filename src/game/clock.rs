@@ -0,0 +1,443 @@
+use std::{fmt, time::{Duration, Instant}};
+
+use crate::{engine::{board::BoardMetaData, PieceColor}, game::uci_client::GoParams};
+
+/// Time controls offered in the new game dialog.
+pub const PRESETS: [&str; 7] = ["1+0", "3+2", "5+0", "10+0", "15+10", "30+0", "40/90, 30+30"];
+
+/// What a player gets back for each move, in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bonus {
+    None,
+    /// Fischer increment, added after every move.
+    Increment(u64),
+    /// Bronstein delay: the time used for a move is given back, up to this much.
+    Bronstein(u64),
+    /// Simple delay: the clock only starts running after this much of each move.
+    Delay(u64),
+}
+
+/// A period of the time control: `time_ms` for `moves` moves, or for the rest of the game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeStage {
+    pub moves: Option<u32>,
+    pub time_ms: u64,
+}
+
+/// Time each player gets. When the last stage has a move count it repeats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeControl {
+    pub stages: Vec<TimeStage>,
+    pub bonus: Bonus,
+}
+
+impl TimeControl {
+    /// Parses stages in minutes followed by the bonus in seconds: "5+3" (increment), "5+3d"
+    /// (simple delay), "5+3b" (Bronstein delay), or "40/90, 30+30" for 40 moves in 90
+    /// minutes and 30 more minutes for the rest of the game. A stage may have no time
+    /// ("0+1") when there is an increment to live on.
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let (stages, bonus) = match text.trim().split_once('+') {
+            Some((stages, bonus)) => (stages, Some(bonus.trim())),
+            None => (text, None),
+        };
+        let seconds = |s: &str| s.trim().parse::<f64>().ok().filter(|v| *v >= 0.0).map(|v| (v * 1000.0).round() as u64);
+        let bonus = match bonus {
+            None => Bonus::None,
+            Some(bonus) => match (bonus.strip_suffix('d'), bonus.strip_suffix('b')) {
+                (Some(delay), _) => Bonus::Delay(seconds(delay)?),
+                (_, Some(delay)) => Bonus::Bronstein(seconds(delay)?),
+                _ => Bonus::Increment(seconds(bonus)?),
+            },
+        };
+        let stages = stages
+            .split(',')
+            .map(|stage| {
+                let (moves, minutes) = match stage.split_once('/') {
+                    Some((moves, minutes)) => (Some(moves.trim().parse().ok().filter(|moves| *moves > 0)?), minutes),
+                    None => (None, stage),
+                };
+                let minutes = minutes.trim().parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+                Some(TimeStage { moves, time_ms: (minutes * 60_000.0).round() as u64 })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        // only the last stage can last for the rest of the game
+        if stages[..stages.len() - 1].iter().any(|stage| stage.moves.is_none()) {
+            return None;
+        }
+        let increment = matches!(bonus, Bonus::Increment(ms) if ms > 0);
+        if !increment && stages.iter().any(|stage| stage.time_ms == 0) {
+            return None;
+        }
+        Some(TimeControl { stages, bonus })
+    }
+
+    /// Value of the PGN TimeControl tag in seconds, e.g. "300+3" or "40/5400+30:1800+30".
+    /// The standard has no form for delays, they keep the "d" or "b" of [`TimeControl::parse`].
+    pub fn pgn_time_control(&self) -> String {
+        let bonus = self.bonus_text();
+        let stages: Vec<String> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                let moves = stage.moves.map_or(String::new(), |moves| format!("{}/", moves));
+                format!("{}{}{}", moves, stage.time_ms as f64 / 1000.0, bonus)
+            })
+            .collect();
+        stages.join(":")
+    }
+
+    /// "+3", "+3b" or "+3d" in seconds, empty without a bonus.
+    fn bonus_text(&self) -> String {
+        match self.bonus {
+            Bonus::None => String::new(),
+            Bonus::Increment(ms) => format!("+{}", ms as f64 / 1000.0),
+            Bonus::Bronstein(ms) => format!("+{}b", ms as f64 / 1000.0),
+            Bonus::Delay(ms) => format!("+{}d", ms as f64 / 1000.0),
+        }
+    }
+
+    /// Bonus per move, which is all UCI has room for: a delay is passed on as an increment.
+    fn bonus_ms(&self) -> u64 {
+        match self.bonus {
+            Bonus::None => 0,
+            Bonus::Increment(ms) | Bonus::Bronstein(ms) | Bonus::Delay(ms) => ms,
+        }
+    }
+
+    /// Time each side starts with. Without time in the first stage that is one increment, so
+    /// the side to move does not lose on time before its first move.
+    fn starting_ms(&self) -> u64 {
+        match self.stages[0].time_ms {
+            0 => self.bonus_ms(),
+            time_ms => time_ms,
+        }
+    }
+
+    /// Stage a side is in after `moves` moves, and how many moves are left in it.
+    fn stage_at(&self, mut moves: u32) -> (usize, Option<u32>) {
+        let last = self.stages.len() - 1;
+        for (index, stage) in self.stages.iter().enumerate() {
+            let Some(length) = stage.moves else { return (index, None) };
+            if index == last {
+                return (index, Some(length - moves % length));
+            }
+            if moves < length {
+                return (index, Some(length - moves));
+            }
+            moves -= length;
+        }
+        (last, None)
+    }
+}
+
+/// The text `parse` reads, e.g. "40/90, 30+30".
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", stage.time_ms as f64 / 60_000.0)?;
+        }
+        write!(f, "{}", self.bonus_text())
+    }
+}
+
+fn side(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+    }
+}
+
+/// The game clock: one side's time runs while the other's is stopped.
+#[derive(Clone, Debug)]
+pub struct ChessClock {
+    control: TimeControl,
+    /// Time left in milliseconds by color, white first, as of the start of the running turn.
+    remaining: [u64; 2],
+    /// Moves each side has made.
+    moves: [u32; 2],
+    /// Side whose time runs, and since when.
+    running: Option<(PieceColor, Instant)>,
+}
+
+impl ChessClock {
+    pub fn new(control: TimeControl) -> ChessClock {
+        let first = control.starting_ms();
+        ChessClock { control, remaining: [first; 2], moves: [0; 2], running: None }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    /// Side whose time is running.
+    pub fn running(&self) -> Option<PieceColor> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Starts `color`'s time.
+    pub fn start(&mut self, color: PieceColor) {
+        self.running = Some((color, Instant::now()));
+    }
+
+    /// Stops the clock, e.g. when the game ended.
+    pub fn stop(&mut self) {
+        if let Some((color, since)) = self.running.take() {
+            self.remaining[side(color)] = self.left_after(color, since);
+        }
+    }
+
+    /// Time `color` has left right now.
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        let left = match self.running {
+            Some((running, since)) if running == color => self.left_after(color, since),
+            _ => self.remaining[side(color)],
+        };
+        Duration::from_millis(left)
+    }
+
+    /// Side that ran out of time.
+    pub fn flagged(&self) -> Option<PieceColor> {
+        [PieceColor::White, PieceColor::Black].into_iter().find(|color| self.remaining(*color).is_zero())
+    }
+
+    /// Ends the running side's move: adds its bonus, and the next stage's time when the move
+    /// completed a stage, then starts the opponent's time. A side out of time stays flagged.
+    /// Returns how long the move took.
+    pub fn press(&mut self) -> Duration {
+        let Some((color, since)) = self.running else { return Duration::ZERO };
+        let spent = since.elapsed();
+        let index = side(color);
+        let mut left = self.left_after(color, since);
+        if left == 0 {
+            self.stop();
+            return spent;
+        }
+        if let Bonus::Increment(ms) = self.control.bonus {
+            left += ms;
+        }
+        if let Bonus::Bronstein(ms) = self.control.bonus {
+            left += (spent.as_millis() as u64).min(ms);
+        }
+        let (_, to_go) = self.control.stage_at(self.moves[index]);
+        self.moves[index] += 1;
+        if to_go == Some(1) {
+            let (next, _) = self.control.stage_at(self.moves[index]);
+            left += self.control.stages[next].time_ms;
+        }
+        self.remaining[index] = left;
+        let opponent = match color {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        self.start(opponent);
+        spent
+    }
+
+    /// Puts the clock back to where the game's current line leads, e.g. after a takeback or
+    /// redo: each side gets the time it had left after its last move of the line, and the
+    /// side to move there has its time running.
+    pub fn rewind(&mut self, meta: &BoardMetaData) {
+        self.remaining = [self.control.starting_ms(); 2];
+        self.moves = [0; 2];
+        let line = meta.moves.line();
+        for (index, record) in line.iter().enumerate() {
+            let mover = side(meta.mover(index));
+            if let Some(clock) = record.time.clock {
+                self.remaining[mover] = clock.as_millis() as u64;
+            }
+            self.moves[mover] += 1;
+        }
+        self.start(meta.mover(line.len()));
+    }
+
+    /// Clock times for an engine that is to move now.
+    pub fn go_params(&self) -> GoParams {
+        let mover = self.running().unwrap_or(PieceColor::White);
+        let bonus = self.control.bonus_ms();
+        GoParams {
+            wtime: Some(self.remaining(PieceColor::White).as_millis() as u64),
+            btime: Some(self.remaining(PieceColor::Black).as_millis() as u64),
+            winc: Some(bonus),
+            binc: Some(bonus),
+            movestogo: self.control.stage_at(self.moves[side(mover)]).1,
+            ..Default::default()
+        }
+    }
+
+    /// Time `color` has left after its turn started at `since`, with a simple delay not counted.
+    fn left_after(&self, color: PieceColor, since: Instant) -> u64 {
+        let mut used = since.elapsed().as_millis() as u64;
+        if let Bonus::Delay(ms) = self.control.bonus {
+            used = used.saturating_sub(ms);
+        }
+        self.remaining[side(color)].saturating_sub(used)
+    }
+}
+
+/// "4:59", with tenths under ten seconds ("0:09.4") and hours when needed ("1:29:59").
+pub fn clock_text(left: Duration) -> String {
+    let ms = left.as_millis() as u64;
+    let (hours, minutes, seconds) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else if ms < 10_000 {
+        format!("0:{:02}.{}", seconds, ms / 100 % 10)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::Board, etc::DEFAULT_FEN};
+
+    const WHITE: PieceColor = PieceColor::White;
+    const BLACK: PieceColor = PieceColor::Black;
+
+    fn clock(control: &str) -> ChessClock {
+        ChessClock::new(TimeControl::parse(control).unwrap())
+    }
+
+    /// Ends `color`'s move as if it took `spent`.
+    fn press_after(clock: &mut ChessClock, color: PieceColor, spent: Duration) {
+        clock.running = Some((color, Instant::now() - spent));
+        clock.press();
+    }
+
+    /// Time left in milliseconds, allowing for the few the test itself takes.
+    fn assert_left(clock: &ChessClock, color: PieceColor, expected: u64) {
+        let left = clock.remaining(color).as_millis() as u64;
+        assert!(left <= expected && expected - left < 100, "{:?} has {} ms, expected {}", color, left, expected);
+    }
+
+    #[test]
+    fn parse_reads_stages_and_bonuses() {
+        let stage = |moves, minutes: u64| TimeStage { moves, time_ms: minutes * 60_000 };
+        assert_eq!(TimeControl::parse("5+3"), Some(TimeControl { stages: vec![stage(None, 5)], bonus: Bonus::Increment(3000) }));
+        assert_eq!(TimeControl::parse("5+3d").unwrap().bonus, Bonus::Delay(3000));
+        assert_eq!(TimeControl::parse(" 5 + 2.5b ").unwrap().bonus, Bonus::Bronstein(2500));
+        assert_eq!(TimeControl::parse("10").unwrap().bonus, Bonus::None);
+        assert_eq!(TimeControl::parse("0.5").unwrap().stages, [TimeStage { moves: None, time_ms: 30_000 }]);
+        assert_eq!(TimeControl::parse("40/90, 30+30").unwrap().stages, [stage(Some(40), 90), stage(None, 30)]);
+        assert_eq!(TimeControl::parse("0+1"), Some(TimeControl { stages: vec![stage(None, 0)], bonus: Bonus::Increment(1000) }));
+
+        for bad in ["", "abc", "5+x", "-5", "0", "0+0", "0+2d", "0+2b", "30, 40/90", "0/90", "5+-1"] {
+            assert_eq!(TimeControl::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn time_controls_print_as_they_parse() {
+        for text in ["5+3", "3+2d", "15+10b", "0+1", "40/90, 30+30", "40/120, 20/60, 15+30"] {
+            assert_eq!(TimeControl::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(TimeControl::parse("40/90, 30+30").unwrap().pgn_time_control(), "40/5400+30:1800+30");
+        assert_eq!(TimeControl::parse("5+3").unwrap().pgn_time_control(), "300+3");
+    }
+
+    #[test]
+    fn press_adds_the_increment_and_starts_the_opponent() {
+        let mut clock = clock("5+3");
+        clock.start(WHITE);
+        press_after(&mut clock, WHITE, Duration::from_secs(10));
+        assert_eq!(clock.running(), Some(BLACK));
+        assert_left(&clock, WHITE, 293_000);
+        assert_left(&clock, BLACK, 300_000);
+
+        let params = clock.go_params();
+        assert_eq!((params.winc, params.binc, params.movestogo), (Some(3000), Some(3000), None));
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_time_used_up_to_the_delay() {
+        let mut clock = clock("5+3b");
+        press_after(&mut clock, WHITE, Duration::from_secs(2));
+        assert_left(&clock, WHITE, 300_000);
+        press_after(&mut clock, WHITE, Duration::from_secs(10));
+        assert_left(&clock, WHITE, 293_000);
+    }
+
+    #[test]
+    fn simple_delay_runs_before_the_clock() {
+        let mut clock = clock("5+3d");
+        clock.running = Some((WHITE, Instant::now() - Duration::from_secs(2)));
+        assert_left(&clock, WHITE, 300_000);
+        clock.press();
+        assert_left(&clock, WHITE, 300_000);
+        press_after(&mut clock, WHITE, Duration::from_secs(10));
+        assert_left(&clock, WHITE, 293_000);
+    }
+
+    #[test]
+    fn completing_a_stage_adds_the_next_one() {
+        let mut clock = clock("2/10, 5");
+        assert_eq!(clock.go_params().movestogo, Some(2));
+        press_after(&mut clock, WHITE, Duration::from_secs(60));
+        // black to move, with both moves of its stage still to go
+        assert_eq!(clock.go_params().movestogo, Some(2));
+        press_after(&mut clock, BLACK, Duration::from_secs(1));
+        assert_eq!(clock.go_params().movestogo, Some(1));
+        press_after(&mut clock, WHITE, Duration::from_secs(60));
+        assert_left(&clock, WHITE, 480_000 + 300_000);
+        assert_left(&clock, BLACK, 599_000);
+    }
+
+    #[test]
+    fn a_last_stage_with_moves_repeats() {
+        let mut clock = clock("1/1");
+        press_after(&mut clock, WHITE, Duration::from_secs(30));
+        assert_left(&clock, WHITE, 90_000);
+        press_after(&mut clock, WHITE, Duration::from_secs(30));
+        assert_left(&clock, WHITE, 120_000);
+    }
+
+    #[test]
+    fn running_out_flags_and_stops_the_clock() {
+        let mut clock = clock("1");
+        press_after(&mut clock, WHITE, Duration::from_secs(61));
+        assert_eq!(clock.flagged(), Some(WHITE));
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.remaining(WHITE), Duration::ZERO);
+    }
+
+    #[test]
+    fn no_base_time_starts_on_the_increment() {
+        let mut clock = clock("0+1");
+        clock.start(WHITE);
+        assert_eq!(clock.flagged(), None);
+        assert_left(&clock, WHITE, 1000);
+        press_after(&mut clock, WHITE, Duration::from_millis(400));
+        assert_left(&clock, WHITE, 1600);
+    }
+
+    #[test]
+    fn rewind_takes_the_times_of_the_line() {
+        let mut board = Board::from(&DEFAULT_FEN.to_string());
+        for (uci, seconds) in [("e2e4", 295), ("e7e5", 290), ("g1f3", 280)] {
+            board.play_uci_move(uci).unwrap();
+            board.meta_data.moves.current_move_mut().unwrap().time.clock = Some(Duration::from_secs(seconds));
+        }
+        let mut clock = clock("5+3");
+        clock.rewind(&board.meta_data);
+        assert_eq!(clock.running(), Some(BLACK));
+        assert_left(&clock, WHITE, 280_000);
+        assert_left(&clock, BLACK, 290_000);
+
+        // back to before 2. Nf3
+        let tree = &board.meta_data.moves;
+        let before = tree.path(tree.current())[1];
+        board.goto(before);
+        clock.rewind(&board.meta_data);
+        assert_eq!(clock.running(), Some(WHITE));
+        assert_left(&clock, WHITE, 295_000);
+        assert_eq!(clock.moves, [1, 1]);
+    }
+}
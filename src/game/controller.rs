@@ -1,24 +1,39 @@
-use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use stockfish::Stockfish;
-
-use crate::{engine::PieceColor, game::stockfish_engine::{StockfishCmd, StockfishResult}};
+use crate::{engine::PieceColor, game::{adaptive::PlayerProfile, clock::{ChessClock, TimeControl}, stockfish_engine::{StockfishCmd, StockfishResult}, strength::EngineStrength}};
 #[derive(Clone)]
 pub enum GameMode { PvP, PvE, Sandbox}
 #[derive(Clone)]
-pub enum LostBy {Checkmate, StaleMate, Draw, Timeout} 
+pub enum LostBy {Checkmate, StaleMate, Draw, Timeout, Resignation, Abandoned}
 pub struct GameController {
     pub mode:GameMode,
     pub player: PieceColor,
     pub enemey: PieceColor,
     pub game_over: bool,
     pub lost_by: Option<LostBy>,
-    pub stockfish: Option<Arc<Mutex<Stockfish>>>,
     pub stockfish_rx: Option<std::sync::mpsc::Receiver<StockfishResult>>,
     pub stockfish_tx: Option<std::sync::mpsc::Sender<StockfishCmd>>,
     pub stockfish_move_pending: bool,
-    pub search_depth: usize,
-   
+    pub strength: EngineStrength,
+    /// Adaptive opponent: strength follows the player's profile and the engine may pick
+    /// weaker moves to keep the game even.
+    pub adaptive: bool,
+    pub profile: PlayerProfile,
+    /// Player move accuracies measured in the current adaptive game.
+    pub accuracy: Vec<f32>,
+    /// Time control of the next game, `None` to play without a clock.
+    pub time_control: Option<TimeControl>,
+    /// Clock of the running game.
+    pub clock: Option<ChessClock>,
+    /// When the side to move started thinking, for timing moves in games without a clock.
+    pub move_started: Instant,
+    /// Moves of the game whose time was already recorded.
+    pub timed_moves: usize,
+    /// Whether moves may be taken back in the running game; never in rated (adaptive) games.
+    pub takebacks: bool,
+    /// A takeback cancelled the engine's search: its moves are dropped until the engine
+    /// confirms.
+    pub cancelling_search: bool,
 }
 
 impl Default for GameController {
@@ -28,15 +43,40 @@ impl Default for GameController {
             player: PieceColor::White,
             enemey: PieceColor::Black,
             game_over: true,
-            stockfish: None,
             stockfish_move_pending: false,
             stockfish_rx:None,
             stockfish_tx:None,
-            search_depth:20,
+            strength: EngineStrength::default(),
+            adaptive: false,
+            profile: PlayerProfile::load(),
+            accuracy: Vec::new(),
             lost_by: None,
-            
+            time_control: None,
+            clock: None,
+            move_started: Instant::now(),
+            timed_moves: 0,
+            takebacks: true,
+            cancelling_search: false,
         }
     }
 }
 
 
+
+impl GameController {
+    /// Records the result of an adaptive game in the player's profile. `score` is from the
+    /// player's side: 1 win, 0.5 draw, 0 loss.
+    pub fn finish_adaptive_game(&mut self, score: f32) {
+        if !self.adaptive {
+            return;
+        }
+        // a couple of measured moves say little about the player's level
+        let accuracy = if self.accuracy.len() >= 5 {
+            Some(self.accuracy.iter().sum::<f32>() / self.accuracy.len() as f32)
+        } else {
+            None
+        };
+        self.profile.record_game(self.strength.elo(), score, accuracy);
+        self.accuracy.clear();
+    }
+}
@@ -0,0 +1,290 @@
+use std::{cmp::Reverse, ops::{Deref, DerefMut}, sync::{mpsc::Sender, Arc, Condvar, Mutex, MutexGuard}};
+
+use crate::game::{engine_registry::EngineConfig, supervisor::EngineSupervisor, uci_client::EngineError};
+
+/// Engines running at once unless the user picks another limit.
+pub const DEFAULT_POOL_SIZE: usize = 2;
+
+/// Who wants an engine. When all engines are busy the highest priority waiting gets the
+/// next free one, in arrival order within a priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    MoveEval,
+    EvalBar,
+    Analysis,
+    Game,
+}
+
+struct PooledEngine {
+    /// Engine configuration the process was started with, see [`PoolState::generation`].
+    generation: u64,
+    engine: EngineSupervisor,
+}
+
+struct PoolState {
+    config: Option<EngineConfig>,
+    /// Bumped when the engine configuration changes, so old processes are not handed out again.
+    generation: u64,
+    idle: Vec<PooledEngine>,
+    /// Engine processes that exist, idle, leased or starting.
+    running: usize,
+    max_running: usize,
+    /// Leases being waited for, by priority and ticket number.
+    waiting: Vec<(Priority, u64)>,
+    next_ticket: u64,
+    /// Whether the last start failed, so the failure is only reported once.
+    spawn_failed: bool,
+    closed: bool,
+}
+
+/// Engine processes shared by the game opponent, the eval bar and move evaluations.
+/// Cloning gives another handle to the same pool.
+#[derive(Clone)]
+pub struct EnginePool {
+    shared: Arc<(Mutex<PoolState>, Condvar)>,
+    errors: Sender<String>,
+}
+
+/// An engine handed out by the pool, returned to it when dropped. Engines whose options were
+/// changed are shut down instead of being returned.
+pub struct EngineLease {
+    pool: EnginePool,
+    engine: Option<PooledEngine>,
+}
+
+impl EnginePool {
+    pub fn new(config: Option<EngineConfig>, max_running: usize, errors: Sender<String>) -> Self {
+        let state = PoolState {
+            config,
+            generation: 0,
+            idle: Vec::new(),
+            running: 0,
+            max_running: max_running.max(1),
+            waiting: Vec::new(),
+            next_ticket: 0,
+            spawn_failed: false,
+            closed: false,
+        };
+        Self { shared: Arc::new((Mutex::new(state), Condvar::new())), errors }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for a free engine, starting a new one while fewer than the limit are running.
+    pub fn lease(&self, priority: Priority) -> Result<EngineLease, EngineError> {
+        let (_, freed) = &*self.shared;
+        let mut state = self.state();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push((priority, ticket));
+        loop {
+            if state.closed {
+                state.waiting.retain(|(_, t)| *t != ticket);
+                return Err(EngineError::Exited);
+            }
+            let first = state.waiting.iter().max_by_key(|(p, t)| (*p, Reverse(*t))).is_some_and(|(_, t)| *t == ticket);
+            if first {
+                if let Some(engine) = state.idle.pop() {
+                    state.waiting.retain(|(_, t)| *t != ticket);
+                    freed.notify_all();
+                    return Ok(EngineLease { pool: self.clone(), engine: Some(engine) });
+                }
+                if state.running < state.max_running {
+                    state.waiting.retain(|(_, t)| *t != ticket);
+                    freed.notify_all();
+                    return self.start_slot(state);
+                }
+            }
+            state = freed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Like [`EnginePool::lease`], but `None` instead of waiting when every engine is busy
+    /// or a lease of the same or a higher priority is already waited for.
+    pub fn try_lease(&self, priority: Priority) -> Result<Option<EngineLease>, EngineError> {
+        let mut state = self.state();
+        if state.closed {
+            return Err(EngineError::Exited);
+        }
+        if state.waiting.iter().any(|(p, _)| *p >= priority) {
+            return Ok(None);
+        }
+        if let Some(engine) = state.idle.pop() {
+            return Ok(Some(EngineLease { pool: self.clone(), engine: Some(engine) }));
+        }
+        if state.running < state.max_running {
+            return self.start_slot(state).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Starts an engine with the current configuration in a free slot.
+    fn start_slot(&self, mut state: MutexGuard<'_, PoolState>) -> Result<EngineLease, EngineError> {
+        let Some(config) = state.config.clone() else {
+            if !state.spawn_failed {
+                let _ = self.errors.send("No engine is configured. Add one in Engines.".to_string());
+            }
+            state.spawn_failed = true;
+            return Err(EngineError::Spawn(std::io::Error::new(std::io::ErrorKind::NotFound, "no engine configured")));
+        };
+        state.running += 1;
+        let generation = state.generation;
+        drop(state);
+        self.start(config, generation)
+    }
+
+    /// Starts an engine for a slot already counted in `running`.
+    fn start(&self, config: EngineConfig, generation: u64) -> Result<EngineLease, EngineError> {
+        let mut engine = EngineSupervisor::new(config, self.errors.clone());
+        let started = engine.start();
+        let mut state = self.state();
+        match started {
+            Ok(()) => {
+                state.spawn_failed = false;
+                Ok(EngineLease { pool: self.clone(), engine: Some(PooledEngine { generation, engine }) })
+            }
+            Err(e) => {
+                state.running -= 1;
+                if !state.spawn_failed {
+                    let _ = self.errors.send(format!("{} could not be started: {}", engine.name(), e));
+                }
+                state.spawn_failed = true;
+                self.shared.1.notify_all();
+                Err(e)
+            }
+        }
+    }
+
+    fn release(&self, mut pooled: PooledEngine) {
+        let mut state = self.state();
+        let reusable = !state.closed && pooled.generation == state.generation && !pooled.engine.is_customized() && !pooled.engine.gave_up();
+        if reusable {
+            state.idle.push(pooled);
+        } else {
+            state.running -= 1;
+            drop(state);
+            pooled.engine.quit();
+        }
+        self.shared.1.notify_all();
+    }
+
+    /// Uses `config` for engines started from now on and shuts down the idle ones.
+    pub fn set_config(&self, config: Option<EngineConfig>) {
+        let mut state = self.state();
+        state.config = config;
+        state.generation += 1;
+        state.spawn_failed = false;
+        let idle = std::mem::take(&mut state.idle);
+        state.running -= idle.len();
+        drop(state);
+        for mut pooled in idle {
+            pooled.engine.quit();
+        }
+        self.shared.1.notify_all();
+    }
+
+    pub fn set_max_running(&self, max_running: usize) {
+        self.state().max_running = max_running.max(1);
+        self.shared.1.notify_all();
+    }
+
+    /// Health-checks the idle engines, see [`EngineSupervisor::check_health`].
+    pub fn check_health(&self) {
+        let idle = std::mem::take(&mut self.state().idle);
+        if idle.is_empty() {
+            return;
+        }
+        let mut checked = Vec::new();
+        for mut pooled in idle {
+            pooled.engine.check_health();
+            checked.push(pooled);
+        }
+        for pooled in checked {
+            // an engine that could not be restarted frees its slot
+            self.release(pooled);
+        }
+    }
+
+    /// Shuts down every engine: idle ones now, leased ones when they are returned.
+    pub fn shutdown(&self) {
+        let mut state = self.state();
+        state.closed = true;
+        let idle = std::mem::take(&mut state.idle);
+        state.running -= idle.len();
+        drop(state);
+        for mut pooled in idle {
+            pooled.engine.quit();
+        }
+        self.shared.1.notify_all();
+    }
+}
+
+impl Deref for EngineLease {
+    type Target = EngineSupervisor;
+
+    fn deref(&self) -> &EngineSupervisor {
+        &self.engine.as_ref().expect("engine lease used after release").engine
+    }
+}
+
+impl DerefMut for EngineLease {
+    fn deref_mut(&mut self) -> &mut EngineSupervisor {
+        &mut self.engine.as_mut().expect("engine lease used after release").engine
+    }
+}
+
+impl Drop for EngineLease {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool.release(engine);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{collections::BTreeMap, fs, sync::mpsc};
+
+    use super::*;
+
+    /// The smallest UCI engine the pool can start, in a shell script.
+    const FAKE_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "id name Fake Engine"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    fn fake_pool(test: &str, max_running: usize) -> EnginePool {
+        let dir = std::env::temp_dir().join(format!("koch-fake-pool-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("engine.sh");
+        fs::write(&script, FAKE_ENGINE).unwrap();
+        let config = EngineConfig {
+            name: "Fake Engine".to_string(),
+            path: "sh".to_string(),
+            args: vec![script.display().to_string()],
+            protocol: Default::default(),
+            options: Vec::new(),
+            values: BTreeMap::new(),
+        };
+        EnginePool::new(Some(config), max_running, mpsc::channel().0)
+    }
+
+    #[test]
+    fn try_lease_does_not_wait_for_a_busy_engine() {
+        let pool = fake_pool("try-lease", 1);
+        let game = pool.try_lease(Priority::Game).unwrap().expect("a slot is free");
+        assert!(pool.try_lease(Priority::EvalBar).unwrap().is_none());
+        drop(game);
+        let bar = pool.try_lease(Priority::EvalBar).unwrap().expect("the engine was returned");
+        drop(bar);
+        pool.shutdown();
+        assert!(pool.try_lease(Priority::EvalBar).is_err());
+    }
+}
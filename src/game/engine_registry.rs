@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{etc::{data_dir, ENGINE_PATH}, game::{chess_engine::{spawn_engine, ChessEngine, Protocol}, engine_pool::DEFAULT_POOL_SIZE, uci_client::EngineError}};
+
+/// Option that makes UCI engines send `wdl` with their scores.
+const SHOW_WDL: &str = "UCI_ShowWDL";
+
+/// Type of a UCI option, with the data the settings form needs to edit it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OptionKind {
+    Spin { min: i64, max: i64 },
+    Check,
+    Combo { vars: Vec<String> },
+    String,
+    Button,
+}
+
+/// One `option name ... type ...` declaration sent by an engine during the handshake.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UciOption {
+    pub name: String,
+    pub kind: OptionKind,
+    pub default: String,
+}
+
+impl UciOption {
+    /// Parses a declaration without the leading `option`, e.g.
+    /// "name Threads type spin default 1 min 1 max 1024".
+    pub fn parse(line: &str) -> Option<UciOption> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let keywords = ["name", "type", "default", "min", "max", "var"];
+        let mut name = Vec::new();
+        let mut kind = None;
+        let mut default = Vec::new();
+        let (mut min, mut max) = (None, None);
+        let mut vars: Vec<Vec<&str>> = Vec::new();
+        let mut current = "";
+        for token in tokens {
+            if keywords.contains(&token) {
+                // names and values may contain spaces, so a keyword only counts once its field is due
+                let in_name = current == "name" && token != "type";
+                if !in_name {
+                    current = token;
+                    if token == "var" {
+                        vars.push(Vec::new());
+                    }
+                    continue;
+                }
+            }
+            match current {
+                "name" => name.push(token),
+                "type" => kind = Some(token),
+                "default" => default.push(token),
+                "min" => min = token.parse().ok(),
+                "max" => max = token.parse().ok(),
+                "var" => {
+                    if let Some(var) = vars.last_mut() {
+                        var.push(token);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if name.is_empty() {
+            return None;
+        }
+        let kind = match kind? {
+            "spin" => OptionKind::Spin { min: min.unwrap_or(i64::MIN), max: max.unwrap_or(i64::MAX) },
+            "check" => OptionKind::Check,
+            "combo" => OptionKind::Combo { vars: vars.iter().map(|v| v.join(" ")).collect() },
+            "string" => OptionKind::String,
+            "button" => OptionKind::Button,
+            _ => return None,
+        };
+        let mut default = default.join(" ");
+        if kind == OptionKind::String && default == "<empty>" {
+            default.clear();
+        }
+        Some(UciOption { name: name.join(" "), kind, default })
+    }
+
+    /// Parses the value of a CECP `feature option="..."`, e.g. "Hash -spin 64 1 4096"
+    /// or "Style -combo Solid /// *Normal /// Risky" where `*` marks the default.
+    pub fn parse_cecp(text: &str) -> Option<UciOption> {
+        let (name, rest) = text.split_once(" -")?;
+        let (kind, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let args = args.trim();
+        let numbers: Vec<i64> = args.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+        let (kind, default) = match kind {
+            "spin" | "slider" => match numbers.as_slice() {
+                [default, min, max, ..] => (OptionKind::Spin { min: *min, max: *max }, default.to_string()),
+                _ => return None,
+            },
+            "check" => (OptionKind::Check, (args == "1").to_string()),
+            "combo" => {
+                let choices: Vec<&str> = args.split("///").map(str::trim).collect();
+                let default = choices.iter().find(|c| c.starts_with('*')).or(choices.first()).map(|c| c.trim_start_matches('*').to_string());
+                let vars = choices.iter().map(|c| c.trim_start_matches('*').to_string()).collect();
+                (OptionKind::Combo { vars }, default.unwrap_or_default())
+            }
+            "string" | "file" | "path" => (OptionKind::String, args.to_string()),
+            "button" | "save" | "reset" => (OptionKind::Button, String::new()),
+            _ => return None,
+        };
+        Some(UciOption { name: name.trim().to_string(), kind, default })
+    }
+}
+
+/// A local UCI or XBoard engine binary and the option values the user picked for it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngineConfig {
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Options the engine declared the last time it was started.
+    #[serde(default)]
+    pub options: Vec<UciOption>,
+    /// Values that differ from the engine defaults, by option name.
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
+
+impl EngineConfig {
+    /// Starts the engine once to read its name and options.
+    pub fn discover(path: &str, args: Vec<String>, protocol: Protocol) -> Result<EngineConfig, EngineError> {
+        let engine = spawn_engine(protocol, path, &args)?;
+        Ok(EngineConfig {
+            name: engine.name().to_string(),
+            path: path.to_string(),
+            args,
+            protocol,
+            options: engine.options(),
+            values: BTreeMap::new(),
+        })
+    }
+
+    /// Current value of an option: the user's setting or the engine default.
+    pub fn value(&self, option: &UciOption) -> String {
+        self.values.get(&option.name).cloned().unwrap_or_else(|| option.default.clone())
+    }
+
+    /// Stores a value, forgetting it again when it matches the engine default.
+    pub fn set_value(&mut self, option: &UciOption, value: String) {
+        if value == option.default {
+            self.values.remove(&option.name);
+        } else {
+            self.values.insert(option.name.clone(), value);
+        }
+    }
+
+    /// Starts the engine and sends every saved option value. Engines that can report
+    /// win/draw/loss chances are asked to, unless the user turned it off.
+    pub fn spawn(&self) -> Result<Box<dyn ChessEngine>, EngineError> {
+        let mut client = spawn_engine(self.protocol, &self.path, &self.args)?;
+        for (name, value) in &self.values {
+            client.set_option(name, Some(value))?;
+        }
+        let shows_wdl = self.options.iter().any(|option| option.name == SHOW_WDL && option.kind == OptionKind::Check);
+        if shows_wdl && !self.values.contains_key(SHOW_WDL) {
+            client.set_option(SHOW_WDL, Some("true"))?;
+        }
+        Ok(client)
+    }
+}
+
+/// Every engine the user has added, saved as `engines.json` in the data folder.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngineRegistry {
+    pub engines: Vec<EngineConfig>,
+    /// Index of the engine used for games and evaluation.
+    pub selected: usize,
+    /// Engine processes that may run at once for games and evaluation.
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize,
+    /// Let the game opponent think on the player's time.
+    #[serde(default)]
+    pub ponder: bool,
+}
+
+fn default_pool_size() -> usize {
+    DEFAULT_POOL_SIZE
+}
+
+impl Default for EngineRegistry {
+    fn default() -> Self {
+        Self {
+            engines: vec![EngineConfig {
+                name: "Stockfish".to_string(),
+                path: ENGINE_PATH.to_string(),
+                args: Vec::new(),
+                protocol: Protocol::Uci,
+                options: Vec::new(),
+                values: BTreeMap::new(),
+            }],
+            selected: 0,
+            pool_size: DEFAULT_POOL_SIZE,
+            ponder: false,
+        }
+    }
+}
+
+impl EngineRegistry {
+    fn file() -> PathBuf {
+        data_dir().join("engines.json")
+    }
+
+    /// Loads the saved registry, falling back to the default engine.
+    pub fn load() -> Self {
+        fs::read_to_string(Self::file())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(Self::file(), json) {
+                    eprintln!("could not save engines: {}", e);
+                }
+            }
+            Err(e) => eprintln!("could not save engines: {}", e),
+        }
+    }
+
+    pub fn selected(&self) -> Option<&EngineConfig> {
+        self.engines.get(self.selected)
+    }
+
+    /// Adds an engine, or replaces the options of one with the same path while keeping its values.
+    pub fn add(&mut self, config: EngineConfig) {
+        match self.engines.iter_mut().find(|e| e.path == config.path) {
+            Some(existing) => {
+                existing.name = config.name;
+                existing.options = config.options;
+            }
+            None => self.engines.push(config),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.engines.len() {
+            self.engines.remove(index);
+            // the selection stays on the same engine
+            if index < self.selected {
+                self.selected -= 1;
+            }
+        }
+        if self.selected >= self.engines.len() {
+            self.selected = self.engines.len().saturating_sub(1);
+        }
+    }
+
+    /// Starts the selected engine with its saved settings.
+    pub fn spawn_selected(&self) -> Result<Box<dyn ChessEngine>, EngineError> {
+        match self.selected() {
+            Some(config) => config.spawn(),
+            None => Err(EngineError::Spawn(std::io::Error::new(std::io::ErrorKind::NotFound, "no engine configured"))),
+        }
+    }
+}
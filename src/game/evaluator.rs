@@ -1,170 +1,283 @@
-use std::{sync::{mpsc::{Receiver, Sender}, Arc}, time::Duration};
+use std::{collections::VecDeque, sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError}, time::Duration};
 
-use eframe::egui::mutex::Mutex;
-use stockfish::Stockfish;
+use crate::{engine::{score::{Score, Wdl}, uci::UciPosition}, game::{engine_pool::{EnginePool, Priority}, supervisor::HEALTH_INTERVAL, uci_client::{GoParams, SearchControl}}, ui::app::MyApp};
 
-use crate::{engine::PieceColor, etc::STOCKFISH_ELO, ui::app::MyApp};
+/// Depths the eval bar is refined through, so a rough value shows up right away.
+pub const BAR_DEPTHS: [u32; 3] = [2, 10, 12];
+const MOVE_EVAL_DEPTH: u32 = 15;
+/// How long a bar request waits before asking the pool again when every engine was busy.
+const BAR_RETRY_INTERVAL: Duration = Duration::from_millis(250);
 
 pub struct EvaluatorQueue {
-    pub eval_queue: Vec<EvaluationRequest>,
+    /// Id given to the next request.
+    pub next_id: u64,
+    /// Id of the newest bar request; results for older positions are dropped.
+    pub latest_bar: u64,
+    /// Position of the newest bar request.
+    pub latest_bar_position: String,
     // tx for transmiting a eval request to the evaluator
     pub eval_request_tx: Option<Sender<EvaluationRequest>>,
-    //rx for receiving the evaluation in centipawns,
-    pub eval_receiver_rx: Option<Receiver<f32>>
+    //rx for receiving the bar evaluations,
+    pub eval_receiver_rx: Option<Receiver<BarEvaluation>>
+}
+
+/// What an evaluation is for, which also decides its priority: the eval bar first,
+/// then single move evaluations.
+pub enum EvalKind {
+    /// Only the newest position matters; older bar requests are dropped or stopped.
+    BarEval,
+    MoveEval { reply_to: Sender<EvalResponse> },
 }
-pub enum EvalKind {BarEval, MoveEval{ reply_to: Sender<EvalResponse>}}
 
 pub struct EvaluationRequest{
+    pub id: u64,
     pub position: String,
     pub kind: EvalKind,
 }
 pub struct EvalResponse {
-    pub value:f32,
+    /// Id of the request this answers.
+    pub id: u64,
+    pub score: Score,
+    pub wdl: Wdl,
+}
+/// One refinement of the eval bar.
+pub struct BarEvaluation {
+    pub id: u64,
+    pub depth: u32,
+    pub score: Score,
+    pub wdl: Wdl,
 }
 
 impl Default for EvaluatorQueue {
     fn default() -> Self {
-        Self { eval_queue: vec![], eval_request_tx: None, eval_receiver_rx: None,}
+        Self { next_id: 1, latest_bar: 0, latest_bar_position: String::new(), eval_request_tx: None, eval_receiver_rx: None,}
     }
 }
 
+#[derive(Default)]
 pub struct Evaluator {
-    pub stockfish_engine: Option<Arc<Mutex<Stockfish>>>,
     //reciever for the evaluations
     pub request_manager: EvaluatorQueue
 }
-pub struct Evaluation {
-    pub centipawns: u32,
-    pub color: PieceColor,
+/// Requests waiting for the evaluator thread.
+#[derive(Default)]
+struct Schedule {
+    bar: Option<EvaluationRequest>,
+    moves: VecDeque<EvaluationRequest>,
+    /// The bar request found every engine busy and waits before asking again.
+    bar_waiting: bool,
+    /// The app is gone and no more requests will come.
+    closed: bool,
 }
-impl Evaluator  {
-    pub fn new() -> Self {
-       
-            
-    let ev = Stockfish::new("/usr/bin/stockfish")
-        .ok()
-        .and_then(|mut s| {
-            s.setup_for_new_game().ok()?;
-            s.set_elo(3500).ok()?;
-            s.set_threads(10).ok()?;
-            println!("started");
-           
-            Some(Arc::new(Mutex::new(s)))
-        });
-   
-    Self {
-        stockfish_engine: ev,
-        request_manager: EvaluatorQueue::default()
+
+impl Schedule {
+    fn push(&mut self, request: EvaluationRequest) {
+        match request.kind {
+            // latest position wins
+            EvalKind::BarEval => self.bar = Some(request),
+            EvalKind::MoveEval { .. } => self.moves.push_back(request),
+        }
+    }
+
+    /// Puts a request that still needs an answer back at the front of its queue. A bar
+    /// request is dropped when a newer one came in.
+    fn retry(&mut self, request: EvaluationRequest) {
+        match request.kind {
+            EvalKind::BarEval => {
+                self.bar.get_or_insert(request);
+            }
+            EvalKind::MoveEval { .. } => self.moves.push_front(request),
+        }
+    }
+
+    /// Moves every request that arrived into the schedule without waiting.
+    fn receive(&mut self, rx: &Receiver<EvaluationRequest>) {
+        loop {
+            match rx.try_recv() {
+                Ok(request) => self.push(request),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<EvaluationRequest> {
+        self.bar.take().or_else(|| self.moves.pop_front())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bar.is_none() && self.moves.is_empty()
+    }
+
+    /// Whether a running search should be stopped: a newer bar position makes a bar search
+    /// useless.
+    fn preempts(&self, running: &EvalKind) -> bool {
+        self.closed || match running {
+            EvalKind::BarEval => self.bar.is_some(),
+            EvalKind::MoveEval { .. } => false,
+        }
     }
 }
+
+/// Runs one request on an engine from the pool. Returns the request when it was stopped
+/// before finishing, or the engine failed and was restarted, and it still needs an answer.
+fn evaluate(pool: &EnginePool, request: EvaluationRequest, schedule: &mut Schedule, rx: &Receiver<EvaluationRequest>, bar_tx: &Sender<BarEvaluation>) -> Option<EvaluationRequest> {
+    // the pool already told the user when no engine could be started
+    let mut engine = match request.kind {
+        // the game and an infinite analysis may hold every engine for as long as they run,
+        // so the bar does not wait for one: it asks again later, for the newest position then
+        EvalKind::BarEval => match pool.try_lease(Priority::EvalBar) {
+            Ok(Some(engine)) => engine,
+            Ok(None) => {
+                schedule.bar_waiting = true;
+                return Some(request);
+            }
+            Err(_) => return None,
+        },
+        EvalKind::MoveEval { .. } => match pool.lease(Priority::MoveEval) {
+            Ok(engine) => engine,
+            Err(_) => return None,
+        },
+    };
+    let position = UciPosition { start_fen: Some(request.position.clone()), moves: Vec::new() };
+    // engines score for the side to move, the app always shows White's view
+    let turn = position.board().turn;
+    if engine.set_position(&position).is_err() {
+        // an engine that gave up was reported, the request cannot be answered
+        return (!engine.gave_up()).then_some(request);
+    }
+    let depths: &[u32] = match request.kind {
+        EvalKind::BarEval => &BAR_DEPTHS,
+        EvalKind::MoveEval { .. } => &[MOVE_EVAL_DEPTH],
+    };
+    for &depth in depths {
+        let mut stopped = false;
+        let output = engine.search(&GoParams::depth(depth), &mut |_| {
+            schedule.receive(rx);
+            stopped = schedule.preempts(&request.kind);
+            if stopped { SearchControl::Stop } else { SearchControl::Continue }
+        });
+        if stopped {
+            return match request.kind {
+                EvalKind::BarEval => None,
+                _ => Some(request),
+            };
+        }
+        let Some(line) = output.ok().and_then(|output| output.main_line().cloned()) else { continue };
+        let Some(score) = line.score.and_then(|score| Score::from_side_to_move(score, turn)) else { continue };
+        // the engine's own chances when it reports them, the model's otherwise
+        let wdl = line.wdl.map_or_else(|| Wdl::from_score(score), |wdl| Wdl::from_side_to_move(wdl, turn));
+        match &request.kind {
+            EvalKind::BarEval => {
+                let _ = bar_tx.send(BarEvaluation { id: request.id, depth, score, wdl });
+            }
+            EvalKind::MoveEval { reply_to } => {
+                let _ = reply_to.send(EvalResponse { id: request.id, score, wdl });
+            }
+        }
+    }
+    None
 }
 
 impl MyApp {
     pub fn start_evaluator(&mut self) {
 
-        //asign the recievere and sender or the otside of the thread
-        let stockfish_evaluator = match &self.evaluator.stockfish_engine {
-            Some(s) => {
-                Arc::clone(s)
-            }
-            None => {
-                return;
-            }
-        };
+        // engines are borrowed from the pool for each request
+        let pool = self.engine_pool.clone();
 
         //we set up the channels
         let (eval_request_tx, eval_request_rx) = std::sync::mpsc::channel::<EvaluationRequest>();
-        let (eval_receiver_tx,  eval_receiver_rx) = std::sync::mpsc::channel::<f32>();
+        let (eval_receiver_tx,  eval_receiver_rx) = std::sync::mpsc::channel::<BarEvaluation>();
 
         self.evaluator.request_manager.eval_request_tx = Some(eval_request_tx);
         self.evaluator.request_manager.eval_receiver_rx = Some(eval_receiver_rx);
-        
+
         let _ = std::thread::Builder::new().name("evaluator_thread".to_string()).spawn(move || {
-            let mut engine = stockfish_evaluator.lock();
-            let mut request_queue:Vec<EvaluationRequest> = Vec::new();
+            let mut schedule = Schedule::default();
             loop {
-                match eval_request_rx.recv() {
-                    Ok(fen) => {
-                        request_queue.push(fen);
-                    }
-                    Err(e) => {}//eprintln!("Line {}: {}", line!(), e), // Added line number
-                }
-                if !request_queue.is_empty(){
-                    let currrent_request = request_queue.remove(0);
-                    match engine.set_fen_position(&currrent_request.position) {
-                        Ok(_) => {}
-                        Err(e) => {}//eprintln!("Line {}: {}", line!(), e)  // Added line number
+                if schedule.bar_waiting {
+                    // a newer position that comes in meanwhile replaces the waiting one
+                    schedule.bar_waiting = false;
+                    match eval_request_rx.recv_timeout(BAR_RETRY_INTERVAL) {
+                        Ok(request) => schedule.push(request),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
-                    match currrent_request.kind {
-                        EvalKind::BarEval => {
-                            for i in [2,  10,  12]{
-                                engine.set_depth(i);
-                                
-                                match engine.go() {
-                                    Ok(output) => {
-                                        let eval = output.eval();
-                                        let eval_type = eval.eval_type();
-                                        println!("{eval_type}");
-                                        let _ = eval_receiver_tx.send(eval.value() as f32);
-                                    }
-                                    Err(e) => {}//eprintln!("Line {}: {}", line!(), e)  // Added line number
-                                }
-                            }
-                        }
-                        EvalKind::MoveEval { reply_to: sender } => {
-
-                                engine.set_depth(15);    
-                                match engine.go() {
-                                    Ok(output) => {
-                                        let eval = output.eval();
-                                        let eval_type = eval.eval_type();
-                                        println!("{eval_type}");
-                                        let _ = sender.send(EvalResponse { value: eval.value() as f32 });
-                                    }
-                                    Err(e) =>{}//eprintln!("Line {}: {}", line!(), e)  // Added line number
-                                };
-                            
-                        }
-                        
+                } else if schedule.is_empty() {
+                    match eval_request_rx.recv_timeout(HEALTH_INTERVAL) {
+                        Ok(request) => schedule.push(request),
+                        // nothing to evaluate, make sure the idle engines still answer
+                        Err(RecvTimeoutError::Timeout) => pool.check_health(),
+                        // the app is gone
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
-                    
                 }
-               
-            
-        }});
-        
-        
-        
+                schedule.receive(&eval_request_rx);
+                if schedule.closed {
+                    break;
+                }
+                let Some(request) = schedule.next() else { continue };
+                // unfinished work goes back to the front of its queue
+                if let Some(unfinished) = evaluate(&pool, request, &mut schedule, &eval_request_rx, &eval_receiver_tx) {
+                    schedule.retry(unfinished);
+                }
+            }
+        });
     }
-    pub fn get_evaluation(&mut self) ->f32 {
+
+    /// Newest bar evaluation for the current position. Results for positions that were
+    /// replaced in the meantime are ignored. The deepest one is kept on the last move.
+    pub fn get_evaluation(&mut self) -> Score {
         let rx = match &self.evaluator.request_manager.eval_receiver_rx {
             Some(rx) => rx,
-            None => return 0.0,
+            None => return self.board.state.current_evaluation,
         };
-        match rx.try_recv() {
-            Ok(eval) => {
-                println!("{eval}");
-                self.board.state.current_evaluation = eval;
-                return self.board.state.current_evaluation;
-            },   
-            Err(e) => {
-                return self.board.state.current_evaluation;
-         
+        while let Ok(eval) = rx.try_recv() {
+            if eval.id != self.evaluator.request_manager.latest_bar {
+                continue;
+            }
+            // a running analysis of the game is deeper and keeps the bar up to date itself
+            if !self.analysis.follow_game {
+                self.board.state.current_evaluation = eval.score;
+                self.board.state.current_wdl = eval.wdl;
+            }
+            // the board may have moved on without a new bar request, e.g. after an engine move
+            let final_depth = BAR_DEPTHS.last() == Some(&eval.depth);
+            if final_depth && self.board.to_string() == self.evaluator.request_manager.latest_bar_position
+                && let Some(last) = self.board.meta_data.moves.current_move_mut() {
+                last.evaluation = Some(eval.score);
+                last.wdl = Some(eval.wdl);
             }
         }
+        self.board.state.current_evaluation
     }
-    
+
 }
 impl Evaluator {
-    pub fn send_eval_request(&mut self, board_position: String, eval_kind: EvalKind) {
-        let  tx = match &self.request_manager.eval_request_tx{
-            Some(tx) => tx,
-            None => return,
-        };
-        match tx.send(EvaluationRequest { position: board_position, kind: eval_kind}) {
-            Ok(_) => {}
-            Err(e) => {}//eprintln!("Line {}: {}", line!(), e)  // Added line number
+    /// Queues an evaluation and returns its id, which answers carry.
+    pub fn send_eval_request(&mut self, board_position: String, eval_kind: EvalKind) -> u64 {
+        let queue = &mut self.request_manager;
+        let id = queue.next_id;
+        queue.next_id += 1;
+        if let EvalKind::BarEval = eval_kind {
+            queue.latest_bar = id;
+            queue.latest_bar_position = board_position.clone();
+        }
+        if let Some(tx) = &queue.eval_request_tx {
+            let _ = tx.send(EvaluationRequest { id, position: board_position, kind: eval_kind });
         }
+        id
     }
-    
-}
\ No newline at end of file
+
+}
+
+impl MyApp {
+    /// Makes the pool start the currently selected engine from now on. Engines in use
+    /// keep running until their lease ends.
+    pub fn use_selected_engine(&mut self) {
+        self.engine_pool.set_config(self.engines.selected().cloned());
+    }
+}
@@ -0,0 +1,553 @@
+use std::{collections::HashMap, fmt, fs::{self, OpenOptions}, io::Write, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, mpsc::Sender, Arc}, time::{Duration, Instant}};
+
+use crate::{engine::{fen::fen_parser, pgn::{read_pgn, MoveComment, MoveTime, PgnGame, PgnMove}, uci::{parse_uci_move, UciPosition}, PieceColor}, game::{adaptive::score_cp, chess_engine::ChessEngine, engine_registry::EngineConfig, uci_client::{EngineError, GoParams, InfoScore, SearchControl}}};
+
+/// Extra time an engine gets past its limit before its search counts as hung.
+const SEARCH_GRACE: Duration = Duration::from_secs(5);
+/// Longest a depth or node limited search may take.
+const UNTIMED_SEARCH_LIMIT: Duration = Duration::from_secs(300);
+
+/// Search limit for one side of a match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchLimit {
+    /// Base time plus increment per move, in milliseconds.
+    Clock { base_ms: u64, inc_ms: u64 },
+    MoveTime(u64),
+    Depth(u32),
+    Nodes(u64),
+}
+
+impl MatchLimit {
+    /// Parses "40+0.4" (seconds plus increment), "st=1" (seconds per move), "depth=8" or
+    /// "nodes=10000".
+    pub fn parse(text: &str) -> Option<MatchLimit> {
+        let text = text.trim();
+        let seconds = |s: &str| s.parse::<f64>().ok().filter(|v| *v >= 0.0).map(|v| (v * 1000.0).round() as u64);
+        if let Some((key, value)) = text.split_once('=') {
+            return match key {
+                "st" => seconds(value).map(MatchLimit::MoveTime),
+                "depth" => value.parse().ok().map(MatchLimit::Depth),
+                "nodes" => value.parse().ok().map(MatchLimit::Nodes),
+                _ => None,
+            };
+        }
+        let (base, inc) = text.split_once('+').unwrap_or((text, "0"));
+        Some(MatchLimit::Clock { base_ms: seconds(base)?, inc_ms: seconds(inc)? })
+    }
+
+    /// Value of the PGN TimeControl tag, "-" when the game had no clock.
+    pub fn pgn_time_control(&self) -> String {
+        match self {
+            MatchLimit::Clock { base_ms, inc_ms: 0 } => format!("{}", base_ms / 1000),
+            MatchLimit::Clock { base_ms, inc_ms } => format!("{}+{}", base_ms / 1000, *inc_ms as f64 / 1000.0),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for MatchLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchLimit::Clock { base_ms, inc_ms } => write!(f, "{}+{}", *base_ms as f64 / 1000.0, *inc_ms as f64 / 1000.0),
+            MatchLimit::MoveTime(ms) => write!(f, "st={}", *ms as f64 / 1000.0),
+            MatchLimit::Depth(depth) => write!(f, "depth={}", depth),
+            MatchLimit::Nodes(nodes) => write!(f, "nodes={}", nodes),
+        }
+    }
+}
+
+/// One side of a match: an engine with its option values and its own limit.
+/// The same engine can play itself at two settings by using two players with different values.
+#[derive(Clone, Debug)]
+pub struct MatchPlayer {
+    pub name: String,
+    pub config: EngineConfig,
+    pub limit: MatchLimit,
+}
+
+/// When games are ended early on the engines' scores.
+#[derive(Clone, Copy, Debug)]
+pub struct Adjudication {
+    /// A side is adjudicated lost once the score stays at least this far against it...
+    pub resign_cp: i32,
+    /// ...for this many moves by each side.
+    pub resign_moves: u32,
+    /// A game is adjudicated drawn once the score stays within this many centipawns...
+    pub draw_cp: i32,
+    /// ...for this many moves by each side...
+    pub draw_moves: u32,
+    /// ...starting at this move number.
+    pub draw_from_move: u32,
+    /// Games still running after this many moves are drawn.
+    pub max_moves: u32,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self { resign_cp: 800, resign_moves: 4, draw_cp: 10, draw_moves: 8, draw_from_move: 40, max_moves: 200 }
+    }
+}
+
+/// Sequential probability ratio test between Elo `elo0` (H0) and `elo1` (H1).
+#[derive(Clone, Copy, Debug)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for SprtConfig {
+    fn default() -> Self {
+        Self { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }
+    }
+}
+
+impl SprtConfig {
+    /// LLR bounds: H0 is accepted below the first, H1 above the second.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+pub struct MatchConfig {
+    pub players: [MatchPlayer; 2],
+    /// Every opening is played twice, once with each engine as White.
+    pub openings: Vec<UciPosition>,
+    pub games: u32,
+    pub adjudication: Adjudication,
+    pub sprt: Option<SprtConfig>,
+    /// Every finished game is appended here.
+    pub pgn_path: Option<PathBuf>,
+    pub event: String,
+}
+
+/// Results from the first player's side.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// Variance of a single game's score given the number of wins, draws and losses.
+fn score_variance(wins: f64, draws: f64, losses: f64) -> f64 {
+    let n = (wins + draws + losses).max(1.0);
+    let s = (wins + draws / 2.0) / n;
+    (wins * (1.0 - s).powi(2) + draws * (0.5 - s).powi(2) + losses * s.powi(2)) / n
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    // adding zero turns -0 into 0 for display
+    -400.0 * (1.0 / score - 1.0).log10() + 0.0
+}
+
+impl MatchStats {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    /// Variance of a single game's score.
+    fn variance(&self) -> f64 {
+        score_variance(self.wins as f64, self.draws as f64, self.losses as f64)
+    }
+
+    /// Elo difference and its 95% error margin, `None` while the score is 0% or 100%.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let s = self.score();
+        if self.games() == 0 || s <= 0.0 || s >= 1.0 {
+            return None;
+        }
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo_from_score((s - margin).max(1e-6));
+        let high = elo_from_score((s + margin).min(1.0 - 1e-6));
+        Some((elo_from_score(s), (high - low) / 2.0))
+    }
+
+    /// Log-likelihood ratio of the SPRT, with the normal approximation of the game results.
+    pub fn llr(&self, sprt: &SprtConfig) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        // identical results have no variance, so half a win and half a loss are added then
+        let mut variance = self.variance();
+        if variance <= 0.0 {
+            variance = score_variance(self.wins as f64 + 0.5, self.draws as f64, self.losses as f64 + 0.5);
+        }
+        let expected = |elo: f64| 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+        let (s0, s1) = (expected(sprt.elo0), expected(sprt.elo1));
+        self.games() as f64 * (s1 - s0) * (2.0 * self.score() - s0 - s1) / (2.0 * variance)
+    }
+}
+
+impl fmt::Display for MatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "W {} D {} L {} ({:.1}%)", self.wins, self.draws, self.losses, self.score() * 100.0)?;
+        match self.elo() {
+            Some((elo, margin)) => write!(f, ", Elo {:+.1} ± {:.1}", elo, margin),
+            None => Ok(()),
+        }
+    }
+}
+
+pub enum MatchEvent {
+    GameStarted { game: u32, white: String, black: String, position: UciPosition },
+    /// A move was played; `score` is the mover's own evaluation.
+    Move { game: u32, position: UciPosition, san: String, score: Option<InfoScore> },
+    GameFinished { game: u32, result: String, reason: String, stats: MatchStats, llr: Option<f64> },
+    Finished { stats: MatchStats, reason: String },
+    Error(String),
+}
+
+/// Reads an opening suite: PGN games by their main line, or EPD/FEN positions one per line.
+pub fn load_openings(path: &Path) -> Result<Vec<UciPosition>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let is_pgn = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pgn"));
+    if is_pgn {
+        return Ok(read_pgn(&text).iter().map(PgnGame::position).collect());
+    }
+    let mut openings = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // EPD has four position fields followed by operations
+        let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+        let fen = format!("{} 0 1", fields.join(" "));
+        fen_parser(&fen).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        openings.push(UciPosition { start_fen: Some(fen), moves: Vec::new() });
+    }
+    Ok(openings)
+}
+
+struct GameOutcome {
+    result: &'static str,
+    /// Standard value of the PGN Termination tag.
+    termination: &'static str,
+    reason: String,
+    position: UciPosition,
+    /// Clock comments by move, the opening's moves included.
+    times: Vec<MoveTime>,
+}
+
+fn search_params(limit: MatchLimit, clocks: [u64; 2], incs: [u64; 2]) -> (GoParams, Duration) {
+    match limit {
+        MatchLimit::Clock { .. } => (
+            GoParams { wtime: Some(clocks[0]), btime: Some(clocks[1]), winc: Some(incs[0]), binc: Some(incs[1]), ..Default::default() },
+            Duration::from_millis(clocks[0].max(clocks[1])) + SEARCH_GRACE,
+        ),
+        MatchLimit::MoveTime(ms) => (GoParams { movetime: Some(ms), ..Default::default() }, Duration::from_millis(ms) + SEARCH_GRACE),
+        MatchLimit::Depth(depth) => (GoParams::depth(depth), UNTIMED_SEARCH_LIMIT),
+        MatchLimit::Nodes(nodes) => (GoParams { nodes: Some(nodes), ..Default::default() }, UNTIMED_SEARCH_LIMIT),
+    }
+}
+
+/// Plays one game. `white` is the index of the player with the white pieces.
+fn play_game(engines: &mut [Box<dyn ChessEngine>], config: &MatchConfig, white: usize, opening: &UciPosition, game: u32, events: &Sender<MatchEvent>, stop: &AtomicBool) -> GameOutcome {
+    let (players, adjudication) = (&config.players, &config.adjudication);
+    let mut position = opening.clone();
+    let mut board = opening.board();
+    let mut repetitions: HashMap<String, u32> = HashMap::new();
+    repetitions.insert(board.position_key(), 1);
+    // clocks by color, white first
+    let limit_of = |color: usize| players[if color == 0 { white } else { 1 - white }].limit;
+    let start_clock = |limit: MatchLimit| match limit {
+        MatchLimit::Clock { base_ms, inc_ms } => (base_ms, inc_ms),
+        _ => (0, 0),
+    };
+    let (mut clocks, mut incs) = ([0; 2], [0; 2]);
+    for color in 0..2 {
+        (clocks[color], incs[color]) = start_clock(limit_of(color));
+    }
+    let (mut resign_plies, mut draw_plies, mut leader) = (0, 0, 0);
+    // opening moves were not timed
+    let mut times = vec![MoveTime::default(); opening.moves.len()];
+    let finish = |result, termination, reason: String, position: &UciPosition, times: &[MoveTime]| GameOutcome { result, termination, reason, position: position.clone(), times: times.to_vec() };
+    let win_for = |color: usize| if color == 0 { "1-0" } else { "0-1" };
+
+    for engine in engines.iter_mut() {
+        let _ = engine.new_game();
+    }
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return finish("*", "abandoned", "match stopped".to_string(), &position, &times);
+        }
+        let color = if board.turn == PieceColor::White { 0 } else { 1 };
+        let legal = board.legal_moves();
+        if legal.is_empty() {
+            return if board.is_in_check(board.turn) {
+                finish(win_for(1 - color), "normal", "checkmate".to_string(), &position, &times)
+            } else {
+                finish("1/2-1/2", "normal", "stalemate".to_string(), &position, &times)
+            };
+        }
+        if board.halfmove_clock >= 100 {
+            return finish("1/2-1/2", "normal", "fifty-move rule".to_string(), &position, &times);
+        }
+        if repetitions.get(&board.position_key()).is_some_and(|n| *n >= 3) {
+            return finish("1/2-1/2", "normal", "threefold repetition".to_string(), &position, &times);
+        }
+        if board.is_insufficient_material() {
+            return finish("1/2-1/2", "normal", "insufficient material".to_string(), &position, &times);
+        }
+        if (position.moves.len() - opening.moves.len()) as u32 >= adjudication.max_moves * 2 {
+            return finish("1/2-1/2", "adjudication", "move limit".to_string(), &position, &times);
+        }
+
+        let mover = if color == 0 { white } else { 1 - white };
+        let name = &players[mover].name;
+        let (params, timeout) = search_params(limit_of(color), clocks, incs);
+        let started = Instant::now();
+        let search = engines[mover].set_position(&position).and_then(|_| engines[mover].search(&params, Some(timeout), &mut |_| SearchControl::Continue));
+        let spent = started.elapsed();
+        let elapsed = spent.as_millis() as u64;
+        let result = match search {
+            Ok(result) => result,
+            Err(EngineError::Timeout(_)) => return finish(win_for(1 - color), "time forfeit", format!("{} did not answer", name), &position, &times),
+            Err(e) => return finish(win_for(1 - color), "rules infraction", format!("{}: {}", name, e), &position, &times),
+        };
+        if let MatchLimit::Clock { .. } = limit_of(color) {
+            if elapsed > clocks[color] {
+                return finish(win_for(1 - color), "time forfeit", format!("{} lost on time", name), &position, &times);
+            }
+            clocks[color] = clocks[color] - elapsed + incs[color];
+        }
+        let clock = matches!(limit_of(color), MatchLimit::Clock { .. }).then(|| Duration::from_millis(clocks[color]));
+        let Some(uci) = legal.iter().find(|m| **m == result.best_move.to_ascii_lowercase()).cloned() else {
+            return finish(win_for(1 - color), "rules infraction", format!("{} played illegal move {}", name, result.best_move), &position, &times);
+        };
+
+        let score = result.main_line().and_then(|line| line.score);
+        match score.map(score_cp) {
+            Some(cp) => {
+                let white_cp = if color == 0 { cp } else { -cp };
+                let ahead = if white_cp > 0 { 0 } else { 1 };
+                if white_cp.abs() >= adjudication.resign_cp {
+                    resign_plies = if ahead == leader { resign_plies + 1 } else { 1 };
+                    leader = ahead;
+                } else {
+                    resign_plies = 0;
+                }
+                let move_number = board.fullmove_number;
+                if move_number >= adjudication.draw_from_move && white_cp.abs() <= adjudication.draw_cp {
+                    draw_plies += 1;
+                } else {
+                    draw_plies = 0;
+                }
+            }
+            None => {
+                resign_plies = 0;
+                draw_plies = 0;
+            }
+        }
+
+        let san = board.encode_san_move(&uci).unwrap_or_else(|| uci.clone());
+        if let Some((from, to, promotion)) = parse_uci_move(&uci) {
+            board.apply_move(from, to, promotion);
+        }
+        position.moves.push(uci);
+        times.push(MoveTime { clock, spent: Some(spent) });
+        *repetitions.entry(board.position_key()).or_insert(0) += 1;
+        let _ = events.send(MatchEvent::Move { game, position: position.clone(), san, score });
+
+        if resign_plies >= adjudication.resign_moves * 2 {
+            return finish(win_for(leader), "adjudication", "score adjudication".to_string(), &position, &times);
+        }
+        if draw_plies >= adjudication.draw_moves * 2 {
+            return finish("1/2-1/2", "adjudication", "draw adjudication".to_string(), &position, &times);
+        }
+    }
+}
+
+fn append_pgn(path: &Path, game: &PgnGame) {
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", game.to_pgn()));
+    if let Err(e) = written {
+        eprintln!("could not write {}: {}", path.display(), e);
+    }
+}
+
+/// Plays the whole match, reporting progress on `events`. Setting `stop` ends it after
+/// abandoning the running game. Returns the final score.
+pub fn run_match(config: MatchConfig, events: Sender<MatchEvent>, stop: Arc<AtomicBool>) -> MatchStats {
+    let mut stats = MatchStats::default();
+    let reason = play_match(&config, &events, &stop, &mut stats);
+    let _ = events.send(MatchEvent::Finished { stats, reason });
+    stats
+}
+
+/// Plays the games of the match, adding their results to `stats`. Returns why the match
+/// ended.
+fn play_match(config: &MatchConfig, events: &Sender<MatchEvent>, stop: &AtomicBool, stats: &mut MatchStats) -> String {
+    let mut engines = Vec::new();
+    for player in &config.players {
+        match player.config.spawn() {
+            Ok(engine) => engines.push(engine),
+            Err(e) => {
+                let _ = events.send(MatchEvent::Error(format!("{}: {}", player.name, e)));
+                return format!("{} could not be started", player.name);
+            }
+        }
+    }
+    let openings = if config.openings.is_empty() {
+        vec![UciPosition { start_fen: None, moves: Vec::new() }]
+    } else {
+        config.openings.clone()
+    };
+    let mut reason = "all games played".to_string();
+    for game in 0..config.games {
+        if stop.load(Ordering::Relaxed) {
+            reason = "stopped".to_string();
+            break;
+        }
+        // an engine that crashed in the last game gets restarted
+        for (engine, player) in engines.iter_mut().zip(&config.players) {
+            if !engine.is_alive() {
+                match player.config.spawn() {
+                    Ok(new_engine) => *engine = new_engine,
+                    Err(e) => {
+                        let _ = events.send(MatchEvent::Error(format!("{}: {}", player.name, e)));
+                        return format!("{} could not be restarted", player.name);
+                    }
+                }
+            }
+        }
+        let opening = &openings[(game / 2) as usize % openings.len()];
+        let white = (game % 2) as usize;
+        let (white_name, black_name) = (config.players[white].name.clone(), config.players[1 - white].name.clone());
+        let _ = events.send(MatchEvent::GameStarted { game, white: white_name.clone(), black: black_name.clone(), position: opening.clone() });
+
+        let outcome = play_game(&mut engines, config, white, opening, game, events, stop);
+        match (outcome.result, white) {
+            ("1-0", 0) | ("0-1", 1) => stats.wins += 1,
+            ("1-0", _) | ("0-1", _) => stats.losses += 1,
+            ("1/2-1/2", _) => stats.draws += 1,
+            _ => {}
+        }
+
+        if let Some(path) = &config.pgn_path {
+            let mut pgn = PgnGame {
+                start_fen: outcome.position.start_fen.clone(),
+                line: outcome
+                    .position
+                    .moves
+                    .iter()
+                    .zip(&outcome.times)
+                    .map(|(uci, time)| PgnMove { comment: MoveComment { time: *time, ..MoveComment::default() }, ..PgnMove::new(uci.clone()) })
+                    .collect(),
+                result: outcome.result.to_string(),
+                ..PgnGame::default()
+            };
+            pgn.set_tag("Event", config.event.clone());
+            pgn.set_tag("Site", "Koch");
+            pgn.set_tag("Date", chrono::Local::now().format("%Y.%m.%d").to_string());
+            pgn.set_tag("Round", (game + 1).to_string());
+            pgn.set_tag("White", white_name);
+            pgn.set_tag("Black", black_name);
+            let (white_tc, black_tc) = (config.players[white].limit.pgn_time_control(), config.players[1 - white].limit.pgn_time_control());
+            if white_tc == black_tc {
+                pgn.set_tag("TimeControl", white_tc);
+            } else {
+                pgn.set_tag("WhiteTimeControl", white_tc);
+                pgn.set_tag("BlackTimeControl", black_tc);
+            }
+            pgn.set_tag("Termination", outcome.termination);
+            append_pgn(path, &pgn);
+        }
+
+        let llr = config.sprt.map(|sprt| stats.llr(&sprt));
+        let _ = events.send(MatchEvent::GameFinished { game, result: outcome.result.to_string(), reason: outcome.reason, stats: *stats, llr });
+        if let (Some(sprt), Some(llr)) = (config.sprt, llr) {
+            let (lower, upper) = sprt.bounds();
+            if llr <= lower {
+                reason = format!("SPRT finished, H0 accepted (LLR {:.2})", llr);
+                break;
+            }
+            if llr >= upper {
+                reason = format!("SPRT finished, H1 accepted (LLR {:.2})", llr);
+                break;
+            }
+        }
+    }
+    reason
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: u32, draws: u32, losses: u32) -> MatchStats {
+        MatchStats { wins, draws, losses }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn limits_parse_and_print() {
+        assert_eq!(MatchLimit::parse("40+0.4"), Some(MatchLimit::Clock { base_ms: 40_000, inc_ms: 400 }));
+        assert_eq!(MatchLimit::parse(" 60 "), Some(MatchLimit::Clock { base_ms: 60_000, inc_ms: 0 }));
+        assert_eq!(MatchLimit::parse("st=0.5"), Some(MatchLimit::MoveTime(500)));
+        assert_eq!(MatchLimit::parse("depth=8"), Some(MatchLimit::Depth(8)));
+        assert_eq!(MatchLimit::parse("nodes=10000"), Some(MatchLimit::Nodes(10_000)));
+        for bad in ["", "fast", "40+x", "-1+1", "st=", "depth=-1", "nodes=1e4", "time=5"] {
+            assert_eq!(MatchLimit::parse(bad), None, "{}", bad);
+        }
+
+        assert_eq!(MatchLimit::Clock { base_ms: 40_000, inc_ms: 400 }.to_string(), "40+0.4");
+        assert_eq!(MatchLimit::MoveTime(1500).to_string(), "st=1.5");
+        assert_eq!(MatchLimit::Clock { base_ms: 40_000, inc_ms: 400 }.pgn_time_control(), "40+0.4");
+        assert_eq!(MatchLimit::Clock { base_ms: 60_000, inc_ms: 0 }.pgn_time_control(), "60");
+        assert_eq!(MatchLimit::Depth(8).pgn_time_control(), "-");
+    }
+
+    #[test]
+    fn sprt_bounds_for_five_percent_errors() {
+        let (lower, upper) = SprtConfig::default().bounds();
+        assert_close(lower, (0.05f64 / 0.95).ln());
+        assert_close(lower, -2.944438979);
+        assert_close(upper, 2.944438979);
+    }
+
+    #[test]
+    fn score_variance_of_known_results() {
+        assert_close(score_variance(60.0, 30.0, 10.0), 0.1125);
+        assert_close(score_variance(5.0, 0.0, 5.0), 0.25);
+        assert_close(score_variance(0.0, 10.0, 0.0), 0.0);
+        assert_close(score_variance(0.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn elo_and_margin_of_known_results() {
+        let (elo, margin) = stats(60, 30, 10).elo().unwrap();
+        assert_close(elo, 190.848501888);
+        assert_close(margin, 62.046219193);
+        let (elo, _) = stats(10, 0, 10).elo().unwrap();
+        assert_close(elo, 0.0);
+        assert_eq!(stats(10, 0, 0).elo(), None);
+        assert_eq!(stats(0, 0, 0).elo(), None);
+    }
+
+    #[test]
+    fn llr_of_known_results() {
+        let sprt = SprtConfig::default();
+        assert_close(stats(60, 30, 10).llr(&sprt), 1.575898513);
+        // ten times the games give ten times the evidence, enough to decide either way
+        let (lower, upper) = sprt.bounds();
+        assert_close(stats(600, 300, 100).llr(&sprt), 15.75898513);
+        assert!(stats(600, 300, 100).llr(&sprt) > upper);
+        assert!(stats(100, 300, 600).llr(&sprt) < lower);
+        // only draws have no variance, half a win and half a loss stand in for it
+        assert_close(stats(0, 10, 0).llr(&sprt), -0.011389224);
+        assert_eq!(stats(0, 0, 0).llr(&sprt), 0.0);
+    }
+}
@@ -1,3 +1,15 @@
 pub mod controller;
 pub mod stockfish_engine;
-pub mod evaluator;
\ No newline at end of file
+pub mod evaluator;
+pub mod uci_client;
+pub mod engine_registry;
+pub mod chess_engine;
+pub mod cecp_client;
+pub mod strength;
+pub mod adaptive;
+pub mod match_runner;
+pub mod supervisor;
+pub mod engine_pool;
+pub mod analysis;
+pub mod clock;
+pub mod archive;
\ No newline at end of file
@@ -1,120 +1,216 @@
-use std::{sync::{ mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread, time::Duration};
+use std::{sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError}, thread, time::Duration};
 
-use stockfish::Stockfish;
-
-use crate::{etc::STOCKFISH_ELO, ui::app::MyApp};
+use crate::{engine::uci::UciPosition, game::{adaptive::{MoveBlender, CANDIDATE_LINES}, strength::EngineStrength, engine_pool::{EngineLease, EnginePool, Priority}, supervisor::HEALTH_INTERVAL, uci_client::{EngineError, GoParams, SearchControl, SearchInfo, SearchResult}}, ui::app::MyApp};
 
 pub enum StockfishCmd {
     NewGame,
-    GoDepth(usize),
-    Go(String),
+    SetStrength(EngineStrength),
+    /// Move in the game so far, with the clock times when the game has a clock.
+    Go(UciPosition, Option<GoParams>),
+    /// Drops the running search, whose move is no longer wanted, e.g. after a takeback.
+    Cancel,
     Stop,
-    Eval,
 }
 pub enum StockfishResult {
     Succes,
     Fail,
     Move(String),
+    Info(SearchInfo),
+    /// Accuracy in percent of the player's last move, sent in adaptive games.
+    PlayerAccuracy(f32),
+    /// A `Cancel` was handled: moves sent before this one answer positions taken back.
+    Cancelled,
+}
+
+/// The engine leased for the game, leasing one with the game's settings on first use.
+fn game_engine<'a>(pool: &EnginePool, lease: &'a mut Option<EngineLease>, strength: EngineStrength, adaptive: bool, ponder: bool) -> Option<&'a mut EngineLease> {
+    if lease.is_none() {
+        let mut engine = pool.lease(Priority::Game).ok()?;
+        for (name, value) in strength.options(&engine.options()) {
+            let _ = engine.set_option(name, &value);
+        }
+        if adaptive {
+            let _ = engine.set_option("MultiPV", &CANDIDATE_LINES.to_string());
+        }
+        if ponder && engine.can_ponder() {
+            let _ = engine.set_option("Ponder", "true");
+        }
+        *lease = Some(engine);
+    }
+    lease.as_mut()
+}
+/// How thinking on the player's time ended.
+enum PonderOutcome {
+    /// The player made the expected move and the search went on; its answer.
+    Hit(Result<SearchResult, EngineError>),
+    /// The player did something else: the command that stopped the search, if any.
+    Missed(Option<StockfishCmd>),
+}
+
+/// Searches `guess`, the game with the engine's move and the reply it expects, until the
+/// player moves. The search is kept with a ponderhit when the player's move is the expected one
+/// and its limits are still those of the search; a ponderhit cannot tell the engine a new clock,
+/// so when the clock moved on the search is stopped and the move searched again with it.
+fn ponder_on(engine: &mut EngineLease, guess: &UciPosition, strength: EngineStrength, params: GoParams, cmd_rx: &Receiver<StockfishCmd>, res_tx: &Sender<StockfishResult>) -> PonderOutcome {
+    if engine.set_position(guess).is_err() {
+        return PonderOutcome::Missed(None);
+    }
+    let declared = engine.options();
+    let mut hit = false;
+    let mut interrupted = None;
+    let search = engine.search(&GoParams { ponder: true, ..params.clone() }, &mut |info| {
+        if hit {
+            let _ = res_tx.send(StockfishResult::Info(info.clone()));
+            return SearchControl::Continue;
+        }
+        if interrupted.is_some() {
+            return SearchControl::Stop;
+        }
+        match cmd_rx.try_recv() {
+            Ok(StockfishCmd::Go(position, clock)) if &position == guess && strength.go_params(&declared, clock.clone()) == params => {
+                hit = true;
+                SearchControl::PonderHit
+            }
+            Ok(cmd) => {
+                interrupted = Some(cmd);
+                SearchControl::Stop
+            }
+            Err(TryRecvError::Empty) => SearchControl::Continue,
+            Err(TryRecvError::Disconnected) => {
+                interrupted = Some(StockfishCmd::Stop);
+                SearchControl::Stop
+            }
+        }
+    });
+    if hit { PonderOutcome::Hit(search) } else { PonderOutcome::Missed(interrupted) }
 }
 
-pub enum StockfishOutput { InvalidOutput, ValidOutput(String)}
 impl MyApp{
 
     pub fn start_stockfish(&mut self){
 
-        self.game.stockfish = match Stockfish::new("/usr/bin/stockfish") {
-            
-            Ok(mut s) => {
-                match s.setup_for_new_game(){
-                    Ok(_) => {
-    
-                        match s.set_skill_level(16){
-                            Ok(_) => {
-                                println!("started");
-                                Some(Arc::new(Mutex::new(s)))
-                            }
-                            Err(e) => {None}
-                        }
-                    }
-                    Err(e) => {None}
-                }
-    
-            }
-            Err(_) => { None }
-        };
-        let stockfish: Arc<Mutex<Stockfish>> = match &self.game.stockfish {
-            Some(s) => Arc::clone(s),
-            None => return,
-            
-        };
-        let search_dept = (self.game.search_depth as u32).clone();
-        println!("cloned stockfish");
+        let pool = self.engine_pool.clone();
+        let mut strength = self.game.strength;
+        // the adaptive opponent chooses among several candidate lines
+        let adaptive = self.game.adaptive;
+        // think on the player's time, when the engine can
+        let ponder = self.engines.ponder;
+        let mut blender = adaptive.then(|| MoveBlender::new(self.game.profile.blend_factor()));
         let (cmd_tx, cmd_rx): (Sender<StockfishCmd>, Receiver<StockfishCmd>) =
             mpsc::channel();
-        let (res_tx, res_rx): (Sender<StockfishResult>, Receiver<StockfishResult>) = mpsc::channel(); // assume you stored a Sender for results earlier
+        let (res_tx, res_rx): (Sender<StockfishResult>, Receiver<StockfishResult>) = mpsc::channel();
         self.game.stockfish_rx = Some(res_rx);
-        
+        // the new engine has no search of an earlier game to cancel
+        self.game.cancelling_search = false;
+
                 // … inside your spawn:
                 let _ = thread::Builder::new().name("player_stockfish".to_string()).spawn(move || {
-                    println!("Spawned thread");
-                    let mut engine = stockfish.lock().unwrap();
-                    // make sure engine is fresh
-                    let _ = engine.setup_for_new_game();
-                    engine.set_depth(search_dept);
+                    // held until the game ends, so the opponent keeps its settings and hash
+                    let mut lease: Option<EngineLease> = None;
+                    // a command that interrupted pondering, handled before waiting for the next
+                    let mut pending: Option<StockfishCmd> = None;
                     loop {
-                        match cmd_rx.recv() {
+                        let next = match pending.take() {
+                            Some(cmd) => Ok(cmd),
+                            None => cmd_rx.recv_timeout(HEALTH_INTERVAL),
+                        };
+                        match next {
                             Ok(cmd) => {
                                 match cmd {
                                     StockfishCmd::NewGame => {
-                                        match  engine.setup_for_new_game(){
-                                            Err(e)=> println!("{:?}", e),
-                                            Ok(_) => println!("Setup for new game succesfull"),
+                                        if let Some(engine) = &mut lease {
+                                            let _ = engine.new_game();
+                                        }
+                                        if let Some(blender) = &mut blender {
+                                            blender.reset();
                                         }
                                         let _ = res_tx.send(StockfishResult::Succes);
                                     }
-                                    StockfishCmd::GoDepth(depth) => {
-                                        engine.set_depth(depth as u32);
-                                    }
-                                    StockfishCmd::Go(mv) => {
-                                        // before calling go(), tell Stockfish about the current position (the FEN string in `mv`)
-                                        let _ = engine.set_fen_position(&mv.clone());
-                                        println!("recived go");
-                                        match engine.go() {
-                                            
-                                            Ok(output) => {
-                                                let mv = output.best_move().clone();
-                                                println!("got mv");
-                                                let _ = res_tx.send(StockfishResult::Move(mv));
+                                    StockfishCmd::SetStrength(new_strength) => {
+                                        strength = new_strength;
+                                        if let Some(engine) = &mut lease {
+                                            for (name, value) in strength.options(&engine.options()) {
+                                                let _ = engine.set_option(name, &value);
                                             }
-                                            Err(e) => {
-                                                eprintln!("engine.go() error: {:?}", e);
-                                                let _ = res_tx.send(StockfishResult::Fail);
+                                        }
+                                    }
+                                    StockfishCmd::Go(new_position, clock) => {
+                                        // the engine gets the whole game, not just the current FEN
+                                        let mut position = new_position;
+                                        // the pool already told the user when no engine could be started
+                                        let Some(engine) = game_engine(&pool, &mut lease, strength, adaptive, ponder) else {
+                                            let _ = res_tx.send(StockfishResult::Fail);
+                                            continue;
+                                        };
+                                        let params = strength.go_params(&engine.options(), clock);
+                                        // the UI only sends a command during a search to take the move back or end the game
+                                        let mut interrupted = None;
+                                        let mut search = engine.set_position(&position).and_then(|_| {
+                                            engine.search(&params, &mut |info| {
+                                                if let Ok(cmd) = cmd_rx.try_recv() {
+                                                    interrupted = Some(cmd);
+                                                    return SearchControl::Stop;
+                                                }
+                                                let _ = res_tx.send(StockfishResult::Info(info.clone()));
+                                                SearchControl::Continue
+                                            })
+                                        });
+                                        if interrupted.is_some() {
+                                            pending = interrupted;
+                                            continue;
+                                        }
+                                        // after a ponder hit the search that went on answers the player's move
+                                        loop {
+                                            let output = match search {
+                                                Ok(output) => output,
+                                                // the supervisor already told the user
+                                                Err(_) => {
+                                                    let _ = res_tx.send(StockfishResult::Fail);
+                                                    break;
+                                                }
+                                            };
+                                            let best_move = match &mut blender {
+                                                Some(blender) => {
+                                                    if let Some(accuracy) = blender.player_accuracy(&output) {
+                                                        let _ = res_tx.send(StockfishResult::PlayerAccuracy(accuracy));
+                                                    }
+                                                    blender.pick(&output)
+                                                }
+                                                None => output.best_move.clone(),
+                                            };
+                                            let _ = res_tx.send(StockfishResult::Move(best_move.clone()));
+                                            // the expected reply only fits the engine's own best move
+                                            let expected = output.ponder.filter(|_| ponder && best_move == output.best_move && engine.can_ponder());
+                                            let Some(expected) = expected else { break };
+                                            position.moves.push(best_move);
+                                            position.moves.push(expected);
+                                            match ponder_on(engine, &position, strength, params.clone(), &cmd_rx, &res_tx) {
+                                                PonderOutcome::Hit(result) => search = result,
+                                                PonderOutcome::Missed(cmd) => {
+                                                    pending = cmd;
+                                                    break;
+                                                }
                                             }
                                         }
                                     }
+                                    StockfishCmd::Cancel => {
+                                        let _ = res_tx.send(StockfishResult::Cancelled);
+                                    }
                                     StockfishCmd::Stop => {
-                                        match  engine.setup_for_new_game(){
-                                            Err(e)=> println!("{:?}", e),
-                                            Ok(_) => println!("Setup for new game succesfull"),
-                                        }
+                                        // hand the engine back to the pool
+                                        drop(lease.take());
                                         let _ = res_tx.send(StockfishResult::Succes);
                                         break;
                                     }
-                                    StockfishCmd::Eval =>{
-                                        match engine.go() {
-                                            
-                                            Ok(output) => {
-                                               let evaluation_score = output.eval();
-                                            }
-                                            Err(e) => {
-                                                eprintln!("engine.go() error: {:?}", e);
-                                                let _ = res_tx.send(StockfishResult::Fail);
-                                            }
-                                        }
-                                    }
                                 }
                             }
-                            Err(_) => {
+                            // nothing to do, make sure the engine still answers
+                            Err(RecvTimeoutError::Timeout) => {
+                                if let Some(engine) = &mut lease {
+                                    engine.check_health();
+                                }
+                            }
+                            Err(RecvTimeoutError::Disconnected) => {
                                 // channel closed -> exit
                                 break;
                             }
@@ -123,48 +219,9 @@ impl MyApp{
                         thread::sleep(Duration::from_millis(1));
                     }
                 });
-    
+
         self.game.stockfish_tx = Some(cmd_tx);
-        
-    }
-    pub fn get_stockfish_move(&self) -> Option<String>{
-        let mut uci_move = String::new();
-        
-        match &self.game.stockfish_tx {
-            Some(tx) =>{
-               
-                match tx.send(StockfishCmd::Go(self.board.to_string())) {
-                    Ok(_) =>{}
-                    Err(e) => {
-                        println!("{:?}", e);
-                        return None;
-                    }
-                }
-                
-            } 
-            
-            None => {println!("nu e transmitor stockfish")}
-        }
-    
-        Some(uci_move)
-    }
-    
-    pub fn make_stockfish_move(&mut self) {
-        // ask the engine for a move
-        if let Some(uci_move) = self.get_stockfish_move() {
-            // UCI string is at least 4 chars long, e.g. "e2e4"
-            match self.board.decode_uci_move(uci_move){
-                Some((from, to)) => {
-                    let _ = self.board.move_piece(from, to);
-                }
-                None => {}
-            }
-                
-            }
-    
-            // we're done with this engine move
-            self.game.stockfish_move_pending = false;
-        
+
     }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{engine_registry::UciOption, uci_client::GoParams};
+
+/// Time an engine playing at a limited Elo or skill level gets per move.
+pub const LIMITED_MOVETIME_MS: u64 = 1000;
+
+/// Search depth that plays at roughly `elo`, for engines that cannot limit their own strength.
+pub fn depth_for_elo(elo: u32) -> u32 {
+    (elo.saturating_sub(1000) / 100).clamp(1, 20)
+}
+
+/// Search depth standing in for a Stockfish `Skill Level` from 0 to 20.
+pub fn depth_for_skill(level: u32) -> u32 {
+    level.min(19) + 1
+}
+
+fn declares(declared: &[UciOption], name: &str) -> bool {
+    declared.iter().any(|option| option.name == name)
+}
+
+/// How strong the PvE engine plays.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EngineStrength {
+    /// Target rating through `UCI_LimitStrength` and `UCI_Elo`.
+    Elo(u32),
+    /// Stockfish `Skill Level`, 0 to 20.
+    Skill(u32),
+    /// Full strength, searching to a fixed depth.
+    Depth(u32),
+    /// Full strength, searching a fixed number of nodes.
+    Nodes(u64),
+    /// Full strength, thinking a fixed number of milliseconds per move.
+    MoveTime(u64),
+}
+
+impl Default for EngineStrength {
+    fn default() -> Self {
+        EngineStrength::Elo(1500)
+    }
+}
+
+impl EngineStrength {
+    /// UCI options to send before the game starts, those the engine did not declare left out.
+    /// Options of the other modes are reset so that switching strength between games does
+    /// not leave a limit behind.
+    pub fn options(&self, declared: &[UciOption]) -> Vec<(&'static str, String)> {
+        let options = match self {
+            EngineStrength::Elo(elo) => vec![
+                ("Skill Level", "20".to_string()),
+                ("UCI_LimitStrength", "true".to_string()),
+                ("UCI_Elo", elo.to_string()),
+            ],
+            EngineStrength::Skill(level) => vec![
+                ("UCI_LimitStrength", "false".to_string()),
+                ("Skill Level", level.to_string()),
+            ],
+            _ => vec![
+                ("UCI_LimitStrength", "false".to_string()),
+                ("Skill Level", "20".to_string()),
+            ],
+        };
+        options.into_iter().filter(|(name, _)| declares(declared, name)).collect()
+    }
+
+    /// Search limits for every engine move. A `clock` replaces the strength's own time
+    /// limit. An engine that cannot play at the Elo or skill level itself gets the depth
+    /// standing in for it.
+    pub fn go_params(&self, declared: &[UciOption], clock: Option<GoParams>) -> GoParams {
+        let mut params = clock.unwrap_or_else(|| match *self {
+            EngineStrength::Elo(_) | EngineStrength::Skill(_) => GoParams { movetime: Some(LIMITED_MOVETIME_MS), ..Default::default() },
+            EngineStrength::Depth(depth) => GoParams::depth(depth),
+            EngineStrength::Nodes(nodes) => GoParams { nodes: Some(nodes), ..Default::default() },
+            EngineStrength::MoveTime(ms) => GoParams { movetime: Some(ms), ..Default::default() },
+        });
+        let fallback = match *self {
+            EngineStrength::Elo(elo) if !(declares(declared, "UCI_LimitStrength") && declares(declared, "UCI_Elo")) => Some(depth_for_elo(elo)),
+            EngineStrength::Skill(level) if level < 20 && !declares(declared, "Skill Level") => Some(depth_for_skill(level)),
+            _ => None,
+        };
+        if let Some(depth) = fallback {
+            params.depth = Some(params.depth.map_or(depth, |limit| limit.min(depth)));
+        }
+        params
+    }
+
+    /// Rating to record for the engine, 0 when the setting has no known rating.
+    /// Skill levels use the inverse of Stockfish's own Elo to skill mapping.
+    pub fn elo(&self) -> u32 {
+        match *self {
+            EngineStrength::Elo(elo) => elo,
+            EngineStrength::Skill(level) if level < 20 => (1346.6 + 143.4 * (level as f64).powf(0.806)).round() as u32,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for EngineStrength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineStrength::Elo(elo) => write!(f, "Elo {}", elo),
+            EngineStrength::Skill(level) => write!(f, "Skill {}", level),
+            EngineStrength::Depth(depth) => write!(f, "Depth {}", depth),
+            EngineStrength::Nodes(nodes) => write!(f, "{} nodes", nodes),
+            EngineStrength::MoveTime(ms) => write!(f, "{:.1} s/move", *ms as f64 / 1000.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::engine_registry::OptionKind;
+
+    fn spin(name: &str) -> UciOption {
+        UciOption { name: name.to_string(), kind: OptionKind::Spin { min: 0, max: 4000 }, default: "0".to_string() }
+    }
+
+    #[test]
+    fn engine_with_the_options_limits_itself() {
+        let declared = [spin("Skill Level"), spin("UCI_Elo"), UciOption { name: "UCI_LimitStrength".to_string(), kind: OptionKind::Check, default: "false".to_string() }];
+        let strength = EngineStrength::Elo(1800);
+        assert_eq!(strength.options(&declared).len(), 3);
+        assert_eq!(strength.go_params(&declared, None).depth, None);
+    }
+
+    #[test]
+    fn engine_without_the_options_gets_a_depth_limit() {
+        let declared = [spin("Hash")];
+        assert!(EngineStrength::Elo(1800).options(&declared).is_empty());
+        let params = EngineStrength::Elo(1800).go_params(&declared, None);
+        assert_eq!((params.depth, params.movetime), (Some(depth_for_elo(1800)), Some(LIMITED_MOVETIME_MS)));
+
+        // a clock takes over the time limit but the depth still holds the engine back
+        let clock = GoParams { wtime: Some(60_000), btime: Some(60_000), ..Default::default() };
+        let params = EngineStrength::Skill(3).go_params(&declared, Some(clock));
+        assert_eq!((params.depth, params.movetime, params.wtime), (Some(depth_for_skill(3)), None, Some(60_000)));
+
+        assert_eq!(EngineStrength::Skill(20).go_params(&declared, None).depth, None);
+        assert_eq!(EngineStrength::Nodes(5000).go_params(&declared, None).nodes, Some(5000));
+    }
+}
@@ -0,0 +1,207 @@
+use std::{sync::mpsc::Sender, time::Duration};
+
+use crate::{engine::uci::UciPosition, game::{chess_engine::{ChessEngine, Protocol}, engine_registry::{EngineConfig, UciOption}, uci_client::{EngineError, GoParams, SearchControl, SearchInfo, SearchResult}}};
+
+/// How long an idle engine gets to answer a health check.
+pub const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often worker threads check an engine that has nothing to do.
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+/// Time a search may take past its own limit before the engine counts as hung.
+const SEARCH_GRACE: Duration = Duration::from_secs(10);
+/// Longest a search without a time limit may run.
+const UNTIMED_SEARCH_LIMIT: Duration = Duration::from_secs(120);
+/// Failures in a row after which the engine is no longer restarted.
+const MAX_RESTARTS: u32 = 3;
+
+/// Time after which a search with `params` counts as hung. A ponder search only starts
+/// counting at the ponderhit.
+pub fn search_timeout(params: &GoParams) -> Option<Duration> {
+    if params.infinite {
+        return None;
+    }
+    let clock = params.movetime.or(params.wtime.max(params.btime));
+    Some(clock.map_or(UNTIMED_SEARCH_LIMIT, |ms| Duration::from_millis(ms) + SEARCH_GRACE))
+}
+
+/// Owns an engine process for a worker thread: checks that it answers, restarts it when it
+/// crashes or hangs, and replays the options and position it had. Failures are reported on
+/// `errors` so the UI can show them.
+pub struct EngineSupervisor {
+    config: EngineConfig,
+    /// Options sent after the saved values on every start, such as strength settings.
+    setup: Vec<(String, String)>,
+    engine: Option<Box<dyn ChessEngine>>,
+    position: Option<UciPosition>,
+    failures: u32,
+    errors: Sender<String>,
+}
+
+impl EngineSupervisor {
+    pub fn new(config: EngineConfig, errors: Sender<String>) -> Self {
+        Self { config, setup: Vec::new(), engine: None, position: None, failures: 0, errors }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Whether the engine failed too often to be restarted again.
+    pub fn gave_up(&self) -> bool {
+        self.failures >= MAX_RESTARTS
+    }
+
+    /// Whether options were set on top of the engine's saved values.
+    pub fn is_customized(&self) -> bool {
+        !self.setup.is_empty()
+    }
+
+    /// Options the engine declared, or those saved for it while it is not running.
+    pub fn options(&self) -> Vec<UciOption> {
+        match &self.engine {
+            Some(engine) => engine.options(),
+            None => self.config.options.clone(),
+        }
+    }
+
+    /// Whether the engine can think on the opponent's time: a UCI engine that declared the
+    /// `Ponder` option.
+    pub fn can_ponder(&self) -> bool {
+        self.config.protocol == Protocol::Uci
+            && self.engine.as_ref().is_some_and(|engine| engine.options().iter().any(|option| option.name == "Ponder"))
+    }
+
+    /// Starts the engine process with its saved values, the setup options and the last position.
+    pub fn start(&mut self) -> Result<(), EngineError> {
+        let mut engine = self.config.spawn()?;
+        for (name, value) in &self.setup {
+            engine.set_option(name, Some(value))?;
+        }
+        engine.new_game()?;
+        if let Some(position) = &self.position {
+            engine.set_position(position)?;
+        }
+        self.engine = Some(engine);
+        Ok(())
+    }
+
+    /// Sets an option now and again after every restart.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), EngineError> {
+        match self.setup.iter_mut().find(|(n, _)| n == name) {
+            Some(option) => option.1 = value.to_string(),
+            None => self.setup.push((name.to_string(), value.to_string())),
+        }
+        if self.engine.is_none() {
+            return Ok(());
+        }
+        self.run(|engine| engine.set_option(name, Some(value)))
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineError> {
+        self.position = None;
+        self.run(|engine| engine.new_game())
+    }
+
+    pub fn set_position(&mut self, position: &UciPosition) -> Result<(), EngineError> {
+        self.position = Some(position.clone());
+        self.run(|engine| engine.set_position(position))
+    }
+
+    /// Searches the last position set. A crashed or hung engine is restarted and the search
+    /// tried once more.
+    pub fn search(&mut self, params: &GoParams, on_info: &mut dyn FnMut(&SearchInfo) -> SearchControl) -> Result<SearchResult, EngineError> {
+        let timeout = search_timeout(params);
+        let result = self.run(|engine| engine.search(params, timeout, on_info));
+        if result.is_ok() {
+            self.failures = 0;
+        }
+        result
+    }
+
+    /// Sends `isready` (or `ping`) to an idle engine and restarts it when it does not answer.
+    pub fn check_health(&mut self) {
+        if self.engine.is_none() {
+            if !self.gave_up() {
+                self.restart();
+            }
+            return;
+        }
+        let healthy = self.engine.as_mut().is_some_and(|engine| engine.is_alive() && engine.is_ready(HEALTH_TIMEOUT).is_ok());
+        if !healthy {
+            self.failed(&EngineError::Timeout("the health check"));
+        }
+    }
+
+    /// Shuts the engine process down; a later command starts it again.
+    pub fn quit(&mut self) {
+        if let Some(mut engine) = self.engine.take() {
+            engine.quit();
+        }
+    }
+
+    /// Runs `action` on the engine, restarting it and retrying once when the engine is gone
+    /// or stops answering. Refused commands are reported but not retried.
+    fn run<T>(&mut self, mut action: impl FnMut(&mut Box<dyn ChessEngine>) -> Result<T, EngineError>) -> Result<T, EngineError> {
+        for attempt in 0..2 {
+            if self.engine.is_none() && (attempt > 0 || !self.restart()) {
+                return Err(EngineError::Exited);
+            }
+            let Some(engine) = self.engine.as_mut() else { return Err(EngineError::Exited) };
+            match action(engine) {
+                Err(e @ (EngineError::Io(_) | EngineError::Exited | EngineError::Timeout(_))) => {
+                    self.failed(&e);
+                    if attempt > 0 {
+                        return Err(e);
+                    }
+                }
+                Err(e) => {
+                    self.report(format!("{}: {}", self.config.name, e));
+                    return Err(e);
+                }
+                result => return result,
+            }
+        }
+        Err(EngineError::Exited)
+    }
+
+    /// Kills the engine after a failure and starts a new one, unless it failed too often.
+    fn failed(&mut self, error: &EngineError) {
+        if let Some(mut engine) = self.engine.take() {
+            engine.quit();
+        }
+        self.failures += 1;
+        if self.gave_up() {
+            self.report(format!("{} stopped working ({}) and will not be restarted. Pick another engine in Engines.", self.config.name, error));
+            return;
+        }
+        self.report(format!("{} stopped working ({}), restarting it.", self.config.name, error));
+        // the failure is already counted
+        self.start_again();
+    }
+
+    /// Starts the engine again, counting a start that fails.
+    fn restart(&mut self) -> bool {
+        if self.gave_up() {
+            return false;
+        }
+        let started = self.start_again();
+        if !started {
+            self.failures += 1;
+        }
+        started
+    }
+
+    fn start_again(&mut self) -> bool {
+        match self.start() {
+            Ok(()) => true,
+            Err(e) => {
+                self.engine = None;
+                self.report(format!("{} could not be restarted: {}", self.config.name, e));
+                false
+            }
+        }
+    }
+
+    fn report(&self, message: String) {
+        let _ = self.errors.send(message);
+    }
+}
@@ -0,0 +1,620 @@
+use std::{fmt, io::{BufRead, BufReader, Write}, process::{Child, ChildStdin, Command, Stdio}, sync::mpsc::{self, Receiver, RecvTimeoutError}, thread, time::{Duration, Instant}};
+
+use crate::{engine::uci::UciPosition, game::{chess_engine::ChessEngine, engine_registry::UciOption}};
+
+/// How long the engine gets to answer `uci` and `isready`.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum EngineError {
+    Spawn(std::io::Error),
+    Io(std::io::Error),
+    Timeout(&'static str),
+    Exited,
+    /// The engine lacks a feature the request needs.
+    Unsupported(&'static str),
+    /// The engine answered with an error, e.g. an illegal move.
+    Rejected(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Spawn(e) => write!(f, "could not start engine: {}", e),
+            EngineError::Io(e) => write!(f, "engine communication failed: {}", e),
+            EngineError::Timeout(waiting_for) => write!(f, "engine did not answer {} in time", waiting_for),
+            EngineError::Exited => write!(f, "engine process exited"),
+            EngineError::Unsupported(feature) => write!(f, "engine does not support {}", feature),
+            EngineError::Rejected(line) => write!(f, "engine refused: {}", line),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// How often a search callback is called while the engine is silent, so that a search
+/// can be stopped even when no info lines arrive.
+pub const SEARCH_POLL: Duration = Duration::from_millis(100);
+
+/// What a search callback wants the engine to do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchControl {
+    Continue,
+    /// Stop searching and answer with the best move found so far.
+    Stop,
+    /// The opponent played the move a ponder search expected; search on with the clock running.
+    PonderHit,
+}
+
+/// Score reported in an `info` line, from the side to move's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfoScore {
+    Cp(i32),
+    Mate(i32),
+}
+
+impl InfoScore {
+    /// Centipawns, or the signed number of moves to mate.
+    pub fn value(&self) -> i32 {
+        match self {
+            InfoScore::Cp(cp) => *cp,
+            InfoScore::Mate(moves) => *moves,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScoreBound {
+    #[default]
+    Exact,
+    Lower,
+    Upper,
+}
+
+/// Everything an engine reports in one `info` line. Fields the engine left out stay `None`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<InfoScore>,
+    pub bound: ScoreBound,
+    /// Win/draw/loss in permille, sent when `UCI_ShowWDL` is on.
+    pub wdl: Option<(u32, u32, u32)>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub hashfull: Option<u32>,
+    pub tbhits: Option<u64>,
+    pub time_ms: Option<u64>,
+    pub pv: Vec<String>,
+    pub string: Option<String>,
+}
+
+impl SearchInfo {
+    /// Parses the part of an engine line after `info`.
+    pub fn parse(rest: &str) -> SearchInfo {
+        let mut info = SearchInfo::default();
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        let mut i = 0;
+        let number = |i: usize| tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok());
+        while i < tokens.len() {
+            match tokens[i] {
+                "depth" => { info.depth = number(i).map(|n| n as u32); i += 2; }
+                "seldepth" => { info.seldepth = number(i).map(|n| n as u32); i += 2; }
+                "multipv" => { info.multipv = number(i).map(|n| n as u32); i += 2; }
+                "nodes" => { info.nodes = number(i); i += 2; }
+                "nps" => { info.nps = number(i); i += 2; }
+                "hashfull" => { info.hashfull = number(i).map(|n| n as u32); i += 2; }
+                "tbhits" => { info.tbhits = number(i); i += 2; }
+                "time" => { info.time_ms = number(i); i += 2; }
+                "score" => {
+                    let value = tokens.get(i + 2).and_then(|t| t.parse::<i32>().ok());
+                    info.score = match (tokens.get(i + 1), value) {
+                        (Some(&"cp"), Some(v)) => Some(InfoScore::Cp(v)),
+                        (Some(&"mate"), Some(v)) => Some(InfoScore::Mate(v)),
+                        _ => None,
+                    };
+                    i += 3;
+                    match tokens.get(i) {
+                        Some(&"lowerbound") => { info.bound = ScoreBound::Lower; i += 1; }
+                        Some(&"upperbound") => { info.bound = ScoreBound::Upper; i += 1; }
+                        _ => {}
+                    }
+                }
+                "wdl" => {
+                    let part = |k: usize| tokens.get(i + k).and_then(|t| t.parse::<u32>().ok());
+                    if let (Some(w), Some(d), Some(l)) = (part(1), part(2), part(3)) {
+                        info.wdl = Some((w, d, l));
+                    }
+                    i += 4;
+                }
+                "pv" => {
+                    // the pv runs to the end of the line
+                    info.pv = tokens[i + 1..].iter().map(|t| t.to_string()).collect();
+                    break;
+                }
+                "string" => {
+                    info.string = Some(tokens[i + 1..].join(" "));
+                    break;
+                }
+                _ => i += 1,
+            }
+        }
+        info
+    }
+}
+
+/// A line received from the engine.
+#[derive(Clone, Debug)]
+pub enum UciEvent {
+    Info(SearchInfo),
+    BestMove { best: String, ponder: Option<String> },
+    ReadyOk,
+    UciOk,
+    IdName(String),
+    IdAuthor(String),
+    /// An `option name ...` declaration, kept verbatim after `option`.
+    Option(String),
+    Other(String),
+}
+
+impl UciEvent {
+    pub fn parse(line: &str) -> UciEvent {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match command {
+            "info" => UciEvent::Info(SearchInfo::parse(rest)),
+            "bestmove" => {
+                let mut tokens = rest.split_whitespace();
+                let best = tokens.next().unwrap_or("(none)").to_string();
+                let ponder = match (tokens.next(), tokens.next()) {
+                    (Some("ponder"), Some(mv)) => Some(mv.to_string()),
+                    _ => None,
+                };
+                UciEvent::BestMove { best, ponder }
+            }
+            "readyok" => UciEvent::ReadyOk,
+            "uciok" => UciEvent::UciOk,
+            "id" => match rest.split_once(char::is_whitespace) {
+                Some(("name", name)) => UciEvent::IdName(name.trim().to_string()),
+                Some(("author", author)) => UciEvent::IdAuthor(author.trim().to_string()),
+                _ => UciEvent::Other(line.to_string()),
+            },
+            "option" => UciEvent::Option(rest.to_string()),
+            _ => UciEvent::Other(line.to_string()),
+        }
+    }
+}
+
+/// Limits for a `go` command. Everything left at `None` is not sent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GoParams {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+    pub ponder: bool,
+    pub searchmoves: Vec<String>,
+}
+
+impl GoParams {
+    pub fn depth(depth: u32) -> Self {
+        Self { depth: Some(depth), ..Default::default() }
+    }
+
+    pub fn to_command(&self) -> String {
+        let mut command = String::from("go");
+        if self.ponder {
+            command += " ponder";
+        }
+        let limits = [
+            ("wtime", self.wtime),
+            ("btime", self.btime),
+            ("winc", self.winc),
+            ("binc", self.binc),
+            ("movestogo", self.movestogo.map(u64::from)),
+            ("depth", self.depth.map(u64::from)),
+            ("nodes", self.nodes),
+            ("movetime", self.movetime),
+        ];
+        for (name, value) in limits {
+            if let Some(value) = value {
+                command += &format!(" {} {}", name, value);
+            }
+        }
+        if self.infinite {
+            command += " infinite";
+        }
+        if !self.searchmoves.is_empty() {
+            command += " searchmoves ";
+            command += &self.searchmoves.join(" ");
+        }
+        command
+    }
+}
+
+/// Result of a finished search: the move and the last info line seen for every multipv slot.
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: String,
+    pub ponder: Option<String>,
+    pub lines: Vec<SearchInfo>,
+}
+
+impl SearchResult {
+    /// Info of the principal line (multipv 1).
+    pub fn main_line(&self) -> Option<&SearchInfo> {
+        self.lines.first()
+    }
+}
+
+/// Keeps the latest scored info per multipv slot while a search runs.
+pub fn record_line(lines: &mut Vec<SearchInfo>, info: &SearchInfo) {
+    if info.score.is_none() || info.pv.is_empty() {
+        return;
+    }
+    let slot = info.multipv.unwrap_or(1).max(1) as usize - 1;
+    if lines.len() <= slot {
+        lines.resize(slot + 1, SearchInfo::default());
+    }
+    lines[slot] = info.clone();
+}
+
+/// A UCI engine running as a child process. Engine output is read on a background thread
+/// and handed out as [`UciEvent`]s.
+pub struct UciClient {
+    pub name: String,
+    pub author: String,
+    /// Raw `option` declarations from the handshake.
+    pub option_lines: Vec<String>,
+    child: Child,
+    stdin: ChildStdin,
+    events: Receiver<UciEvent>,
+}
+
+impl UciClient {
+    /// Starts the engine at `path` and runs the uci/isready handshake.
+    pub fn spawn(path: &str, args: &[String]) -> Result<Self, EngineError> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(EngineError::Spawn)?;
+        let stdin = child.stdin.take().ok_or(EngineError::Exited)?;
+        let stdout = child.stdout.take().ok_or(EngineError::Exited)?;
+        let (tx, rx) = mpsc::channel();
+        let _ = thread::Builder::new().name("uci_reader".to_string()).spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if tx.send(UciEvent::parse(&line)).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut client = UciClient {
+            name: path.to_string(),
+            author: String::new(),
+            option_lines: Vec::new(),
+            child,
+            stdin,
+            events: rx,
+        };
+        client.handshake()?;
+        Ok(client)
+    }
+
+    fn handshake(&mut self) -> Result<(), EngineError> {
+        self.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            match self.recv_until(deadline, "uciok")? {
+                UciEvent::UciOk => break,
+                UciEvent::IdName(name) => self.name = name,
+                UciEvent::IdAuthor(author) => self.author = author,
+                UciEvent::Option(option) => self.option_lines.push(option),
+                _ => {}
+            }
+        }
+        self.is_ready(HANDSHAKE_TIMEOUT)
+    }
+
+    fn recv_until(&self, deadline: Instant, waiting_for: &'static str) -> Result<UciEvent, EngineError> {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match self.events.recv_timeout(left) {
+            Ok(event) => Ok(event),
+            Err(RecvTimeoutError::Timeout) => Err(EngineError::Timeout(waiting_for)),
+            Err(RecvTimeoutError::Disconnected) => Err(EngineError::Exited),
+        }
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{}", command).map_err(EngineError::Io)?;
+        self.stdin.flush().map_err(EngineError::Io)
+    }
+
+    /// Sends `isready` and waits for `readyok`. Anything the engine prints before that is dropped,
+    /// so only call this while no search is running.
+    pub fn is_ready(&mut self, timeout: Duration) -> Result<(), EngineError> {
+        self.send("isready")?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let UciEvent::ReadyOk = self.recv_until(deadline, "isready")? {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), EngineError> {
+        match value {
+            Some(value) => self.send(&format!("setoption name {} value {}", name, value)),
+            None => self.send(&format!("setoption name {}", name)),
+        }
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineError> {
+        self.send("ucinewgame")?;
+        self.is_ready(HANDSHAKE_TIMEOUT)
+    }
+
+    /// Sends the position as a start position plus the moves played from it, so the engine
+    /// sees the game history (repetitions, fifty-move rule).
+    pub fn set_position(&mut self, position: &UciPosition) -> Result<(), EngineError> {
+        let mut command = match &position.start_fen {
+            Some(fen) => format!("position fen {}", fen),
+            None => String::from("position startpos"),
+        };
+        if !position.moves.is_empty() {
+            command += " moves ";
+            command += &position.moves.join(" ");
+        }
+        self.send(&command)
+    }
+
+    pub fn go(&mut self, params: &GoParams) -> Result<(), EngineError> {
+        self.send(&params.to_command())
+    }
+
+    pub fn stop(&mut self) -> Result<(), EngineError> {
+        self.send("stop")
+    }
+
+    pub fn ponder_hit(&mut self) -> Result<(), EngineError> {
+        self.send("ponderhit")
+    }
+
+    /// Next engine event if one is waiting.
+    pub fn try_event(&self) -> Result<Option<UciEvent>, EngineError> {
+        match self.events.try_recv() {
+            Ok(event) => Ok(Some(event)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(EngineError::Exited),
+        }
+    }
+
+    pub fn recv_event(&self, timeout: Duration) -> Result<UciEvent, EngineError> {
+        self.recv_until(Instant::now() + timeout, "the search")
+    }
+
+    /// Runs `go` and blocks until `bestmove`, handing every info line to `on_info`, which can
+    /// stop the search early or turn a ponder search into a normal one. While the engine
+    /// prints nothing `on_info` gets an empty info every [`SEARCH_POLL`]. `timeout` bounds the
+    /// whole search, for a ponder search from the ponderhit on; use `None` for searches that
+    /// end on their own or are stopped by `on_info`.
+    pub fn search(&mut self, params: &GoParams, timeout: Option<Duration>, mut on_info: impl FnMut(&SearchInfo) -> SearchControl) -> Result<SearchResult, EngineError> {
+        self.go(params)?;
+        let mut stopping = false;
+        let mut pondering = params.ponder;
+        let mut deadline = if pondering { None } else { timeout.map(|t| Instant::now() + t) };
+        let mut result = SearchResult::default();
+        loop {
+            let wait = deadline.map_or(SEARCH_POLL, |deadline| deadline.saturating_duration_since(Instant::now()).min(SEARCH_POLL));
+            let info = match self.events.recv_timeout(wait) {
+                Ok(UciEvent::Info(info)) => {
+                    record_line(&mut result.lines, &info);
+                    info
+                }
+                Ok(UciEvent::BestMove { best, ponder }) => {
+                    result.best_move = best;
+                    result.ponder = ponder;
+                    return Ok(result);
+                }
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    return Err(EngineError::Timeout("the search"));
+                }
+                // let the caller stop an engine that has gone quiet
+                Err(RecvTimeoutError::Timeout) => SearchInfo::default(),
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Exited),
+            };
+            match on_info(&info) {
+                SearchControl::Stop if !stopping => {
+                    stopping = true;
+                    self.stop()?;
+                }
+                // the ponder search goes on as a normal one, which has to finish in time
+                SearchControl::PonderHit if pondering => {
+                    pondering = false;
+                    self.ponder_hit()?;
+                    deadline = timeout.map(|t| Instant::now() + t);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the engine process is still running.
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Asks the engine to quit, killing it if it does not exit promptly.
+    pub fn quit(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for UciClient {
+    fn drop(&mut self) {
+        self.quit();
+    }
+}
+
+impl ChessEngine for UciClient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn options(&self) -> Vec<UciOption> {
+        self.option_lines.iter().filter_map(|line| UciOption::parse(line)).collect()
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), EngineError> {
+        UciClient::set_option(self, name, value)
+    }
+
+    fn new_game(&mut self) -> Result<(), EngineError> {
+        UciClient::new_game(self)
+    }
+
+    fn set_position(&mut self, position: &UciPosition) -> Result<(), EngineError> {
+        UciClient::set_position(self, position)
+    }
+
+    fn search(&mut self, params: &GoParams, timeout: Option<Duration>, on_info: &mut dyn FnMut(&SearchInfo) -> SearchControl) -> Result<SearchResult, EngineError> {
+        UciClient::search(self, params, timeout, on_info)
+    }
+
+    fn stop(&mut self) -> Result<(), EngineError> {
+        UciClient::stop(self)
+    }
+
+    fn is_ready(&mut self, timeout: Duration) -> Result<(), EngineError> {
+        UciClient::is_ready(self, timeout)
+    }
+
+    fn is_alive(&mut self) -> bool {
+        UciClient::is_alive(self)
+    }
+
+    fn quit(&mut self) {
+        UciClient::quit(self)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    /// A UCI engine in a shell script: it logs every command it gets and answers with canned
+    /// lines.
+    const FAKE_ENGINE: &str = r#"
+while read -r line; do
+    echo "$line" >> "$1"
+    case "$line" in
+        uci)
+            echo "id name Fake Engine 1.0"
+            echo "id author Koch"
+            echo "option name Hash type spin default 16 min 1 max 1024"
+            echo "uciok" ;;
+        isready) echo "readyok" ;;
+        go*)
+            echo "info depth 1 score cp 10 pv e7e5"
+            echo "info depth 12 seldepth 18 multipv 1 score cp 34 wdl 420 480 100 nodes 123456 nps 1000000 hashfull 12 tbhits 0 time 123 pv e7e5 g1f3 b8c6"
+            echo "info depth 12 seldepth 15 multipv 2 score mate -3 upperbound pv c7c5"
+            echo "bestmove e7e5 ponder g1f3" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    /// Starts the fake engine in a folder of its own; returns it with the file it logs to.
+    fn fake_engine(test: &str) -> (UciClient, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("koch-fake-uci-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("engine.sh");
+        let log = dir.join("commands.log");
+        fs::write(&script, FAKE_ENGINE).unwrap();
+        let _ = fs::remove_file(&log);
+        let args = [script.display().to_string(), log.display().to_string()];
+        (UciClient::spawn("sh", &args).unwrap(), log)
+    }
+
+    fn sent_commands(mut client: UciClient, log: &PathBuf) -> Vec<String> {
+        client.quit();
+        fs::read_to_string(log).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn handshake_reads_id_and_options() {
+        let (client, log) = fake_engine("handshake");
+        assert_eq!(client.name, "Fake Engine 1.0");
+        assert_eq!(client.author, "Koch");
+        assert_eq!(client.option_lines, ["name Hash type spin default 16 min 1 max 1024"]);
+        assert_eq!(sent_commands(client, &log)[..2], ["uci", "isready"]);
+    }
+
+    #[test]
+    fn search_sends_the_game_and_reads_the_lines() {
+        let (mut client, log) = fake_engine("search");
+        let position = UciPosition { start_fen: None, moves: vec!["e2e4".to_string()] };
+        client.set_position(&position).unwrap();
+        let mut infos = 0;
+        let result = client
+            .search(&GoParams::depth(12), Some(Duration::from_secs(10)), |info| {
+                infos += usize::from(info.depth.is_some());
+                SearchControl::Continue
+            })
+            .unwrap();
+        assert_eq!(infos, 3);
+        assert_eq!(result.best_move, "e7e5");
+        assert_eq!(result.ponder.as_deref(), Some("g1f3"));
+        assert_eq!(result.lines.len(), 2);
+
+        let main = result.main_line().unwrap();
+        assert_eq!((main.depth, main.seldepth, main.multipv), (Some(12), Some(18), Some(1)));
+        assert_eq!(main.score, Some(InfoScore::Cp(34)));
+        assert_eq!(main.bound, ScoreBound::Exact);
+        assert_eq!(main.wdl, Some((420, 480, 100)));
+        assert_eq!((main.nodes, main.nps, main.hashfull, main.tbhits, main.time_ms), (Some(123456), Some(1_000_000), Some(12), Some(0), Some(123)));
+        assert_eq!(main.pv, ["e7e5", "g1f3", "b8c6"]);
+
+        let second = &result.lines[1];
+        assert_eq!(second.multipv, Some(2));
+        assert_eq!(second.score, Some(InfoScore::Mate(-3)));
+        assert_eq!(second.bound, ScoreBound::Upper);
+        assert_eq!(second.pv, ["c7c5"]);
+
+        let commands = sent_commands(client, &log);
+        assert!(commands.contains(&"position startpos moves e2e4".to_string()), "{:?}", commands);
+        assert!(commands.contains(&"go depth 12".to_string()), "{:?}", commands);
+    }
+
+    #[test]
+    fn info_string_keeps_the_rest_of_the_line() {
+        let info = SearchInfo::parse("depth 3 string NNUE evaluation enabled");
+        assert_eq!(info.depth, Some(3));
+        assert_eq!(info.string.as_deref(), Some("NNUE evaluation enabled"));
+    }
+}
@@ -0,0 +1,524 @@
+use std::fs;
+
+use eframe::egui::{self, pos2, vec2, CentralPanel, DragValue, Pos2, Rect, RichText, ScrollArea, Sense, SidePanel, TextEdit, Ui};
+
+use crate::{engine::{board::BoardMetaData, fen::fen_parser, game_tree::GameTree, pgn::{parse_pgn, PgnGame}, san::square_name, uci::UciPosition, Board, PieceColor, PieceType}, etc::DEFAULT_FEN, game::analysis::{AnalysisEvent, AnalysisLine, AnalysisRequest, Analyzer, SearchStats, DEFAULT_LINES, MAX_LINES}, ui::app::{AppScreen, MyApp}};
+
+/// The engine analysis shown on the "Analysis" screen, or next to the game when
+/// `follow_game` is set.
+pub struct AnalysisState {
+    /// FEN of the position to analyze, or the PGN of a game.
+    pub fen: String,
+    pub lines_wanted: u32,
+    /// Search until stopped instead of to a fixed depth.
+    pub infinite: bool,
+    /// Analyze the game board, restarting whenever its position changes.
+    pub follow_game: bool,
+    /// Board the position is explored on, with the moves tried as a tree: a loaded game or
+    /// just the start position. The position analyzed is the current node of its tree.
+    game: Board,
+    /// Side the board is seen from.
+    pov: PieceColor,
+    /// Piece picked to move on the board.
+    selected: Option<(u8, u8)>,
+    /// Piece a pawn reaching the last rank becomes.
+    promotion: PieceType,
+    /// FEN or PGN file to load.
+    pub path: String,
+    /// Position being analyzed.
+    position: UciPosition,
+    board: Board,
+    lines: Vec<AnalysisLine>,
+    stats: SearchStats,
+    /// Line and move shown on the board instead of the analyzed position.
+    preview: Option<(usize, usize)>,
+    /// Started the first time something is analyzed.
+    analyzer: Option<Analyzer>,
+    running: bool,
+    status: String,
+}
+
+impl Default for AnalysisState {
+    fn default() -> Self {
+        Self {
+            fen: DEFAULT_FEN.to_string(),
+            lines_wanted: DEFAULT_LINES,
+            infinite: false,
+            follow_game: false,
+            game: Board::from(&DEFAULT_FEN.to_string()),
+            pov: PieceColor::White,
+            selected: None,
+            promotion: PieceType::Queen,
+            path: String::new(),
+            position: UciPosition { start_fen: None, moves: Vec::new() },
+            board: Board::from(&DEFAULT_FEN.to_string()),
+            lines: Vec::new(),
+            stats: SearchStats::default(),
+            preview: None,
+            analyzer: None,
+            running: false,
+            status: String::new(),
+        }
+    }
+}
+
+impl AnalysisState {
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(analyzer) = &mut self.analyzer {
+            analyzer.stop();
+        }
+        self.running = false;
+    }
+
+    /// Best line found so far.
+    pub fn best_line(&self) -> Option<&AnalysisLine> {
+        self.lines.first()
+    }
+
+    fn handle_event(&mut self, event: AnalysisEvent) {
+        match event {
+            AnalysisEvent::Line(line) => {
+                let slot = line.multipv.max(1) as usize - 1;
+                if self.lines.len() <= slot {
+                    self.lines.resize(slot + 1, line.clone());
+                }
+                // a changed line makes a preview into it meaningless
+                if self.preview.is_some_and(|(previewed, _)| previewed == slot && self.lines[slot].uci != line.uci) {
+                    self.preview = None;
+                }
+                self.lines[slot] = line;
+            }
+            AnalysisEvent::Stats(stats) => {
+                self.stats = stats;
+            }
+            AnalysisEvent::Finished => {
+                self.running = false;
+            }
+        }
+    }
+
+    /// The board with the first `ply + 1` moves of line `line` played.
+    fn preview_board(&self, line: usize, ply: usize) -> Option<Board> {
+        let mut position = self.position.clone();
+        position.moves.extend_from_slice(self.lines.get(line)?.uci.get(..=ply)?);
+        Some(position.board())
+    }
+}
+
+/// The line starting with the move into `first` as clickable moves, with the alternatives
+/// to each move in parentheses after it.
+fn tree_line_labels(ui: &mut Ui, game: &BoardMetaData, first: usize, clicked: &mut Option<usize>) {
+    let tree = &game.moves;
+    // a black move is numbered at the start of a line and after a variation
+    let mut numbered = false;
+    let line = std::iter::once(first).chain(tree.mainline_from(first));
+    for (ply, id) in (tree.path(first).len() - 1..).zip(line) {
+        let record = &tree.node(id).record;
+        let white = game.mover(ply) == PieceColor::White;
+        if white || !numbered {
+            ui.label(format!("{}{}", record.move_number, if white { "." } else { "..." }));
+        }
+        numbered = true;
+        if ui.selectable_label(tree.current() == id, &record.san).clicked() {
+            *clicked = Some(id);
+        }
+        for variation in tree.variations(id) {
+            ui.weak("(");
+            tree_line_labels(ui, game, *variation, clicked);
+            ui.weak(")");
+            numbered = false;
+        }
+    }
+}
+
+/// Node counts and the like in a short form, e.g. "12.3M".
+fn short_count(n: u64) -> String {
+    match n {
+        0..1_000 => n.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", n as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1}M", n as f64 / 1e6),
+        _ => format!("{:.1}G", n as f64 / 1e9),
+    }
+}
+
+impl MyApp {
+    /// Opens the analysis screen on the current game, with its moves to explore from the
+    /// position on the board.
+    pub fn open_analysis(&mut self) {
+        self.analyze_game(self.board.meta_data.clone());
+    }
+
+    /// Opens the analysis screen on the current position of `game`, with its moves and
+    /// variations to step through.
+    pub fn analyze_game(&mut self, game: BoardMetaData) {
+        let mut board = Board::from(&game.starting_position);
+        let current = game.moves.current();
+        board.meta_data = game;
+        board.goto(current);
+        board.state.pov = self.analysis.pov;
+        self.analysis.game = board;
+        self.analysis.follow_game = false;
+        self.screen = AppScreen::Analyze;
+        self.analyze_game_position();
+    }
+
+    /// Analyzes the current position of the analysis board.
+    fn analyze_game_position(&mut self) {
+        let position = self.analysis.game.uci_position();
+        self.analysis.fen = self.analysis.game.to_string();
+        self.analysis.selected = None;
+        self.analyze_position(position);
+    }
+
+    /// Shows node `id` of the analysis board's game and analyzes it.
+    fn goto_analysis_node(&mut self, id: usize) {
+        self.analysis.game.goto(id);
+        self.analyze_game_position();
+    }
+
+    /// Analyzes the position in the FEN field, replacing any running analysis. A new FEN
+    /// starts a new game on the analysis board and a PGN loads its first game; the position
+    /// already on the board is analyzed again with its moves kept.
+    fn start_analysis(&mut self) {
+        let text = self.analysis.fen.trim().to_string();
+        if text == self.analysis.game.to_string() {
+            self.analyze_game_position();
+            return;
+        }
+        match fen_parser(&text) {
+            Ok(board) => {
+                self.analyze_game(BoardMetaData { starting_position: board.to_string(), ..BoardMetaData::default() });
+            }
+            Err(e) => match parse_pgn(&text) {
+                (games, errors) if !games.is_empty() => {
+                    self.analyze_game(games[0].to_board().meta_data);
+                    if let Some(error) = errors.first() {
+                        self.analysis.status = error.to_string();
+                    }
+                }
+                _ => self.analysis.status = e.to_string(),
+            },
+        }
+    }
+
+    /// Loads the FEN or PGN file at the path given.
+    fn open_analysis_file(&mut self) {
+        let path = self.analysis.path.trim().to_string();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                self.analysis.fen = text;
+                self.start_analysis();
+            }
+            Err(e) => self.analysis.status = format!("{}: {}", path, e),
+        }
+    }
+
+    fn analyze_position(&mut self, position: UciPosition) {
+        let pool = self.engine_pool.clone();
+        let state = &mut self.analysis;
+        let analyzer = state.analyzer.get_or_insert_with(|| Analyzer::start(pool));
+        analyzer.analyze(AnalysisRequest { position: position.clone(), lines: state.lines_wanted, infinite: state.infinite || state.follow_game });
+        state.board = position.board();
+        state.position = position;
+        state.lines.clear();
+        state.stats = SearchStats::default();
+        state.preview = None;
+        state.running = true;
+        state.status.clear();
+    }
+
+    /// Starts or stops analyzing the game board until stopped.
+    pub fn set_follow_game(&mut self, follow: bool) {
+        self.analysis.follow_game = follow;
+        if follow {
+            let position = self.board.uci_position();
+            self.analyze_position(position);
+        } else {
+            self.analysis.stop();
+        }
+    }
+
+    /// Stops analyzing the game board, when the game ends or its screen is left, so the
+    /// search gives back its engine and the eval bar follows the evaluator again.
+    pub fn stop_following_game(&mut self) {
+        if self.analysis.follow_game {
+            self.set_follow_game(false);
+        }
+    }
+
+    /// Takes in what the analysis found. While it follows the game it also restarts on every
+    /// new game position and drives the eval bar.
+    pub fn update_analysis(&mut self) {
+        if self.analysis.follow_game {
+            let position = self.board.uci_position();
+            if position != self.analysis.position {
+                self.analyze_position(position);
+            }
+        }
+        let events = self.analysis.analyzer.as_ref().map(Analyzer::poll).unwrap_or_default();
+        for event in events {
+            self.analysis.handle_event(event);
+        }
+        if self.analysis.follow_game && let Some(best) = self.analysis.best_line() {
+            self.board.state.current_evaluation = best.score;
+            self.board.state.current_wdl = best.wdl;
+        }
+    }
+
+    /// Depth, node counts and the other statistics of the running search.
+    pub fn render_search_stats(&self, ui: &mut Ui) {
+        let stats = &self.analysis.stats;
+        let depth = match (stats.depth, stats.seldepth) {
+            (Some(depth), Some(seldepth)) => format!("{}/{}", depth, seldepth),
+            (Some(depth), None) => depth.to_string(),
+            _ => "-".to_string(),
+        };
+        egui::Grid::new("search_stats").num_columns(2).show(ui, |ui| {
+            ui.label("Depth");
+            ui.label(depth);
+            ui.end_row();
+            ui.label("Nodes");
+            ui.label(stats.nodes.map_or("-".to_string(), short_count));
+            ui.end_row();
+            ui.label("Nodes/s");
+            ui.label(stats.nps.map_or("-".to_string(), short_count));
+            ui.end_row();
+            ui.label("Hash");
+            ui.label(stats.hashfull.map_or("-".to_string(), |permille| format!("{:.1}%", permille as f32 / 10.0)));
+            ui.end_row();
+            ui.label("TB hits");
+            ui.label(stats.tbhits.map_or("-".to_string(), short_count));
+            ui.end_row();
+        });
+    }
+
+    /// Moves and variations of the analysis board's game; clicking one analyzes the position
+    /// after it.
+    fn render_analyzed_game(&mut self, ui: &mut Ui) {
+        let game = &mut self.analysis.game.meta_data;
+        if !game.white_player_name.is_empty() || !game.black_player_name.is_empty() {
+            ui.strong(format!("{} - {}  {}", game.white_player_name, game.black_player_name, game.result.pgn()));
+        }
+        let current = game.moves.current();
+        let mut clicked = None;
+        ScrollArea::vertical().id_salt("analyzed_game").max_height(300.0).show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                if ui.selectable_label(current == GameTree::ROOT, "start").clicked() {
+                    clicked = Some(GameTree::ROOT);
+                }
+                if let Some(&first) = game.moves.node(GameTree::ROOT).children.first() {
+                    tree_line_labels(ui, game, first, &mut clicked);
+                }
+            });
+        });
+        // promoting and demoting move the whole variation the move is in
+        let line = game.moves.line_start(current);
+        let changed = ui
+            .horizontal(|ui| {
+                let enabled = current != GameTree::ROOT;
+                let promote = ui.add_enabled(enabled, egui::Button::new("Promote")).on_hover_text("Move the variation up, to the main line from the top").clicked();
+                let demote = ui.add_enabled(enabled, egui::Button::new("Demote")).clicked();
+                let delete = ui.add_enabled(enabled, egui::Button::new("Delete")).on_hover_text("Delete this move and what follows it").clicked();
+                (promote && game.moves.promote(line)) || (demote && game.moves.demote(line)) || (delete && game.moves.delete(current))
+            })
+            .inner;
+        // a deleted line takes the current position back to where it started
+        let after_change = game.moves.current();
+        match clicked {
+            Some(id) if id != current => self.goto_analysis_node(id),
+            _ if changed => self.goto_analysis_node(after_change),
+            _ => {}
+        }
+    }
+
+    /// The analysis board seen from the chosen side, with the last move and the moves of the
+    /// picked piece marked. A piece of the side to move and then a square play a move into
+    /// the game tree, as a variation when it differs from the one played before. Engine
+    /// lines are previewed read-only; a click returns to the position.
+    fn render_analysis_board(&mut self, top_left: Pos2, square_size: f32, ui: &mut Ui) {
+        let state = &mut self.analysis;
+        let preview = state.preview.and_then(|(line, ply)| state.preview_board(line, ply));
+        let (from, targets) = match (state.selected, &preview) {
+            (Some(from), None) => {
+                let name = square_name(from);
+                (Some(from), state.game.legal_moves().into_iter().filter(|uci| uci.starts_with(&name)).map(|uci| uci[2..4].to_string()).collect())
+            }
+            _ => (None, Vec::new()),
+        };
+        let state = &self.analysis;
+        let board = preview.as_ref().unwrap_or(&state.game);
+        let last_move = preview.is_none().then(|| state.game.meta_data.moves.line().last().map(|mv| mv.uci.clone())).flatten().unwrap_or_default();
+        let painter = ui.painter();
+        let mut clicked = None;
+        let mut deselect = false;
+        for raw_rank in 0..8 {
+            for raw_file in 0..8 {
+                let square = if state.pov == PieceColor::Black { (7 - raw_rank, 7 - raw_file) } else { (raw_rank, raw_file) };
+                let rect = Rect::from_min_size(pos2(top_left.x + raw_file as f32 * square_size, top_left.y + raw_rank as f32 * square_size), vec2(square_size, square_size));
+                let response = ui.interact(rect, ui.make_persistent_id(("analysis_square", raw_rank, raw_file)), Sense::click());
+                let light = (raw_rank + raw_file) % 2 == 0;
+                painter.rect_filled(rect, 0.0, if light { self.theme.light_square } else { self.theme.dark_square }.to_opaque());
+                let name = square_name(square);
+                if from == Some(square) {
+                    painter.rect_filled(rect, 0.0, self.theme.square_select_highlight);
+                } else if last_move.get(..2) == Some(&name) {
+                    painter.rect_filled(rect, 0.0, self.theme.moved_from_highlight.to_opaque());
+                } else if last_move.get(2..4) == Some(&name) {
+                    painter.rect_filled(rect, 0.0, self.theme.moved_to_highlight.to_opaque());
+                }
+                let piece = &board.squares[square.0 as usize][square.1 as usize];
+                self.render_piece(piece, &rect, painter);
+                if targets.contains(&name) {
+                    let color = if light { self.theme.light_pseudo_move_highlight } else { self.theme.dark_pseudo_move_highlight };
+                    painter.circle_filled(rect.center(), square_size * 0.225, color);
+                }
+                if response.clicked() {
+                    clicked = Some(square);
+                }
+                deselect |= response.secondary_clicked();
+            }
+        }
+        let state = &mut self.analysis;
+        if deselect {
+            state.selected = None;
+        }
+        let Some(square) = clicked else { return };
+        if state.preview.is_some() {
+            state.preview = None;
+            return;
+        }
+        let piece = state.game.squares[square.0 as usize][square.1 as usize];
+        match from {
+            Some(from) if targets.contains(&square_name(square)) => {
+                // the promotion piece only counts for moves that need one
+                let promoting = state.game.encode_uci_move(from, square, Some(state.promotion));
+                let uci = if state.game.legal_moves().contains(&promoting) { promoting } else { state.game.encode_uci_move(from, square, None) };
+                match state.game.play_uci_move(&uci) {
+                    Ok(_) => self.analyze_game_position(),
+                    Err(e) => state.status = format!("{:?}", e),
+                }
+            }
+            _ if from != Some(square) && piece.is_some_and(|piece| piece.color == state.game.turn) => state.selected = Some(square),
+            _ => state.selected = None,
+        }
+    }
+
+    pub fn render_analysis(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_analysis();
+        let running = self.analysis.running();
+        if running {
+            ctx.request_repaint();
+        }
+
+        SidePanel::left("analysis_settings").min_width(250.0).show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.analysis.stop();
+                self.screen = AppScreen::MainMenu;
+            }
+            ui.heading("Analysis");
+            ui.label("Position (FEN or PGN)");
+            ui.add(TextEdit::multiline(&mut self.analysis.fen).desired_rows(2));
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut self.analysis.path).hint_text("FEN or PGN file").desired_width(160.0));
+                if ui.add_enabled(!self.analysis.path.trim().is_empty(), egui::Button::new("Open")).clicked() {
+                    self.open_analysis_file();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Lines");
+                ui.add(DragValue::new(&mut self.analysis.lines_wanted).range(1..=MAX_LINES));
+            });
+            ui.checkbox(&mut self.analysis.infinite, "Run until stopped");
+            ui.horizontal(|ui| {
+                if ui.button("Analyze").clicked() {
+                    self.start_analysis();
+                }
+                if ui.add_enabled(running, egui::Button::new("Stop")).clicked() {
+                    self.analysis.stop();
+                }
+            });
+            if ui.button("Use game position").clicked() {
+                self.open_analysis();
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Flip board").clicked() {
+                    let pov = if self.analysis.pov == PieceColor::White { PieceColor::Black } else { PieceColor::White };
+                    self.analysis.pov = pov;
+                    self.analysis.game.state.pov = pov;
+                }
+                ui.label("Promote to");
+                for (kind, label) in [(PieceType::Queen, "Q"), (PieceType::Rook, "R"), (PieceType::Bishop, "B"), (PieceType::Knight, "N")] {
+                    ui.selectable_value(&mut self.analysis.promotion, kind, label);
+                }
+            });
+            ui.horizontal(|ui| {
+                let game = &self.analysis.game;
+                if ui.button("Copy FEN").clicked() {
+                    ctx.copy_text(game.to_string());
+                }
+                if ui.button("Copy PGN").on_hover_text("The moves leading to this position").clicked() {
+                    ctx.copy_text(PgnGame::from_game_line(&game.meta_data).to_pgn());
+                }
+                if ui.button("Copy all").on_hover_text("The whole game with its variations").clicked() {
+                    ctx.copy_text(PgnGame::from_game(&game.meta_data).to_pgn());
+                }
+            });
+            if running {
+                ui.spinner();
+            }
+            ui.label(&self.analysis.status);
+            ui.separator();
+            self.render_search_stats(ui);
+            ui.separator();
+            self.render_analyzed_game(ui);
+        });
+
+        CentralPanel::default().show(ctx, |ui| {
+            let square_size = (ui.available_width() / 14.0).clamp(24.0, 60.0);
+            // room for the eval bar left of the board
+            let top_left = ui.cursor().min + vec2(20.0 + self.ui.padding as f32, 0.0);
+            let (score, wdl) = self.analysis.best_line().map_or_else(Default::default, |best| (best.score, best.wdl));
+            self.draw_eval_bar(top_left, 8.0 * square_size, score, wdl, self.analysis.pov, ui);
+            self.render_analysis_board(top_left, square_size, ui);
+            ui.allocate_rect(Rect::from_min_size(top_left, vec2(8.0 * square_size, 8.0 * square_size)), Sense::hover());
+            if self.analysis.preview.is_some() && ui.button("Back to the position").clicked() {
+                self.analysis.preview = None;
+            }
+            ui.separator();
+            let state = &self.analysis;
+            let mut clicked = None;
+            ScrollArea::vertical().show(ui, |ui| {
+                for (index, line) in state.lines.iter().enumerate() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(RichText::new(format!("{:>6}", line.score.to_string())).monospace().strong());
+                        ui.label(RichText::new(format!("d{}", line.depth)).weak());
+                        let mut number = state.board.fullmove_number;
+                        let mut turn = state.board.turn;
+                        for (ply, san) in line.san.iter().enumerate() {
+                            if turn == PieceColor::White {
+                                ui.label(format!("{}.", number));
+                            } else if ply == 0 {
+                                ui.label(format!("{}...", number));
+                            }
+                            let selected = state.preview == Some((index, ply));
+                            if ui.selectable_label(selected, san).clicked() {
+                                clicked = Some((index, ply));
+                            }
+                            if turn == PieceColor::Black {
+                                number += 1;
+                            }
+                            turn = if turn == PieceColor::White { PieceColor::Black } else { PieceColor::White };
+                        }
+                    });
+                }
+            });
+            if let Some(preview) = clicked {
+                self.analysis.preview = Some(preview);
+            }
+        });
+    }
+}
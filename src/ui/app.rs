@@ -1,6 +1,8 @@
-use eframe::{egui::{self, vec2, CentralPanel, Color32, Frame, SidePanel, Stroke}, CreationContext};
+use std::sync::mpsc::{self, Receiver, Sender};
 
-use crate::{engine::{Board, PieceColor, PieceType}, game::{controller::{GameController, GameMode}, evaluator::Evaluator}, ui::{theme, ui_setting::UiSettings, DEFAULT_FEN}};
+use eframe::{egui, CreationContext};
+
+use crate::{engine::Board, game::{controller::GameController, engine_pool::EnginePool, engine_registry::EngineRegistry, evaluator::Evaluator}, ui::{analysis_render::AnalysisState, engine_render::EngineSettingsState, history_render::HistoryState, match_render::EngineMatchState, move_list::MoveListState, multiplayer_render::MultiplayerState, new_game_render::NewGameDialog, problem_render::ProblemSolverState, theme, ui_setting::UiSettings, DEFAULT_FEN}};
 
 pub enum AppScreen {
     MainMenu,
@@ -8,9 +10,12 @@ pub enum AppScreen {
     Multiplayer,
     History,
     Analyze,
+    ProblemSolver,
+    EngineSettings,
+    EngineMatch,
 }
 #[derive(Clone)]
-pub enum PopupType { GameLostPopup(String) }
+pub enum PopupType { GameLostPopup(String), EngineError(String) }
 pub struct MyApp {
     pub screen: AppScreen,
     pub popup: Option<PopupType>,
@@ -19,6 +24,19 @@ pub struct MyApp {
     pub game: GameController,
     pub evaluator: Evaluator,
     pub ui: UiSettings,
+    pub problem: ProblemSolverState,
+    pub engines: EngineRegistry,
+    pub engine_pool: EnginePool,
+    pub engine_settings: EngineSettingsState,
+    pub new_game: NewGameDialog,
+    pub engine_match: EngineMatchState,
+    pub analysis: AnalysisState,
+    pub history: HistoryState,
+    pub move_list: MoveListState,
+    pub multiplayer: MultiplayerState,
+    /// Engine failures reported by the worker threads, shown as popups.
+    pub engine_error_tx: Sender<String>,
+    pub engine_error_rx: Receiver<String>,
 }
 
 
@@ -28,6 +46,8 @@ impl From<&CreationContext<'_>> for MyApp{
 
     fn from(cc : &CreationContext) -> Self {
 
+        let engines = EngineRegistry::load();
+        let (engine_error_tx, engine_error_rx) = mpsc::channel();
         let mut app = 
             Self {
 
@@ -36,11 +56,23 @@ impl From<&CreationContext<'_>> for MyApp{
                 theme: theme::ThemeLoader::from(cc),
                 board: Board::from(&DEFAULT_FEN.to_owned()),
                 game: GameController::default(),
-                evaluator: Evaluator::new(),
-                ui : UiSettings::default()
+                evaluator: Evaluator::default(),
+                ui : UiSettings::default(),
+                problem: ProblemSolverState::default(),
+                engine_pool: EnginePool::new(engines.selected().cloned(), engines.pool_size, engine_error_tx.clone()),
+                engines,
+                engine_settings: EngineSettingsState::default(),
+                new_game: NewGameDialog::default(),
+                engine_match: EngineMatchState::default(),
+                analysis: AnalysisState::default(),
+                history: HistoryState::load(),
+                move_list: MoveListState::default(),
+                multiplayer: MultiplayerState::default(),
+                engine_error_tx,
+                engine_error_rx,
             };
         app.start_evaluator();
-        return app
+        app
 
         
 
@@ -59,19 +91,40 @@ impl eframe::App for MyApp {
                 self.render_train_with_ai(ctx, _frame);
             }
             AppScreen::History => {
-
+                self.render_history(ctx, _frame);
             }
             AppScreen::Multiplayer => {
-
+                self.render_multiplayer(ctx, _frame);
             }
             AppScreen::Analyze => {
-
+                self.render_analysis(ctx, _frame);
+            }
+            AppScreen::ProblemSolver => {
+                self.render_problem_solver(ctx, _frame);
+            }
+            AppScreen::EngineSettings => {
+                self.render_engine_settings(ctx, _frame);
+            }
+            AppScreen::EngineMatch => {
+                self.render_engine_match(ctx, _frame);
             }
         } // end matchd
+        if self.popup.is_none()
+            && let Ok(message) = self.engine_error_rx.try_recv() {
+            self.popup = Some(PopupType::EngineError(message));
+        }
         if let Some(popup) = self.popup.clone() {
             self.popup_handler(&popup, ctx, _frame);
         }
         }
+
+    /// Keeps a running game in the archive, stops the game worker and shuts down every
+    /// engine process.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.abandon_game();
+        self.game.stockfish_tx = None;
+        self.engine_pool.shutdown();
+    }
             
 
         
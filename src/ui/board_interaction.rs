@@ -1,10 +1,17 @@
+use std::time::Instant;
+
 use eframe::egui::Response;
 
-use crate::{engine::{ChessPiece, PieceColor, PieceType}, game::{controller::GameMode, evaluator::EvalKind, stockfish_engine::{StockfishCmd, StockfishResult}}, ui::app::{MyApp, PopupType}};
+use crate::{engine::{board::GameResult, ChessPiece, PieceColor, PieceType}, game::{archive::ArchivedGame, clock::ChessClock, controller::{GameMode, LostBy}, evaluator::EvalKind, stockfish_engine::{StockfishCmd, StockfishResult}}, ui::app::{MyApp, PopupType}};
 
 
 impl MyApp{
     pub fn handle_board_interaction_logic(&mut self,  piece: &Option<ChessPiece>, poz :&(u8, u8), response :&Response) {
+    // a position picked in the move list is only looked at, clicking returns to the game
+    if self.move_list.viewed_board().is_some() && (response.clicked() || response.secondary_clicked()) {
+        self.move_list.back_to_game();
+        return;
+    }
     match self.game.mode {
         GameMode::PvE => {
             // start stockfish
@@ -14,34 +21,46 @@ impl MyApp{
                 {
                 if !self.game.stockfish_move_pending {
                     self.game.stockfish_move_pending = true; // Mark move in progress
+                    // the player's move has to be off the clock before the engine gets its times
+                    self.update_clock();
                     // send UCI "go" command to Stockfish
                     if let Some(tx) = &self.game.stockfish_tx {
-                        let _ = tx.send(StockfishCmd::Go(self.board.to_string()));
+                        let clock = self.game.clock.as_ref().map(ChessClock::go_params);
+                        let _ = tx.send(StockfishCmd::Go(self.board.uci_position(), clock));
                     }
                 } else {
-                    // try to receive Stockfish result and apply it
+                    // try to receive Stockfish result and apply it, skipping the streamed info lines
+                    let mut engine_move = None;
+                    let mut failed = false;
                     if let Some(rx) = &self.game.stockfish_rx {
-                        if let Ok(StockfishResult::Move(mv)) = rx.try_recv() {
-                            println!("recived");
-                            println!("move {}", &mv);
-                            match self.board.decode_uci_move(mv) {
-                                Some((from, to)) => {
-                                        match self.board.move_piece(from, to){
-                                            Err(e) => {
-                                                println!("cannot move");
-                                            }
-                                            _ => {
-                                                self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                            }
-                                        }
-                                    
-                                }
-                                None => {}
+                        while let Ok(result) = rx.try_recv() {
+                            match result {
+                                // a move for a position that was taken back
+                                StockfishResult::Move(_) if self.game.cancelling_search => {}
+                                StockfishResult::Cancelled => self.game.cancelling_search = false,
+                                StockfishResult::Move(mv) => engine_move = Some(mv),
+                                StockfishResult::Fail => failed = true,
+                                StockfishResult::PlayerAccuracy(accuracy) => self.game.accuracy.push(accuracy),
+                                _ => {}
                             }
-                            
-                            self.game.stockfish_move_pending = false;
                         }
                     }
+                    if let Some(mv) = engine_move {
+                        match self.board.play_uci_move(&mv) {
+                            Err(e) => {
+                                eprintln!("the engine played {}, which cannot be played here: {:?}", mv, e);
+                                failed = true;
+                            }
+                            _ => {
+                                self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
+                            }
+                        }
+                        self.game.stockfish_move_pending = false;
+                    }
+                    // asking again would fail again every frame
+                    if failed {
+                        self.engine_failed();
+                    }
                 }
                       
                 } else {
@@ -62,36 +81,24 @@ impl MyApp{
                         match piece {
                             Some(piece) => {
                                 if piece.color !=  selected_piece.color {
-                                    match self.board.move_piece(selected_piece.position, piece.position){
-                                        Ok(_) => { 
-                                            self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                            println!("Ok");}
-                                        Err(_) => {
-                                            println!("Not Ok");
-                                        }
+                                    if self.board.move_piece(selected_piece.position, piece.position).is_ok() {
+                                        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
                                     }
                                     self.board.deselect_piece();
                                 }
                                 else{
                                     if piece.color == self.board.turn{
                                         if selected_piece.kind == PieceType::King && piece.kind == PieceType::Rook && !self.board.is_in_check(piece.color){
-                                            match &self.board.state.capture_moves{
-                                                Some(moves) => {
-                                                    if moves.contains(&piece.position){
-                                                        match self.board.execute_castle(selected_piece.position, piece.position){
-                                                            Ok(_) => {
-                                                                self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                                            }
-                                                            _ => {}
-                                                        }
-                                                        self.board.deselect_piece();
-                                                       
-                                                    }
-                                                    else {
-                                                        self.board.select_piece(*piece);
+                                            if let Some(moves) = &self.board.state.capture_moves {
+                                                if moves.contains(&piece.position){
+                                                    if self.board.execute_castle(selected_piece.position, piece.position).is_ok() {
+                                                        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
                                                     }
+                                                    self.board.deselect_piece();
+                                                }
+                                                else {
+                                                    self.board.select_piece(*piece);
                                                 }
-                                                _=>{}
                                             }
                                         }else{
                                             if self.game.player == piece.color{
@@ -104,13 +111,8 @@ impl MyApp{
                                 }
                             }
                             None => {
-                                match self.board.move_piece(selected_piece.position, *poz){
-                                    Ok(_) => { 
-                                        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                        println!("Ok");}
-                                    Err(_) => {
-                                        println!("Not Ok");
-                                    }
+                                if self.board.move_piece(selected_piece.position, *poz).is_ok() {
+                                    self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
                                 }
                                 self.board.deselect_piece();
                             }
@@ -134,113 +136,310 @@ impl MyApp{
                 }   
                 }
                 }
-                if self.board.has_lost() { 
-                    self.game.game_over = true;
-                    self.popup = Some(PopupType::GameLostPopup("ai perdut".to_owned()));
-                    let king_pos = self
-                        .board
-                        .squares
-                        .iter()
-                        .enumerate()
-                        .find_map(|(r, row)| {
-                            row.iter().enumerate().find_map(|(c, &cell)| {
-                                if let Some(piece) = cell {
-                                    if piece.kind == PieceType::King && piece.color == self.board.turn {
-                                        Some((r as u8, c as u8))
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            })
-                        });
-                    self.board.state.checkmate_square = king_pos;
-                   
-                }
+                self.check_game_end();
             }
             
         }
-        GameMode::Sandbox => {
-            if response.secondary_clicked() {
-                self.board.deselect_piece();
+        GameMode::Sandbox => self.handle_free_move(piece, poz, response),
+        GameMode::PvP => {
+            if !self.game.game_over {
+                self.handle_free_move(piece, poz, response);
+                self.check_game_end();
             }
-            if response.clicked() {
-            match self.board.state.selected_piece {
-                Some(selected_piece) => {
-                    //if a piece is already selected
-                    match piece {
-                        Some(piece) => {
-                            if piece.color !=  selected_piece.color {
-                                match self.board.move_piece(selected_piece.position, piece.position){
-                                    Ok(_) => { 
-                                        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                        println!("Ok");}
-                                    Err(_) => {
-                                        println!("Not Ok");
-                                    }
-                                }
-                                self.board.deselect_piece();
+        }
+    }
+    
+}
+
+    /// Selects and moves pieces of the side to move, for both sides: Sandbox and games
+    /// between two players at this board.
+    fn handle_free_move(&mut self, piece: &Option<ChessPiece>, poz: &(u8, u8), response: &Response) {
+        if response.secondary_clicked() {
+            self.board.deselect_piece();
+        }
+        if response.clicked() {
+        match self.board.state.selected_piece {
+            Some(selected_piece) => {
+                //if a piece is already selected
+                match piece {
+                    Some(piece) => {
+                        if piece.color !=  selected_piece.color {
+                            if self.board.move_piece(selected_piece.position, piece.position).is_ok() {
+                                self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
                             }
-                            else{
-                                if piece.color == selected_piece.color{
-                                    if selected_piece.kind == PieceType::King && piece.kind == PieceType::Rook && !self.board.is_in_check(piece.color){
-                                        match &self.board.state.capture_moves{
-                                            Some(moves) => {
-                                                if moves.contains(&piece.position){
-                                                match self.board.execute_castle(selected_piece.position, piece.position){
-                                                    Ok(_) => {
-                                                        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                                    }
-                                                    _ => {}
-                                                }
-                                                self.board.deselect_piece();
-                                                }
-                                            }
-                                            _=>{}
+                            self.board.deselect_piece();
+                        }
+                        else{
+                            if piece.color == selected_piece.color{
+                                if selected_piece.kind == PieceType::King && piece.kind == PieceType::Rook && !self.board.is_in_check(piece.color){
+                                    if let Some(moves) = &self.board.state.capture_moves
+                                        && moves.contains(&piece.position) {
+                                        if self.board.execute_castle(selected_piece.position, piece.position).is_ok() {
+                                            self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
                                         }
-
-                                    }else{
-                                            self.board.select_piece(*piece);
-                                        
+                                        self.board.deselect_piece();
                                     }
+
+                                }else{
+                                        self.board.select_piece(*piece);
                                     
                                 }
+                                
                             }
                         }
-                        None => {
-                            match self.board.move_piece(selected_piece.position, *poz){
-                                Ok(_) => { 
-                                    self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
-                                    println!("Ok");}
-                                Err(_) => {
-                                    println!("Not Ok");
-                                }
-                            }
-                            self.board.deselect_piece();
+                    }
+                    None => {
+                        if self.board.move_piece(selected_piece.position, *poz).is_ok() {
+                            self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
+                        }
+                        self.board.deselect_piece();
+                    }
+                    
+                }
+            }
+            None => {
+                //if piece not selected already select piece
+                match piece {
+                    Some(piece) =>{
+                        if piece.color == self.board.turn{
+                            self.board.select_piece(*piece);
                         }
                         
                     }
+                    None => {
+                        self.board.deselect_piece();
+                    }
                 }
-                None => {
-                    //if piece not selected already select piece
-                    match piece {
-                        Some(piece) =>{
-                            if piece.color == self.board.turn{
-                                self.board.select_piece(*piece);
+            }
+        }
+        }
+    }
+
+    /// Ends the game when the side to move is mated or stalemated or the fifty-move rule
+    /// applies, marking the mated king.
+    fn check_game_end(&mut self) {
+        if self.board.has_lost() { 
+            let (result, termination, message) = if self.board.is_chackmate() {
+                match self.board.turn {
+                    PieceColor::White => (GameResult::BlackWin, LostBy::Checkmate, "Checkmate: Black wins"),
+                    PieceColor::Black => (GameResult::WhiteWin, LostBy::Checkmate, "Checkmate: White wins"),
+                }
+            } else if self.board.is_stale_mate() {
+                (GameResult::Draw, LostBy::StaleMate, "Stalemate: draw")
+            } else {
+                (GameResult::Draw, LostBy::Draw, "Fifty moves without a capture or pawn move: draw")
+            };
+            self.end_game(result, termination, message.to_string());
+            let king_pos = self
+                .board
+                .squares
+                .iter()
+                .enumerate()
+                .find_map(|(r, row)| {
+                    row.iter().enumerate().find_map(|(c, &cell)| {
+                        if let Some(piece) = cell {
+                            if piece.kind == PieceType::King && piece.color == self.board.turn {
+                                Some((r as u8, c as u8))
+                            } else {
+                                None
                             }
-                            
-                        }
-                        None => {
-                            self.board.deselect_piece();
+                        } else {
+                            None
                         }
-                    }
+                    })
+                });
+            self.board.state.checkmate_square = king_pos;
+           
+        }
+    }
+
+    /// Records the time of the moves made since the last call, pressing the clock for each,
+    /// and ends the game when a flag fell. Running out of time against a lone king, or
+    /// anything else that cannot mate, is a draw.
+    pub fn update_clock(&mut self) {
+        if self.game.game_over {
+            return;
+        }
+        self.record_move_times();
+        let Some(loser) = self.game.clock.as_ref().and_then(ChessClock::flagged) else { return };
+        let winner = match loser {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        let (result, termination, message) = match (self.board.can_mate(winner), winner) {
+            (false, _) => (GameResult::Draw, LostBy::Draw, format!("{:?} ran out of time, but {:?} cannot mate: draw", loser, winner)),
+            (true, PieceColor::White) => (GameResult::WhiteWin, LostBy::Timeout, "Black ran out of time: White wins".to_string()),
+            (true, PieceColor::Black) => (GameResult::BlackWin, LostBy::Timeout, "White ran out of time: Black wins".to_string()),
+        };
+        self.end_game(result, termination, message);
+    }
+
+    /// Stores how long each new move took and the clock after it. New moves of a game are
+    /// what was played, so a line taken back before them drops to a variation.
+    fn record_move_times(&mut self) {
+        let tree = &mut self.board.meta_data.moves;
+        if !matches!(self.game.mode, GameMode::Sandbox) && tree.ply() > self.game.timed_moves {
+            tree.promote_line(tree.current());
+        }
+        while let Some(record) = self.board.meta_data.moves.line_move_mut(self.game.timed_moves) {
+            match &mut self.game.clock {
+                Some(clock) if clock.flagged().is_some() => break,
+                Some(clock) => {
+                    let mover = clock.running();
+                    record.time.spent = Some(clock.press());
+                    record.time.clock = mover.map(|color| clock.remaining(color));
                 }
+                None => record.time.spent = Some(self.game.move_started.elapsed()),
             }
+            self.game.move_started = Instant::now();
+            self.game.timed_moves += 1;
+        }
+    }
+
+    /// Ends the running game: records the result and how it came about, stops the clock,
+    /// saves the game to the archive and tells the player.
+    pub fn end_game(&mut self, result: GameResult, termination: LostBy, message: String) {
+        self.record_move_times();
+        if let Some(clock) = &mut self.game.clock {
+            clock.stop();
+        }
+        self.game.game_over = true;
+        self.stop_following_game();
+        self.game.lost_by = Some(termination.clone());
+        let score = match (&result, self.game.player) {
+            (GameResult::WhiteWin, PieceColor::White) | (GameResult::BlackWin, PieceColor::Black) => 1.0,
+            (GameResult::WhiteWin, _) | (GameResult::BlackWin, _) => 0.0,
+            _ => 0.5,
+        };
+        self.game.finish_adaptive_game(score);
+        self.board.meta_data.termination = termination;
+        self.board.meta_data.result = result;
+        self.history.archive.add([ArchivedGame::from_game(&self.board.meta_data, self.local_player())]);
+        self.popup = Some(PopupType::GameLostPopup(message));
+    }
+
+    /// Saves a game that is left before it ended to the archive, unfinished, e.g. when a new
+    /// game starts or the app closes. Sandbox play and games without a move are dropped. The
+    /// engine of a running game against it is stopped.
+    pub fn abandon_game(&mut self) {
+        if matches!(self.game.mode, GameMode::PvE) && !self.game.game_over {
+            self.stop_engine_opponent();
+        }
+        if matches!(self.game.mode, GameMode::Sandbox) || self.game.game_over || self.board.meta_data.moves.mainline().is_empty() {
+            return;
+        }
+        self.record_move_times();
+        if let Some(clock) = &mut self.game.clock {
+            clock.stop();
+        }
+        self.game.game_over = true;
+        self.game.lost_by = Some(LostBy::Abandoned);
+        self.board.meta_data.termination = LostBy::Abandoned;
+        self.board.meta_data.result = GameResult::Unfinished;
+        self.history.archive.add([ArchivedGame::from_game(&self.board.meta_data, self.local_player())]);
+    }
+
+    /// Hands the engine of a game left running back to the pool; a move it still sends is
+    /// dropped.
+    fn stop_engine_opponent(&mut self) {
+        if let Some(tx) = &self.game.stockfish_tx {
+            if let Err(e) = tx.send(StockfishCmd::Stop) {
+                eprintln!("failed to send `stop` to stockfish: {}", e);
             }
-        }   
-        _ => {}
+            self.game.cancelling_search = true;
+        }
+        self.game.stockfish_move_pending = false;
+    }
+
+    /// Stops a game whose engine could not move, or answered with an illegal move: the game
+    /// is kept as abandoned and the player is told.
+    fn engine_failed(&mut self) {
+        self.game.stockfish_move_pending = false;
+        self.abandon_game();
+        // a game without moves is not kept, but it is over all the same
+        self.game.game_over = true;
+        if let Some(clock) = &mut self.game.clock {
+            clock.stop();
+        }
+        self.popup = Some(PopupType::EngineError("The engine could not answer, the game was stopped. Start a new game or pick another engine in Engines.".to_string()));
+    }
+
+    /// Color of the one player at this board against the engine; two players share it
+    /// otherwise.
+    fn local_player(&self) -> Option<PieceColor> {
+        matches!(self.game.mode, GameMode::PvE).then_some(self.game.player)
+    }
+
+    /// Whether moves can be taken back or redone now: always in Sandbox, while the game runs
+    /// and allows it otherwise.
+    pub fn can_take_back(&self) -> bool {
+        match self.game.mode {
+            GameMode::PvE | GameMode::PvP => !self.game.game_over && self.game.takebacks,
+            _ => true,
+        }
+    }
+
+    /// Takes back the last move, or against the engine the moves since the player's
+    /// previous turn: the engine's reply and the player's move, or just the player's move
+    /// while the engine thinks about it.
+    pub fn take_back(&mut self) {
+        if !self.can_take_back() || self.board.state.promtion_pending.is_some() {
+            return;
+        }
+        let meta = &self.board.meta_data;
+        let tree = &meta.moves;
+        let mut target = tree.current();
+        loop {
+            let Some(parent) = tree.node(target).parent else { return };
+            target = parent;
+            if !matches!(self.game.mode, GameMode::PvE) || meta.mover(tree.path(target).len()) == self.game.player {
+                break;
+            }
+        }
+        self.show_game_node(target);
+    }
+
+    /// Plays the moves taken back again along the main line, as many as `take_back` took.
+    pub fn redo(&mut self) {
+        if !self.can_take_back() || self.board.state.promtion_pending.is_some() {
+            return;
+        }
+        let meta = &self.board.meta_data;
+        let tree = &meta.moves;
+        let mut target = tree.current();
+        while let Some(&next) = tree.node(target).children.first() {
+            target = next;
+            if !matches!(self.game.mode, GameMode::PvE) || meta.mover(tree.path(target).len()) == self.game.player {
+                break;
+            }
+        }
+        if target != tree.current() {
+            self.show_game_node(target);
+        }
+    }
+
+    /// Sets the board to node `id` of the game after a takeback or redo: cancels the engine's
+    /// search and puts the evaluation and the clock back to that position.
+    fn show_game_node(&mut self, id: usize) {
+        if self.game.stockfish_move_pending {
+            if let Some(tx) = &self.game.stockfish_tx {
+                let _ = tx.send(StockfishCmd::Cancel);
+                self.game.cancelling_search = true;
+            }
+            self.game.stockfish_move_pending = false;
+        }
+        self.board.goto(id);
+        if let Some(record) = self.board.meta_data.moves.line().last() {
+            self.board.state.current_evaluation = record.evaluation.unwrap_or_default();
+            self.board.state.current_wdl = record.wdl.unwrap_or_default();
+        }
+        self.evaluator.send_eval_request(self.board.to_string(), EvalKind::BarEval);
+        if !self.game.game_over {
+            if let Some(clock) = &mut self.game.clock {
+                clock.rewind(&self.board.meta_data);
+            }
+            self.game.timed_moves = self.board.meta_data.moves.ply();
+            self.game.move_started = Instant::now();
+        }
     }
-    
 }
-}
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::sync::mpsc::{self, Receiver};
+
+use eframe::egui::{self, CentralPanel, ComboBox, DragValue, RichText, SidePanel, TextEdit};
+
+use crate::{game::{chess_engine::Protocol, engine_registry::{EngineConfig, OptionKind}}, ui::app::{AppScreen, MyApp}};
+
+#[derive(Default)]
+pub struct EngineSettingsState {
+    pub new_path: String,
+    pub new_args: String,
+    pub new_protocol: Protocol,
+    /// Engine whose options are shown in the form.
+    pub editing: usize,
+    pub status: String,
+    /// Option values edited in the form but not yet saved and handed to the engines.
+    pub unapplied: bool,
+    pub discover_rx: Option<Receiver<Result<EngineConfig, String>>>,
+}
+
+impl MyApp {
+    /// Starts `path` on a background thread to read its name and options.
+    fn discover_engine(&mut self, path: String, args: Vec<String>, protocol: Protocol) {
+        let (tx, rx) = mpsc::channel();
+        let _ = std::thread::Builder::new().name("engine_discovery".to_string()).spawn(move || {
+            let _ = tx.send(EngineConfig::discover(&path, args, protocol).map_err(|e| e.to_string()));
+        });
+        self.engine_settings.discover_rx = Some(rx);
+        self.engine_settings.status = "Starting engine...".to_string();
+    }
+
+    /// Saves option values edited in the form and restarts the engines with them.
+    fn apply_engine_options(&mut self) {
+        if self.engine_settings.unapplied {
+            self.engine_settings.unapplied = false;
+            self.engines.save();
+            self.use_selected_engine();
+        }
+    }
+
+    pub fn render_engine_settings(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(rx) = &self.engine_settings.discover_rx {
+            match rx.try_recv() {
+                Ok(Ok(config)) => {
+                    self.engine_settings.status = format!("Found {} with {} options", config.name, config.options.len());
+                    let path = config.path.clone();
+                    self.engines.add(config);
+                    self.engines.save();
+                    self.engine_settings.editing = self.engines.engines.iter().position(|e| e.path == path).unwrap_or(0);
+                    self.engine_settings.discover_rx = None;
+                }
+                Ok(Err(e)) => {
+                    self.engine_settings.status = e;
+                    self.engine_settings.discover_rx = None;
+                }
+                Err(_) => ctx.request_repaint(),
+            }
+        }
+        let busy = self.engine_settings.discover_rx.is_some();
+
+        SidePanel::left("engine_list").min_width(250.0).show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.apply_engine_options();
+                self.screen = AppScreen::MainMenu;
+            }
+            ui.heading("Engines");
+            let mut clicked = None;
+            for (i, engine) in self.engines.engines.iter().enumerate() {
+                let label = if i == self.engines.selected { format!("{} (in use)", engine.name) } else { engine.name.clone() };
+                if ui.selectable_label(i == self.engine_settings.editing, label).clicked() {
+                    clicked = Some(i);
+                }
+            }
+            if let Some(i) = clicked {
+                self.apply_engine_options();
+                self.engine_settings.editing = i;
+            }
+            ui.horizontal(|ui| {
+                ui.label("Engines running at once");
+                if ui.add(DragValue::new(&mut self.engines.pool_size).range(1..=8)).changed() {
+                    self.engine_pool.set_max_running(self.engines.pool_size);
+                    self.engines.save();
+                }
+            });
+            if ui.checkbox(&mut self.engines.ponder, "Think on my time").on_hover_text("The engine keeps thinking during your turn, from the next game on. Needs a UCI engine with the Ponder option.").changed() {
+                self.engines.save();
+            }
+            ui.separator();
+            ui.label("Add an engine");
+            ui.add(TextEdit::singleline(&mut self.engine_settings.new_path).hint_text("/path/to/engine"));
+            ui.add(TextEdit::singleline(&mut self.engine_settings.new_args).hint_text("arguments"));
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.engine_settings.new_protocol, Protocol::Uci, "UCI");
+                ui.radio_value(&mut self.engine_settings.new_protocol, Protocol::Cecp, "XBoard");
+            });
+            if ui.add_enabled(!busy && !self.engine_settings.new_path.trim().is_empty(), egui::Button::new("Add")).clicked() {
+                let path = self.engine_settings.new_path.trim().to_string();
+                let args = self.engine_settings.new_args.split_whitespace().map(String::from).collect();
+                self.discover_engine(path, args, self.engine_settings.new_protocol);
+            }
+            if busy {
+                ui.spinner();
+            }
+            ui.label(&self.engine_settings.status);
+        });
+
+        CentralPanel::default().show(ctx, |ui| {
+            let index = self.engine_settings.editing;
+            let Some(engine) = self.engines.engines.get(index).cloned() else {
+                ui.label("No engine added yet.");
+                return;
+            };
+            ui.heading(&engine.name);
+            let protocol = match engine.protocol {
+                Protocol::Uci => "UCI",
+                Protocol::Cecp => "XBoard",
+            };
+            ui.label(RichText::new(format!("{} {} ({})", engine.path, engine.args.join(" "), protocol)).monospace());
+            ui.horizontal(|ui| {
+                if ui.add_enabled(index != self.engines.selected, egui::Button::new("Use this engine")).clicked() {
+                    self.engines.selected = index;
+                    self.engines.save();
+                    self.use_selected_engine();
+                }
+                if ui.add_enabled(!busy, egui::Button::new("Refresh options")).clicked() {
+                    self.discover_engine(engine.path.clone(), engine.args.clone(), engine.protocol);
+                }
+                if ui.button("Reset to defaults").clicked() {
+                    self.engines.engines[index].values.clear();
+                    self.engines.save();
+                    self.use_selected_engine();
+                }
+                if ui.button("Remove").clicked() {
+                    self.engines.remove(index);
+                    self.engines.save();
+                    self.engine_settings.editing = 0;
+                    self.use_selected_engine();
+                }
+            });
+            ui.label(RichText::new("Changes are used from the next game or evaluation on.").weak());
+            ui.separator();
+            if engine.options.is_empty() {
+                ui.label("No options known for this engine, press \"Refresh options\".");
+                return;
+            }
+            let mut edited = false;
+            // a value being dragged or typed is only used once it is let go of
+            let mut finished = false;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("engine_options").num_columns(2).striped(true).show(ui, |ui| {
+                    for option in &engine.options {
+                        if option.kind == OptionKind::Button {
+                            continue;
+                        }
+                        let config = &mut self.engines.engines[index];
+                        let current = config.value(option);
+                        let mut value = current.clone();
+                        ui.label(&option.name);
+                        match &option.kind {
+                            OptionKind::Spin { min, max } => {
+                                let mut number: i64 = value.parse().unwrap_or(0);
+                                let response = ui.add(DragValue::new(&mut number).range(*min..=*max));
+                                value = number.to_string();
+                                finished |= response.drag_stopped() || response.lost_focus() || (response.changed() && !response.dragged() && !response.has_focus());
+                            }
+                            OptionKind::Check => {
+                                let mut checked = value == "true";
+                                finished |= ui.checkbox(&mut checked, "").changed();
+                                value = checked.to_string();
+                            }
+                            OptionKind::Combo { vars } => {
+                                ComboBox::from_id_salt(&option.name).selected_text(value.clone()).show_ui(ui, |ui| {
+                                    for var in vars {
+                                        finished |= ui.selectable_value(&mut value, var.clone(), var).clicked();
+                                    }
+                                });
+                            }
+                            OptionKind::String => {
+                                finished |= ui.add(TextEdit::singleline(&mut value).desired_width(300.0)).lost_focus();
+                            }
+                            OptionKind::Button => {}
+                        }
+                        ui.end_row();
+                        if value != current {
+                            config.set_value(option, value);
+                            edited = true;
+                        }
+                    }
+                });
+            });
+            self.engine_settings.unapplied |= edited;
+            if finished {
+                self.apply_engine_options();
+            }
+        });
+    }
+}
@@ -1,8 +1,25 @@
 use eframe::egui::{self, pos2, vec2, Color32, CornerRadius, Pos2, Rect, Stroke, Ui, UiBuilder, Vec2};
 
-use crate::{engine::PieceType, etc::STOCKFISH_ELO, ui::app::MyApp};
+use crate::{engine::{PieceColor, PieceType}, game::{clock::clock_text, controller::GameMode}, ui::app::MyApp};
+
+/// Time left below which a clock is drawn in red.
+const LOW_TIME_MS: u128 = 10_000;
 
 impl MyApp {
+    /// Time left for `color`, strong while it runs and red when it is nearly out.
+    pub fn clock_label(&self, color: PieceColor) -> egui::RichText {
+        let Some(clock) = &self.game.clock else { return egui::RichText::new("--:--").weak() };
+        let left = clock.remaining(color);
+        let mut text = egui::RichText::new(clock_text(left));
+        if clock.running() == Some(color) {
+            text = text.strong();
+        }
+        if left.as_millis() < LOW_TIME_MS {
+            text = text.color(Color32::RED);
+        }
+        text
+    }
+
     pub fn render_game_info(&mut self, top_left: Pos2, ui : &mut Ui) {
         
         let unit =  &self.ui.square_size;
@@ -46,14 +63,25 @@ impl MyApp {
         }
         ui.painter().image(
             self.theme.white_pfp.as_ref().unwrap_or(&self.theme.empty_texture).id(), white_player_pfp, Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)), Color32::WHITE);
-        let against: &str = "Stockfish";
+        // two players at the board sit on the sides they see it from, the engine sits on top
+        let (top, bottom, top_label, bottom_label) = match self.game.mode {
+            GameMode::PvP => {
+                let bottom = self.board.state.pov;
+                let top = if bottom == PieceColor::White { PieceColor::Black } else { PieceColor::White };
+                (top, bottom, self.board.meta_data.player_name(top).to_string(), self.board.meta_data.player_name(bottom).to_string())
+            }
+            _ => {
+                let against = self.engines.selected().map(|e| e.name.as_str()).unwrap_or("Engine");
+                (self.game.enemey, self.game.player, format!("{} ({})", against, self.game.strength), format!("Player ({:.0})", self.game.profile.rating))
+            }
+        };
         ui.allocate_new_ui(UiBuilder::new().max_rect(white_player_label), |ui| {
             ui.style_mut().text_styles.insert(
                 egui::TextStyle::Body,
                 egui::FontId::new(16.0, egui::FontFamily::Proportional)
             );
             ui.horizontal_centered(|ui| {
-                ui.label(format!("{}({})", against, STOCKFISH_ELO));
+                ui.label(top_label);
             });
         });
             ui.allocate_new_ui(UiBuilder::new().max_rect(white_box), |ui| {
@@ -62,7 +90,7 @@ impl MyApp {
                 egui::FontId::new(18.0, egui::FontFamily::Proportional)
                 );
                 ui.centered_and_justified(|ui| {
-                ui.label(egui::RichText::new("10:00").strong());
+                ui.label(self.clock_label(top));
                 });
             });
             ui.allocate_new_ui(UiBuilder::new().max_rect(white_player_pieces), |ui| {
@@ -128,7 +156,7 @@ impl MyApp {
                 egui::FontId::new(18.0, egui::FontFamily::Proportional)
                 );
                 ui.centered_and_justified(|ui| {
-                ui.label(egui::RichText::new("10:00").strong());
+                ui.label(self.clock_label(bottom));
                 });
             });
             let black_player_pfp = Rect::from_min_size (
@@ -167,7 +195,7 @@ impl MyApp {
                     egui::FontId::new(16.0, egui::FontFamily::Proportional)
                 );
                 ui.horizontal_centered(|ui| {
-                    ui.label(format!("Player(???)"));
+                    ui.label(bottom_label);
                 });
             });
 
@@ -0,0 +1,174 @@
+use std::fs;
+
+use eframe::egui::{self, CentralPanel, CollapsingHeader, Grid, ScrollArea, TextEdit};
+
+use crate::{engine::{board::BoardMetaData, game_tree::GameTree, pgn::{parse_pgn, PgnError, PgnGame}}, etc::data_dir, game::{archive::{ArchivedGame, GameArchive}, controller::GameMode}, ui::app::{AppScreen, MyApp}};
+
+/// The games played here and imported, kept in the archive.
+#[derive(Default)]
+pub struct HistoryState {
+    pub archive: GameArchive,
+    /// Game whose "Delete" was clicked once, waiting to be confirmed.
+    confirm_delete: Option<usize>,
+    /// Outcome of the last export or import.
+    pub status: String,
+    /// PGN file to import, or PGN text pasted in.
+    pub import_path: String,
+    pub import_text: String,
+    /// What could not be read in the last import.
+    pub import_errors: Vec<PgnError>,
+}
+
+impl HistoryState {
+    /// History with the saved archive.
+    pub fn load() -> Self {
+        Self { archive: GameArchive::load(), ..Self::default() }
+    }
+}
+
+/// Writes the game as a PGN file in the `games` folder of the data folder and copies the
+/// PGN to the clipboard. Returns what happened, for the user.
+pub fn export_pgn(ctx: &egui::Context, meta: &BoardMetaData) -> String {
+    let pgn = PgnGame::from_game(meta).to_pgn();
+    ctx.copy_text(pgn.clone());
+    let name: String = format!("{} {} - {}.pgn", meta.date, meta.white_player_name, meta.black_player_name)
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_.".contains(c) { c } else { '_' })
+        .collect();
+    let dir = data_dir().join("games");
+    let path = dir.join(name);
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, pgn)) {
+        Ok(()) => format!("Saved as {} and copied to the clipboard", path.display()),
+        Err(e) => format!("Could not write {} ({}), the PGN was copied to the clipboard", path.display(), e),
+    }
+}
+
+/// What can be done with an archived game.
+#[derive(Clone, Copy)]
+enum HistoryAction { Replay, Analyze, Export, ConfirmDelete, Delete }
+
+impl MyApp {
+    fn history_action(&mut self, ctx: &egui::Context, index: usize, action: HistoryAction) {
+        self.history.confirm_delete = None;
+        let Some(game) = self.history.archive.games.get(index) else { return };
+        if let HistoryAction::ConfirmDelete = action {
+            self.history.confirm_delete = Some(index);
+            return;
+        }
+        if let HistoryAction::Delete = action {
+            self.history.archive.remove(index);
+            self.history.status = "Game deleted".to_string();
+            return;
+        }
+        let Some(board) = game.to_board() else {
+            self.history.status = "The game's PGN could not be read".to_string();
+            return;
+        };
+        match action {
+            HistoryAction::Replay => self.replay_game(board.meta_data),
+            HistoryAction::Analyze => self.analyze_game(board.meta_data),
+            _ => self.history.status = export_pgn(ctx, &board.meta_data),
+        }
+    }
+
+    /// Adds the games of a PGN text to the archive, as far as they could be read.
+    pub fn import_pgn(&mut self, text: &str) {
+        let (games, errors) = parse_pgn(text);
+        self.history.status = match (games.len(), errors.len()) {
+            (0, 0) => "No games found".to_string(),
+            (count, 0) => format!("Imported {} game(s)", count),
+            (count, problems) => format!("Imported {} game(s), {} could not be read completely", count, problems),
+        };
+        self.history.archive.add(games.iter().map(|game| ArchivedGame::from_game(&game.to_board().meta_data, None)));
+        self.history.import_errors = errors;
+    }
+
+    fn render_import(&mut self, ui: &mut egui::Ui) {
+        CollapsingHeader::new("Import PGN").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut self.history.import_path);
+                if ui.button("Import file").clicked() {
+                    let path = self.history.import_path.trim().to_string();
+                    match fs::read_to_string(&path) {
+                        Ok(text) => self.import_pgn(&text),
+                        Err(e) => self.history.status = format!("Could not read {}: {}", path, e),
+                    }
+                }
+            });
+            ui.label("or paste the PGN");
+            ui.add(TextEdit::multiline(&mut self.history.import_text).desired_rows(4).code_editor());
+            if ui.button("Import text").clicked() {
+                let text = std::mem::take(&mut self.history.import_text);
+                self.import_pgn(&text);
+            }
+            for error in &self.history.import_errors {
+                ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+            }
+        });
+    }
+
+    /// Shows `game` on the board from its first move, to step through it with the move list
+    /// or the arrow keys. A game still running is abandoned.
+    pub fn replay_game(&mut self, game: BoardMetaData) {
+        self.abandon_game();
+        let mut board = self.board.clone();
+        let end = game.moves.mainline_from(GameTree::ROOT).last().copied().unwrap_or(GameTree::ROOT);
+        board.meta_data = game;
+        board.goto(end);
+        self.board = board;
+        self.board.deselect_piece();
+        self.game.mode = GameMode::Sandbox;
+        self.game.game_over = true;
+        self.game.clock = None;
+        self.move_list.back_to_game();
+        self.view_position(GameTree::ROOT);
+        self.screen = AppScreen::TrainWithAi;
+    }
+
+    pub fn render_history(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        CentralPanel::default().show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.screen = AppScreen::MainMenu;
+                self.history.status.clear();
+            }
+            ui.heading("Game History");
+            self.render_import(ui);
+            if self.history.archive.games.is_empty() {
+                ui.label("Finished, abandoned and imported games show up here.");
+                ui.label(&self.history.status);
+                return;
+            }
+            let mut action = None;
+            ScrollArea::vertical().show(ui, |ui| {
+                Grid::new("history_games").striped(true).num_columns(9).show(ui, |ui| {
+                    for heading in ["Date", "Opponent", "Result", "Opening", "Moves", "", "", "", ""] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+                    for (index, game) in self.history.archive.games.iter().enumerate().rev() {
+                        ui.label(&game.date);
+                        ui.label(&game.opponent).on_hover_text(format!("{} - {}", game.white, game.black));
+                        ui.label(&game.result);
+                        ui.label(&game.opening);
+                        ui.label(game.moves.to_string());
+                        for (label, kind) in [("Replay", HistoryAction::Replay), ("Analyze", HistoryAction::Analyze), ("Export PGN", HistoryAction::Export)] {
+                            if ui.button(label).clicked() {
+                                action = Some((index, kind));
+                            }
+                        }
+                        let confirming = self.history.confirm_delete == Some(index);
+                        if ui.button(if confirming { "Really delete?" } else { "Delete" }).clicked() {
+                            action = Some((index, if confirming { HistoryAction::Delete } else { HistoryAction::ConfirmDelete }));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            if let Some((index, action)) = action {
+                self.history_action(ctx, index, action);
+            }
+            ui.label(&self.history.status);
+        });
+    }
+}
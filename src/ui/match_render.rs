@@ -0,0 +1,266 @@
+use std::{path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc}};
+
+use eframe::egui::{self, pos2, CentralPanel, ComboBox, DragValue, RichText, ScrollArea, SidePanel, TextEdit};
+
+use crate::{engine::{uci::UciPosition, Board}, etc::data_dir, game::{match_runner::{load_openings, run_match, Adjudication, MatchConfig, MatchEvent, MatchLimit, MatchPlayer, MatchStats, SprtConfig}, uci_client::InfoScore}, ui::app::{AppScreen, MyApp}};
+
+/// Settings of one side in the engine match form.
+pub struct MatchSide {
+    pub engine: usize,
+    /// Option values on top of the engine's saved ones, "Name=Value" separated by ';'.
+    pub options: String,
+    pub limit: String,
+}
+
+impl Default for MatchSide {
+    fn default() -> Self {
+        Self { engine: 0, options: String::new(), limit: "st=0.5".to_string() }
+    }
+}
+
+/// The "Engine match" screen: match settings and the game that is being played.
+pub struct EngineMatchState {
+    pub sides: [MatchSide; 2],
+    pub games: u32,
+    pub openings: String,
+    pub pgn: String,
+    pub sprt: bool,
+    pub elo0: f64,
+    pub elo1: f64,
+    events: Option<Receiver<MatchEvent>>,
+    stop: Arc<AtomicBool>,
+    board: Board,
+    white: String,
+    black: String,
+    moves: Vec<String>,
+    score: Option<InfoScore>,
+    stats: MatchStats,
+    llr: Option<f64>,
+    results: Vec<String>,
+    status: String,
+}
+
+impl Default for EngineMatchState {
+    fn default() -> Self {
+        Self {
+            sides: [MatchSide::default(), MatchSide::default()],
+            games: 10,
+            openings: String::new(),
+            pgn: data_dir().join("match.pgn").to_string_lossy().to_string(),
+            sprt: false,
+            elo0: 0.0,
+            elo1: 10.0,
+            events: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            board: UciPosition { start_fen: None, moves: Vec::new() }.board(),
+            white: String::new(),
+            black: String::new(),
+            moves: Vec::new(),
+            score: None,
+            stats: MatchStats::default(),
+            llr: None,
+            results: Vec::new(),
+            status: String::new(),
+        }
+    }
+}
+
+impl EngineMatchState {
+    pub fn running(&self) -> bool {
+        self.events.is_some()
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    fn handle_event(&mut self, event: MatchEvent) {
+        match event {
+            MatchEvent::GameStarted { game, white, black, position } => {
+                self.status = format!("Game {} of {}", game + 1, self.games);
+                self.board = position.board();
+                self.white = white;
+                self.black = black;
+                self.moves.clear();
+                self.score = None;
+            }
+            MatchEvent::Move { position, san, score, .. } => {
+                self.board = position.board();
+                self.moves.push(san);
+                self.score = score;
+            }
+            MatchEvent::GameFinished { game, result, reason, stats, llr } => {
+                self.results.push(format!("{}. {} - {}  {} ({})", game + 1, self.white, self.black, result, reason));
+                self.stats = stats;
+                self.llr = llr;
+            }
+            MatchEvent::Finished { reason, .. } => {
+                self.status = format!("Finished: {}", reason);
+                self.events = None;
+            }
+            MatchEvent::Error(e) => {
+                self.status = e;
+            }
+        }
+    }
+}
+
+impl MyApp {
+    /// Builds the match from the form and runs it on a background thread.
+    fn start_engine_match(&mut self) {
+        let state = &mut self.engine_match;
+        let mut players = Vec::new();
+        for (i, side) in state.sides.iter().enumerate() {
+            let Some(mut config) = self.engines.engines.get(side.engine).cloned() else {
+                state.status = "Add an engine first".to_string();
+                return;
+            };
+            for pair in side.options.split(';').filter(|p| !p.trim().is_empty()) {
+                let Some((name, value)) = pair.split_once('=') else {
+                    state.status = format!("Option \"{}\" is not Name=Value", pair.trim());
+                    return;
+                };
+                config.values.insert(name.trim().to_string(), value.trim().to_string());
+            }
+            let Some(limit) = MatchLimit::parse(&side.limit) else {
+                state.status = format!("Bad time control \"{}\"", side.limit);
+                return;
+            };
+            let mut name = config.name.clone();
+            if !side.options.trim().is_empty() {
+                name = format!("{} ({})", name, side.options.trim());
+            } else if state.sides[0].engine == state.sides[1].engine {
+                name = format!("{} #{}", name, i + 1);
+            }
+            players.push(MatchPlayer { name, config, limit });
+        }
+        let openings = if state.openings.trim().is_empty() {
+            Vec::new()
+        } else {
+            match load_openings(&PathBuf::from(state.openings.trim())) {
+                Ok(openings) => openings,
+                Err(e) => {
+                    state.status = e;
+                    return;
+                }
+            }
+        };
+        let Ok(players) = <[MatchPlayer; 2]>::try_from(players) else { return };
+        let config = MatchConfig {
+            players,
+            openings,
+            games: state.games,
+            adjudication: Adjudication::default(),
+            sprt: state.sprt.then_some(SprtConfig { elo0: state.elo0, elo1: state.elo1, ..SprtConfig::default() }),
+            pgn_path: (!state.pgn.trim().is_empty()).then(|| PathBuf::from(state.pgn.trim())),
+            event: "Engine match".to_string(),
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let spawned = std::thread::Builder::new().name("engine_match".to_string()).spawn(move || {
+            run_match(config, tx, thread_stop);
+        });
+        if let Err(e) = spawned {
+            state.status = e.to_string();
+            return;
+        }
+        state.stop = stop;
+        state.events = Some(rx);
+        state.stats = MatchStats::default();
+        state.llr = None;
+        state.results.clear();
+        state.status = "Starting engines...".to_string();
+    }
+
+    pub fn render_engine_match(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Some(event) = self.engine_match.events.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.engine_match.handle_event(event);
+        }
+        let running = self.engine_match.running();
+        if running {
+            ctx.request_repaint();
+        }
+
+        SidePanel::left("match_settings").min_width(280.0).show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.engine_match.stop();
+                self.screen = AppScreen::MainMenu;
+            }
+            ui.heading("Engine match");
+            let names: Vec<String> = self.engines.engines.iter().map(|e| e.name.clone()).collect();
+            let state = &mut self.engine_match;
+            ui.add_enabled_ui(!running, |ui| {
+                for (i, side) in state.sides.iter_mut().enumerate() {
+                    ui.separator();
+                    ui.label(format!("Engine {}", i + 1));
+                    ComboBox::from_id_salt(("match_engine", i))
+                        .selected_text(names.get(side.engine).cloned().unwrap_or_default())
+                        .show_ui(ui, |ui| {
+                            for (index, name) in names.iter().enumerate() {
+                                ui.selectable_value(&mut side.engine, index, name);
+                            }
+                        });
+                    ui.add(TextEdit::singleline(&mut side.options).hint_text("Skill Level=10; Hash=64"));
+                    ui.horizontal(|ui| {
+                        ui.label("Time control");
+                        ui.add(TextEdit::singleline(&mut side.limit).desired_width(80.0));
+                    });
+                }
+                ui.label(RichText::new("40+0.4, st=1, depth=8 or nodes=10000").weak());
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Games");
+                    ui.add(DragValue::new(&mut state.games).range(1..=10_000));
+                });
+                ui.add(TextEdit::singleline(&mut state.openings).hint_text("openings .epd or .pgn"));
+                ui.add(TextEdit::singleline(&mut state.pgn).hint_text("save games to .pgn"));
+                ui.checkbox(&mut state.sprt, "Stop early with SPRT");
+                ui.add_enabled_ui(state.sprt, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Elo0");
+                        ui.add(DragValue::new(&mut state.elo0).speed(0.5));
+                        ui.label("Elo1");
+                        ui.add(DragValue::new(&mut state.elo1).speed(0.5));
+                    });
+                });
+            });
+            ui.separator();
+            if running {
+                if ui.button("Stop").clicked() {
+                    self.engine_match.stop();
+                }
+            } else if ui.button("Start").clicked() {
+                self.start_engine_match();
+            }
+            ui.label(&self.engine_match.status);
+        });
+
+        CentralPanel::default().show(ctx, |ui| {
+            let state = &self.engine_match;
+            ui.heading(format!("{} - {}", state.white, state.black));
+            let score = match state.score {
+                Some(InfoScore::Cp(cp)) => format!("{:+.2}", cp as f32 / 100.0),
+                Some(InfoScore::Mate(moves)) => format!("#{}", moves),
+                None => String::new(),
+            };
+            ui.label(score);
+            let square_size = (ui.available_width() / 14.0).clamp(24.0, 60.0);
+            let top_left = ui.cursor().min;
+            self.render_static_board(&state.board, pos2(top_left.x, top_left.y), square_size, ui);
+            ui.label(RichText::new(state.moves.join(" ")).monospace());
+            ui.separator();
+            ui.label(RichText::new(state.stats.to_string()).strong());
+            if let Some(llr) = state.llr {
+                let (lower, upper) = SprtConfig { elo0: state.elo0, elo1: state.elo1, ..SprtConfig::default() }.bounds();
+                ui.label(format!("LLR {:.2} ({:.2}, {:.2})", llr, lower, upper));
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                for result in state.results.iter().rev() {
+                    ui.label(result);
+                }
+            });
+        });
+    }
+}
@@ -7,4 +7,13 @@ pub mod game_info;
 pub mod screen_render;
 pub mod qote;
 pub mod popup_render;
+pub mod problem_render;
+pub mod engine_render;
+pub mod new_game_render;
+pub mod match_render;
+pub mod analysis_render;
+pub mod time_chart;
+pub mod history_render;
+pub mod move_list;
+pub mod multiplayer_render;
 pub use crate::etc::DEFAULT_FEN;
\ No newline at end of file
@@ -0,0 +1,140 @@
+use eframe::egui::{pos2, vec2, Align, Button, Color32, Grid, Pos2, Rect, RichText, ScrollArea, Ui, UiBuilder};
+
+use crate::{engine::{game_tree::GameTree, pgn::{nag_suffix, pgn_eval}, san::figurine, Board, PieceColor}, ui::app::MyApp};
+
+/// How moves are written in the move list.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Notation { San, Figurine }
+
+/// The move list next to the game board.
+pub struct MoveListState {
+    pub notation: Notation,
+    /// Position of the game picked in the list, shown on the board read-only, and the board
+    /// set up for it. `None` shows the game.
+    view: Option<(usize, Board)>,
+    /// Move highlighted last frame, to scroll to a newly highlighted one.
+    highlighted: usize,
+}
+
+impl Default for MoveListState {
+    fn default() -> Self {
+        Self { notation: Notation::San, view: None, highlighted: GameTree::ROOT }
+    }
+}
+
+impl MoveListState {
+    /// Board of the position picked in the move list, if one is.
+    pub fn viewed_board(&self) -> Option<&Board> {
+        self.view.as_ref().map(|(_, board)| board)
+    }
+
+    /// Stops viewing an earlier position and shows the game again.
+    pub fn back_to_game(&mut self) {
+        self.view = None;
+    }
+}
+
+impl MyApp {
+    /// Shows position `id` of the game's current line on the board, or the game itself for
+    /// its current position.
+    pub fn view_position(&mut self, id: usize) {
+        if id == self.board.meta_data.moves.current() {
+            self.move_list.view = None;
+            return;
+        }
+        let mut board = self.board.clone();
+        board.goto(id);
+        self.move_list.view = Some((id, board));
+    }
+
+    /// The moves of the game in SAN or figurines with their numbers, evaluations and
+    /// annotations, right of the board. Clicking a move shows its position; the buttons step
+    /// through the game.
+    pub fn render_move_history(&mut self, top_left: Pos2, ui: &mut Ui, is_visible: bool) {
+        if !is_visible {
+            return;
+        }
+        let square = self.ui.square_size;
+        let pad = self.ui.padding as f32;
+        let rect = Rect::from_min_size(pos2(top_left.x + 8.0 * square + pad, top_left.y), vec2(3.5 * square, 8.0 * square));
+        ui.painter().rect_filled(rect, 0.0, Color32::BLACK);
+
+        let tree = &self.board.meta_data.moves;
+        // the game line, starting with the start position
+        let mut positions = vec![GameTree::ROOT];
+        positions.extend(tree.path(tree.current()));
+        // a viewed position the game no longer passes through, e.g. after a new game
+        if let Some((id, board)) = &self.move_list.view {
+            let viewed_line = board.meta_data.moves.line().iter().map(|mv| mv.uci.clone()).collect::<Vec<_>>();
+            let line = tree.line().iter().map(|mv| mv.uci.clone()).collect::<Vec<_>>();
+            if !positions.contains(id) || !line.starts_with(&viewed_line) {
+                self.move_list.view = None;
+            }
+        }
+        let shown = self.move_list.view.as_ref().map_or(tree.current(), |(id, _)| *id);
+        let index = positions.iter().position(|id| *id == shown).unwrap_or(positions.len() - 1);
+        let scroll = shown != self.move_list.highlighted;
+        self.move_list.highlighted = shown;
+
+        let mut clicked = None;
+        ui.allocate_new_ui(UiBuilder::default().max_rect(rect.shrink(pad / 2.0)), |ui| {
+            ui.horizontal(|ui| {
+                let last = positions.len() - 1;
+                for (label, target, hint) in [("|<", 0, "First move"), ("<", index.saturating_sub(1), "Previous move"), (">", (index + 1).min(last), "Next move"), (">|", last, "Last move")] {
+                    if ui.add_enabled(target != index, Button::new(label)).on_hover_text(hint).clicked() {
+                        clicked = Some(positions[target]);
+                    }
+                }
+                let notation = &mut self.move_list.notation;
+                ui.selectable_value(notation, Notation::San, "N");
+                ui.selectable_value(notation, Notation::Figurine, "♘");
+            });
+            if self.move_list.view.is_some() {
+                ui.label(RichText::new("Viewing an earlier position").weak().small());
+            }
+            let meta = &self.board.meta_data;
+            let line = meta.moves.line();
+            ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                Grid::new("move_list").num_columns(3).striped(true).show(ui, |ui| {
+                    for (ply, record) in line.iter().enumerate() {
+                        let white = meta.mover(ply) == PieceColor::White;
+                        if white || ply == 0 {
+                            ui.label(RichText::new(format!("{}.", record.move_number)).weak());
+                        }
+                        if !white && ply == 0 {
+                            ui.label("...");
+                        }
+                        let san = match self.move_list.notation {
+                            Notation::San => record.san.clone(),
+                            Notation::Figurine => figurine(&record.san),
+                        };
+                        // an annotation from a PGN wins over the one from the evaluations
+                        let glyph = record.nags.iter().find_map(|nag| nag_suffix(*nag)).or_else(|| meta.annotation(ply)).unwrap_or("");
+                        let id = positions[ply + 1];
+                        let response = ui
+                            .horizontal(|ui| {
+                                let response = ui.selectable_label(id == shown, format!("{}{}", san, glyph));
+                                if let Some(eval) = record.evaluation {
+                                    ui.label(RichText::new(pgn_eval(eval)).weak().small());
+                                }
+                                response
+                            })
+                            .inner;
+                        if response.clicked() {
+                            clicked = Some(id);
+                        }
+                        if scroll && id == shown {
+                            response.scroll_to_me(Some(Align::Center));
+                        }
+                        if !white {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+        });
+        if let Some(id) = clicked {
+            self.view_position(id);
+        }
+    }
+}
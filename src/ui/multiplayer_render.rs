@@ -0,0 +1,202 @@
+use std::{ops::Range, time::Instant};
+
+use eframe::egui::{self, Grid, SidePanel, TextEdit};
+
+use crate::{engine::{board::GameResult, Board, PieceColor}, game::{clock::{ChessClock, TimeControl}, controller::{GameMode, LostBy}}, ui::{app::{AppScreen, MyApp}, new_game_render::time_control_picker, DEFAULT_FEN}};
+
+/// Settings and draw offers of a game between two players at this board.
+pub struct MultiplayerState {
+    pub white_name: String,
+    pub black_name: String,
+    /// Turn the board to the side to move after every move.
+    pub auto_flip: bool,
+    /// Play with a clock, using the time control typed in `time_control`.
+    timed: bool,
+    time_control: String,
+    /// Side that offered a draw and the plies of the game during which the offer stands:
+    /// until the other side has moved.
+    draw_offer: Option<(PieceColor, Range<usize>)>,
+}
+
+impl Default for MultiplayerState {
+    fn default() -> Self {
+        Self {
+            white_name: "White".to_string(),
+            black_name: "Black".to_string(),
+            auto_flip: false,
+            timed: false,
+            time_control: "10+0".to_string(),
+            draw_offer: None,
+        }
+    }
+}
+
+impl MultiplayerState {
+    fn time_control(&self) -> Option<TimeControl> {
+        self.timed.then(|| TimeControl::parse(&self.time_control)).flatten()
+    }
+}
+
+fn opponent(color: PieceColor) -> PieceColor {
+    match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    }
+}
+
+impl MyApp {
+    /// Whether a game between two players at this board is being played.
+    fn pvp_running(&self) -> bool {
+        matches!(self.game.mode, GameMode::PvP) && !self.game.game_over
+    }
+
+    /// Starts a game between the two players named, with the clock chosen. A game still
+    /// running is abandoned.
+    pub fn start_pvp_game(&mut self) {
+        self.abandon_game();
+        self.stop_following_game();
+        self.board = Board::from(&DEFAULT_FEN.to_owned());
+        self.move_list.back_to_game();
+        self.game.mode = GameMode::PvP;
+        self.game.game_over = false;
+        self.game.lost_by = None;
+        self.game.adaptive = false;
+        self.game.takebacks = true;
+        self.game.player = PieceColor::White;
+        self.game.enemey = PieceColor::Black;
+        let control = self.multiplayer.time_control();
+        self.game.clock = control.clone().map(ChessClock::new);
+        if let Some(clock) = &mut self.game.clock {
+            clock.start(self.board.turn);
+        }
+        self.game.move_started = Instant::now();
+        self.game.timed_moves = 0;
+        self.multiplayer.draw_offer = None;
+
+        let state = &self.multiplayer;
+        let name = |name: &str, fallback: &str| if name.trim().is_empty() { fallback.to_string() } else { name.trim().to_string() };
+        let meta = &mut self.board.meta_data;
+        meta.white_player_name = name(&state.white_name, "White");
+        meta.black_player_name = name(&state.black_name, "Black");
+        meta.time_control = control.as_ref().map_or("-".to_string(), TimeControl::pgn_time_control);
+    }
+
+    /// Turns the board to the side to move when asked to and lets a draw offer lapse once
+    /// the other side moved or the offer was taken back.
+    fn update_pvp(&mut self) {
+        if !self.pvp_running() {
+            return;
+        }
+        let ply = self.board.meta_data.moves.ply();
+        if self.multiplayer.draw_offer.as_ref().is_some_and(|(_, standing)| !standing.contains(&ply)) {
+            self.multiplayer.draw_offer = None;
+        }
+        if self.multiplayer.auto_flip && self.board.state.promtion_pending.is_none() {
+            self.board.state.pov = self.board.turn;
+        }
+    }
+
+    /// Resignation and the draw offer of `color`, with its clock.
+    fn render_pvp_side(&mut self, ui: &mut egui::Ui, color: PieceColor) {
+        let name = self.board.meta_data.player_name(color).to_string();
+        ui.horizontal(|ui| {
+            ui.strong(format!("{} ({:?})", name, color));
+            if self.game.clock.is_some() {
+                ui.label(self.clock_label(color));
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Resign").clicked() {
+                let (result, winner) = match color {
+                    PieceColor::White => (GameResult::BlackWin, PieceColor::Black),
+                    PieceColor::Black => (GameResult::WhiteWin, PieceColor::White),
+                };
+                let message = format!("{} resigned: {} wins", name, self.board.meta_data.player_name(winner));
+                self.end_game(result, LostBy::Resignation, message);
+                return;
+            }
+            match &self.multiplayer.draw_offer {
+                Some((by, _)) if *by == color => {
+                    ui.weak("Draw offered");
+                }
+                Some(_) => {
+                    if ui.button("Accept draw").clicked() {
+                        self.multiplayer.draw_offer = None;
+                        self.end_game(GameResult::Draw, LostBy::Draw, "Draw agreed".to_string());
+                    } else if ui.button("Decline").clicked() {
+                        self.multiplayer.draw_offer = None;
+                    }
+                }
+                None => {
+                    if ui.button("Offer draw").clicked() {
+                        // the offer stands until the other side's next move is made
+                        let ply = self.board.meta_data.moves.ply();
+                        let lapses = if self.board.turn == color { ply + 2 } else { ply + 1 };
+                        self.multiplayer.draw_offer = Some((color, ply..lapses));
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn render_multiplayer(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_clock();
+        self.update_pvp();
+        if !ctx.wants_keyboard_input() {
+            let (back, forward) = ctx.input(|i| (i.key_pressed(egui::Key::ArrowLeft), i.key_pressed(egui::Key::ArrowRight)));
+            if back {
+                self.take_back();
+            } else if forward {
+                self.redo();
+            }
+        }
+        SidePanel::left("multiplayer").min_width(250.0).show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.screen = AppScreen::MainMenu;
+            }
+            ui.heading("Two Players");
+            let running = self.pvp_running();
+            let state = &mut self.multiplayer;
+            let mut valid = true;
+            ui.add_enabled_ui(!running, |ui| {
+                Grid::new("pvp_names").num_columns(2).show(ui, |ui| {
+                    ui.label("White");
+                    ui.add(TextEdit::singleline(&mut state.white_name).desired_width(150.0));
+                    ui.end_row();
+                    ui.label("Black");
+                    ui.add(TextEdit::singleline(&mut state.black_name).desired_width(150.0));
+                    ui.end_row();
+                });
+                valid = time_control_picker(ui, &mut state.timed, &mut state.time_control);
+            });
+            ui.checkbox(&mut state.auto_flip, "Turn the board after each move");
+            ui.horizontal(|ui| {
+                if ui.add_enabled(valid, egui::Button::new(if running { "Restart" } else { "Start" })).clicked() {
+                    self.start_pvp_game();
+                }
+                if ui.button("Flip board").clicked() {
+                    self.board.state.pov = opponent(self.board.state.pov);
+                }
+            });
+            if !running {
+                return;
+            }
+            ui.separator();
+            // the side at the top of the board first
+            for color in [opponent(self.board.state.pov), self.board.state.pov] {
+                ui.group(|ui| self.render_pvp_side(ui, color));
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let allowed = self.can_take_back();
+                if ui.add_enabled(allowed, egui::Button::new("take back")).on_hover_text("Left arrow").clicked() {
+                    self.take_back();
+                }
+                if ui.add_enabled(allowed, egui::Button::new("redo")).on_hover_text("Right arrow").clicked() {
+                    self.redo();
+                }
+            });
+        });
+        self.render_game_board(ctx);
+    }
+}
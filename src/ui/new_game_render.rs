@@ -0,0 +1,234 @@
+use std::time::Instant;
+
+use eframe::egui::{self, DragValue};
+
+use crate::{engine::{Board, PieceColor}, game::{adaptive::{MAX_ENGINE_ELO, MIN_ENGINE_ELO}, clock::{ChessClock, TimeControl, PRESETS}, strength::EngineStrength}, ui::{app::MyApp, DEFAULT_FEN}};
+
+#[derive(Clone, Copy, PartialEq)]
+enum StrengthKind { Elo, Skill, Depth, Nodes, MoveTime }
+
+/// Settings picked in the "New game" window before a PvE game starts.
+pub struct NewGameDialog {
+    pub open: bool,
+    kind: StrengthKind,
+    elo: u32,
+    skill: u32,
+    depth: u32,
+    nodes: u64,
+    movetime_ms: u64,
+    /// Color of the human player, `None` for a random pick.
+    pub color: Option<PieceColor>,
+    pub adaptive: bool,
+    /// Play with a clock, using the time control typed in `time_control`.
+    timed: bool,
+    time_control: String,
+    /// Allow taking moves back. Rated (adaptive) games never do.
+    pub takebacks: bool,
+}
+
+impl Default for NewGameDialog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            kind: StrengthKind::Elo,
+            elo: 1500,
+            skill: 16,
+            depth: 12,
+            nodes: 100_000,
+            movetime_ms: 1000,
+            color: None,
+            adaptive: false,
+            timed: false,
+            time_control: "10+0".to_string(),
+            takebacks: true,
+        }
+    }
+}
+
+impl NewGameDialog {
+    pub fn strength(&self) -> EngineStrength {
+        match self.kind {
+            StrengthKind::Elo => EngineStrength::Elo(self.elo),
+            StrengthKind::Skill => EngineStrength::Skill(self.skill),
+            StrengthKind::Depth => EngineStrength::Depth(self.depth),
+            StrengthKind::Nodes => EngineStrength::Nodes(self.nodes),
+            StrengthKind::MoveTime => EngineStrength::MoveTime(self.movetime_ms),
+        }
+    }
+
+    /// Loads a previously used strength so the dialog opens on the last setting.
+    pub fn set_strength(&mut self, strength: EngineStrength) {
+        match strength {
+            EngineStrength::Elo(elo) => { self.kind = StrengthKind::Elo; self.elo = elo; }
+            EngineStrength::Skill(skill) => { self.kind = StrengthKind::Skill; self.skill = skill; }
+            EngineStrength::Depth(depth) => { self.kind = StrengthKind::Depth; self.depth = depth; }
+            EngineStrength::Nodes(nodes) => { self.kind = StrengthKind::Nodes; self.nodes = nodes; }
+            EngineStrength::MoveTime(ms) => { self.kind = StrengthKind::MoveTime; self.movetime_ms = ms; }
+        }
+    }
+
+    /// Time control of the game, `None` without a clock or when the text does not parse.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.timed.then(|| TimeControl::parse(&self.time_control)).flatten()
+    }
+
+    /// Loads the previous game's time control.
+    pub fn set_time_control(&mut self, control: Option<&TimeControl>) {
+        self.timed = control.is_some();
+        if let Some(control) = control {
+            self.time_control = control.to_string();
+        }
+    }
+}
+
+/// "Clock" checkbox with the preset time controls and a field for any other. Returns
+/// whether the settings can start a game: no clock, or a time control that parses.
+pub fn time_control_picker(ui: &mut egui::Ui, timed: &mut bool, time_control: &mut String) -> bool {
+    ui.checkbox(timed, "Clock");
+    let valid = !*timed || TimeControl::parse(time_control).is_some();
+    ui.add_enabled_ui(*timed, |ui| {
+        ui.horizontal_wrapped(|ui| {
+            for preset in PRESETS {
+                if ui.selectable_label(time_control == preset, preset).clicked() {
+                    *time_control = preset.to_string();
+                }
+            }
+        });
+        ui.text_edit_singleline(time_control).on_hover_text(
+            "Minutes, then the bonus per move in seconds: 5+3 increment, 5+3d simple delay, \
+             5+3b Bronstein delay. 40/90, 30+30 is 40 moves in 90 minutes, then 30 minutes more.",
+        );
+        if !valid {
+            ui.colored_label(ui.visuals().error_fg_color, "Not a time control, e.g. 5+3 or 40/90, 30+30");
+        }
+    });
+    valid
+}
+
+impl MyApp {
+    pub fn render_new_game_dialog(&mut self, ctx: &egui::Context) {
+        if !self.new_game.open {
+            return;
+        }
+        let mut start = false;
+        let mut cancel = false;
+        egui::Window::new("New game")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let profile = &self.game.profile;
+                let dialog = &mut self.new_game;
+                ui.checkbox(&mut dialog.adaptive, "Adaptive opponent");
+                if dialog.adaptive {
+                    ui.label(format!(
+                        "Your rating: {:.0} after {} games, the engine plays at Elo {}",
+                        profile.rating,
+                        profile.games.len(),
+                        profile.suggested_engine_elo()
+                    ));
+                }
+                ui.add_enabled_ui(!dialog.adaptive, |ui| {
+                    ui.label("Engine strength");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut dialog.kind, StrengthKind::Elo, "Elo");
+                        ui.radio_value(&mut dialog.kind, StrengthKind::Skill, "Skill level");
+                        ui.radio_value(&mut dialog.kind, StrengthKind::Depth, "Depth");
+                        ui.radio_value(&mut dialog.kind, StrengthKind::Nodes, "Nodes");
+                        ui.radio_value(&mut dialog.kind, StrengthKind::MoveTime, "Move time");
+                    });
+                    match dialog.kind {
+                        StrengthKind::Elo => ui.add(DragValue::new(&mut dialog.elo).range(MIN_ENGINE_ELO..=MAX_ENGINE_ELO).suffix(" Elo")),
+                        StrengthKind::Skill => ui.add(DragValue::new(&mut dialog.skill).range(0..=20)),
+                        StrengthKind::Depth => ui.add(DragValue::new(&mut dialog.depth).range(1..=40).suffix(" plies")),
+                        StrengthKind::Nodes => ui.add(DragValue::new(&mut dialog.nodes).range(1..=100_000_000).speed(1000).suffix(" nodes")),
+                        StrengthKind::MoveTime => ui.add(DragValue::new(&mut dialog.movetime_ms).range(50..=60_000).speed(50).suffix(" ms")),
+                    };
+                });
+                ui.separator();
+                let valid = time_control_picker(ui, &mut dialog.timed, &mut dialog.time_control);
+                ui.separator();
+                if dialog.adaptive {
+                    ui.add_enabled(false, egui::Checkbox::new(&mut false, "Allow takebacks"))
+                        .on_disabled_hover_text("Adaptive games are rated and cannot be taken back");
+                } else {
+                    ui.checkbox(&mut dialog.takebacks, "Allow takebacks");
+                }
+                ui.separator();
+                ui.label("Play as");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut dialog.color, None, "Random");
+                    ui.radio_value(&mut dialog.color, Some(PieceColor::White), "White");
+                    ui.radio_value(&mut dialog.color, Some(PieceColor::Black), "Black");
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    start = ui.add_enabled(valid, egui::Button::new("Start")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        if start {
+            self.new_game.open = false;
+            self.start_pve_game(ctx);
+        } else if cancel {
+            self.new_game.open = false;
+        }
+    }
+
+    /// Resets the board and starts an engine with the strength from the new game dialog.
+    pub fn start_pve_game(&mut self, ctx: &egui::Context) {
+        self.abandon_game();
+        self.stop_following_game();
+        self.board = Board::from(&DEFAULT_FEN.to_owned());
+        self.game.game_over = false;
+        self.game.adaptive = self.new_game.adaptive;
+        self.game.takebacks = self.new_game.takebacks && !self.game.adaptive;
+        self.game.accuracy.clear();
+        self.game.time_control = self.new_game.time_control();
+        self.game.clock = self.game.time_control.clone().map(ChessClock::new);
+        if let Some(clock) = &mut self.game.clock {
+            clock.start(self.board.turn);
+        }
+        self.game.move_started = Instant::now();
+        self.game.timed_moves = 0;
+        self.game.strength = if self.game.adaptive {
+            EngineStrength::Elo(self.game.profile.suggested_engine_elo())
+        } else {
+            self.new_game.strength()
+        };
+        let player_color = match self.new_game.color {
+            Some(color) => color,
+            None => [PieceColor::White, PieceColor::Black][rand::random::<u32>() as usize % 2],
+        };
+        self.game.player = player_color;
+        self.game.enemey = match player_color {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        if self.board.state.pov != self.game.player {
+            self.board.state.pov = self.game.player;
+            ctx.request_repaint();
+        }
+
+        let engine_name = self.engines.selected().map(|e| e.name.clone()).unwrap_or_default();
+        let player_elo = self.game.profile.rating.round() as u32;
+        let meta = &mut self.board.meta_data;
+        meta.time_control = self.game.time_control.as_ref().map_or("-".to_string(), TimeControl::pgn_time_control);
+        match self.game.enemey {
+            PieceColor::White => {
+                meta.white_player_name = engine_name;
+                meta.white_player_elo = self.game.strength.elo();
+                meta.black_player_name = "Player".to_string();
+                meta.black_player_elo = player_elo;
+            }
+            PieceColor::Black => {
+                meta.black_player_name = engine_name;
+                meta.black_player_elo = self.game.strength.elo();
+                meta.white_player_name = "Player".to_string();
+                meta.white_player_elo = player_elo;
+            }
+        }
+
+        self.start_stockfish();       // ← start the cmd_rx loop right away
+    }
+}
@@ -1,6 +1,6 @@
 use eframe::egui;
 
-use crate::{engine::Board, ui::{app::{MyApp, PopupType}, DEFAULT_FEN}};
+use crate::{engine::Board, ui::{app::{MyApp, PopupType}, history_render::export_pgn, DEFAULT_FEN}};
 
 
 
@@ -15,7 +15,12 @@ impl MyApp {
                             .show(ctx, |ui| {
                                 ui.vertical_centered(|ui| {
                                     ui.label(msg);
+                                    if ui.button("Export PGN").clicked() {
+                                        self.history.status = export_pgn(ctx, &self.board.meta_data);
+                                    }
+                                    ui.label(&self.history.status);
                                     if ui.button("x").clicked(){
+                                        self.history.status.clear();
                                         self.popup = None;
                                         self.board = Board::from(&DEFAULT_FEN.to_owned());
                                         self.game.game_over = true;
@@ -23,6 +28,20 @@ impl MyApp {
                                 });
                             });
             }
+            PopupType::EngineError(msg) => {
+                egui::Window::new("Engine problem")
+                            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                            .collapsible(false)
+                            .resizable(false)
+                            .show(ctx, |ui| {
+                                ui.vertical_centered(|ui| {
+                                    ui.label(msg);
+                                    if ui.button("ok").clicked() {
+                                        self.popup = None;
+                                    }
+                                });
+                            });
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use std::sync::mpsc::{self, Receiver};
+
+use eframe::egui::{self, CentralPanel, RichText, TextEdit};
+
+use crate::{engine::problem::solve_with_limit, ui::app::{AppScreen, MyApp}};
+
+pub struct ProblemSolverState {
+    pub fen: String,
+    pub stipulation: String,
+    /// Stop searching at the second solution, enough to call the problem cooked.
+    pub stop_at_cook: bool,
+    pub output: String,
+    pub result_rx: Option<Receiver<String>>,
+}
+
+impl Default for ProblemSolverState {
+    fn default() -> Self {
+        Self {
+            fen: String::new(),
+            stipulation: String::from("#2"),
+            stop_at_cook: true,
+            output: String::new(),
+            result_rx: None,
+        }
+    }
+}
+
+impl MyApp {
+    pub fn render_problem_solver(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // pick up the result of a running solve
+        if let Some(rx) = &self.problem.result_rx {
+            if let Ok(output) = rx.try_recv() {
+                self.problem.output = output;
+                self.problem.result_rx = None;
+            } else {
+                ctx.request_repaint();
+            }
+        }
+        CentralPanel::default().show(ctx, |ui| {
+            if ui.button("back").clicked() {
+                self.screen = AppScreen::MainMenu;
+            }
+            ui.heading("Problem solver");
+            ui.label("Position (FEN)");
+            ui.add(TextEdit::singleline(&mut self.problem.fen).desired_width(f32::INFINITY));
+            ui.label("Stipulation (#2, h#3, h#2.5, s#2)");
+            ui.add(TextEdit::singleline(&mut self.problem.stipulation).desired_width(120.0));
+            ui.checkbox(&mut self.problem.stop_at_cook, "Stop at the first cook");
+            let solving = self.problem.result_rx.is_some();
+            if ui.add_enabled(!solving, egui::Button::new("Solve")).clicked() {
+                let (tx, rx) = mpsc::channel();
+                let fen = self.problem.fen.clone();
+                let stipulation = self.problem.stipulation.clone();
+                let limit = self.problem.stop_at_cook.then_some(2);
+                let _ = std::thread::Builder::new().name("problem_solver".to_string()).spawn(move || {
+                    let output = match solve_with_limit(&fen, &stipulation, limit) {
+                        Ok(report) => {
+                            let verdict = if report.is_sound() {
+                                "Sound".to_string()
+                            } else if report.solutions.is_empty() {
+                                "Unsolvable".to_string()
+                            } else if !report.complete {
+                                "Cooked".to_string()
+                            } else {
+                                format!("Cooked: {} solutions", report.solutions.len())
+                            };
+                            format!("{}\n\n{}", verdict, report)
+                        }
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    let _ = tx.send(output);
+                });
+                self.problem.result_rx = Some(rx);
+                self.problem.output.clear();
+            }
+            if solving {
+                ui.spinner();
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.label(RichText::new(&self.problem.output).monospace());
+            });
+        });
+    }
+}
@@ -1,5 +1,5 @@
-use std::{default, fs, path::Path, vec};
-use rand::{seq::{IndexedRandom, SliceRandom}, thread_rng};
+use std::{fs, path::Path};
+use rand::seq::IndexedRandom;
 
 use serde::Deserialize;
 use serde_json;
@@ -29,9 +29,9 @@ impl MyApp {
             Err(e) => {println!("{:?}", e); return default;}
         };
 
-        return match qoute_vec.choose(&mut rand::rng()){
-            Some(quote) => String::from(format!("- {} -", quote.quote)),
+        match qoute_vec.choose(&mut rand::rng()){
+            Some(quote) => format!("- {} -", quote.quote),
             None => default,
-        };
+        }
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
-use eframe::{egui::{pos2, vec2, Color32, CornerRadius, Painter, Pos2, Rect, RichText, Sense, Stroke, Ui, UiBuilder}, egui_glow::painter, glow::TEXTURE14};
+use eframe::egui::{pos2, vec2, Align2, Color32, Painter, Pos2, Rect, FontId, Sense, Stroke, Ui};
 
-use crate::{engine::{ChessPiece, PieceColor}, ui::app::MyApp};
+use crate::{engine::{score::{Score, Wdl}, Board, ChessPiece, PieceColor}, ui::app::MyApp};
 
 impl MyApp{
 
@@ -48,10 +48,15 @@ impl MyApp{
             // pull the piece out of the mapped board cell
             
             let piece = self.board.squares[board_rank][board_file];
-            self.render_selected(&piece, &rect, painter);
-            self.render_piece(&piece, &rect, &painter);
-            self.render_quiet_move(&(board_rank as u8, board_file as u8), &rect, &painter);
-            self.render_capture_move(&(board_rank as u8, board_file as u8), &rect, &painter);
+            // a position picked in the move list is shown without the game's highlights
+            if let Some(viewed) = self.move_list.viewed_board() {
+                self.render_piece(&viewed.squares[board_rank][board_file], &rect, painter);
+            } else {
+                self.render_selected(&piece, &rect, painter);
+                self.render_piece(&piece, &rect, painter);
+                self.render_quiet_move(&(board_rank as u8, board_file as u8), &rect, painter);
+                self.render_capture_move(&(board_rank as u8, board_file as u8), &rect, painter);
+            }
             self.handle_board_interaction_logic(
                 &piece,
                 &(board_rank as u8, board_file as u8),
@@ -60,136 +65,86 @@ impl MyApp{
         }
     }
 }
-pub fn render_quiet_move(&self, poz :&(u8, u8), rect: &Rect, painter: &Painter){
-    match &self.board.state.quiet_moves {
-        Some(moves) => {
-            if moves.contains(poz) {
-                let center = rect.center();
-                let radius = self.ui.square_size * 0.225;
-                painter.circle_filled(center, radius, 
-                 if (poz.0 + poz.1) % 2 ==0 {self.theme.light_pseudo_move_highlight} else {self.theme.dark_pseudo_move_highlight});
-            }
-            else {return;}
+/// Draws a board without highlights or input handling, from White's side.
+pub fn render_static_board(&self, board: &Board, top_left: Pos2, square_size: f32, ui: &mut Ui) {
+    let painter = ui.painter();
+    for rank in 0..8 {
+        for file in 0..8 {
+            let rect = Rect::from_min_size(pos2(top_left.x + file as f32 * square_size, top_left.y + rank as f32 * square_size), vec2(square_size, square_size));
+            let color = if (rank + file) % 2 == 0 { self.theme.light_square } else { self.theme.dark_square };
+            painter.rect_filled(rect, 0.0, color.to_opaque());
+            self.render_piece(&board.squares[rank][file], &rect, painter);
         }
-        None => {return;}
+    }
+    ui.allocate_rect(Rect::from_min_size(top_left, vec2(8.0 * square_size, 8.0 * square_size)), Sense::hover());
+}
+pub fn render_quiet_move(&self, poz :&(u8, u8), rect: &Rect, painter: &Painter){
+    if let Some(moves) = &self.board.state.quiet_moves
+        && moves.contains(poz) {
+        let center = rect.center();
+        let radius = self.ui.square_size * 0.225;
+        painter.circle_filled(center, radius, 
+         if (poz.0 + poz.1).is_multiple_of(2) {self.theme.light_pseudo_move_highlight} else {self.theme.dark_pseudo_move_highlight});
     }
 }
 pub fn render_capture_move(&self, poz :&(u8, u8), rect: &Rect, painter: &Painter){
-        match &self.board.state.capture_moves {
-            Some(moves) => {
-                
-                if moves.contains(poz) {
-                                    let center = rect.center();
-                                    let radius = self.ui.square_size * 0.225;
-                                    painter.circle_stroke(
-                                        center,
-                                        radius,
-                                        Stroke::from((
-                                            2.5,
-                                            if (poz.0 + poz.1) % 2 == 0 {
-                                                self.theme.light_pseudo_move_highlight
-                                            } else {
-                                                self.theme.dark_pseudo_move_highlight
-                                            },
-                                        )),
-                                    );
-                                }
-                            
-                        }
-                        None => return,
-                    }
-                    
-                
+    if let Some(moves) = &self.board.state.capture_moves
+        && moves.contains(poz) {
+        let center = rect.center();
+        let radius = self.ui.square_size * 0.225;
+        painter.circle_stroke(
+            center,
+            radius,
+            Stroke::from((
+                2.5,
+                if (poz.0 + poz.1).is_multiple_of(2) {
+                    self.theme.light_pseudo_move_highlight
+                } else {
+                    self.theme.dark_pseudo_move_highlight
+                },
+            )),
+        );
+    }
 }
 pub fn render_selected(&self, piece: &Option<ChessPiece>, rect: &Rect, painter: &Painter){
-    match piece {    
-        Some(p) =>{
-            match self.board.state.selected_piece{
-                Some (selected_piece) => {
-
-                    if p.position == selected_piece.position {
-                        painter.rect_filled(*rect, 0.0, self.theme.square_select_highlight);
-                    }
-                    
-                }
-                None => {
-
-                }
-            }
-            match self.board.state.moved_from{
-                Some (pos) => {
-
-                    if p.position == pos {
-                        painter.rect_filled(*rect, 0.0, self.theme.moved_from_highlight.to_opaque());
-                    }
-                    
-                }
-                None => {
-
-                }
-            }
-            match self.board.state.moved_to{
-                Some (pos) => {
-
-                    if p.position == pos {
-                        painter.rect_filled(*rect, 0.0, self.theme.moved_from_highlight);
-                    }
-                    
-                }
-                None => {
-
-                }
-            }
-            // highlight king in check or checkmate
-            match self.board.state.checkmate_square {
-                Some(pos) => {
-                    if p.position == pos {
-                        painter.rect_filled(*rect, 0.0, self.theme.checkmate_square);
-                    }
-                }
-                None => {}
-            } 
-
-
-        }
-        None => {
-
-        }
+    let Some(p) = piece else { return };
+    if let Some(selected_piece) = self.board.state.selected_piece
+        && p.position == selected_piece.position {
+        painter.rect_filled(*rect, 0.0, self.theme.square_select_highlight);
+    }
+    if self.board.state.moved_from == Some(p.position) {
+        painter.rect_filled(*rect, 0.0, self.theme.moved_from_highlight.to_opaque());
+    }
+    if self.board.state.moved_to == Some(p.position) {
+        painter.rect_filled(*rect, 0.0, self.theme.moved_from_highlight);
+    }
+    // highlight king in check or checkmate
+    if self.board.state.checkmate_square == Some(p.position) {
+        painter.rect_filled(*rect, 0.0, self.theme.checkmate_square);
     }
 }
 pub fn render_piece(&self, piece: &Option<ChessPiece>, rect: &Rect, painter: &Painter){
-    match piece {
-        Some(p) =>{
-            painter.image(
-            match self.theme.piece_map.get(&(p.kind, p.color)) {
-                Some(rez) => {
-                    match rez {
-                        Ok(texture) => {
-                            texture.id()
-                        }
-                        Err(err) =>{
-                            self.theme.empty_texture.id()
-                        }
-                    }
-                }
-                None => {
-                    self.theme.empty_texture.id()
-                }
-            },
+    if let Some(p) = piece {
+        let texture = match self.theme.piece_map.get(&(p.kind, p.color)) {
+            Some(Ok(texture)) => texture.id(),
+            _ => self.theme.empty_texture.id(),
+        };
+        painter.image(
+            texture,
             *rect,
             Rect { min: Pos2 { x: 0.0, y: 0.0 }, max: Pos2{ x: 1.0, y:1.0}},
             Color32::WHITE
         );
     }
-    None => {
-
-    }
-}
 }
 pub fn render_eval_bar(&self, top_left: Pos2, ui: &mut Ui, is_visible: bool){
     if !is_visible {return;}
+    let state = &self.board.state;
+    self.draw_eval_bar(top_left, 8.0 * self.ui.square_size, state.current_evaluation, state.current_wdl, state.pov, ui);
+}
+/// Eval bar of `bar_height` left of a board at `top_left` seen from `pov`'s side.
+pub fn draw_eval_bar(&self, top_left: Pos2, bar_height: f32, score: Score, wdl: Wdl, pov: PieceColor, ui: &mut Ui){
     let painter = ui.painter();
-    let bar_height = 8.0 * self.ui.square_size;
     let bar_width = 20.0;
 
     let bar_color = Color32::BLACK;
@@ -197,21 +152,13 @@ pub fn render_eval_bar(&self, top_left: Pos2, ui: &mut Ui, is_visible: bool){
 
     let eval_x= top_left.x -20.0 - self.ui.padding as f32;
     let mut eval_y = top_left.y;
-    
-    let mut eval_side: f32 =1.0;
-    
-    if self.board.state.pov != PieceColor::White {
-        eval_side*=-1.0;
-    }
-    
 
-    let eval_height = bar_height/2.0 + (bar_height* eval_side*self.board.state.current_evaluation/1000.0).min(self.ui.square_size*3.0);
-    if self.board.state.pov == PieceColor::White {
-        eval_y = top_left.y + self.ui.square_size*8.0 - eval_height;
+    // White's share of the bar is its expected score, whichever way the board faces
+    let eval_height = bar_height * wdl.expected_score();
+    if pov == PieceColor::White {
+        eval_y = top_left.y + bar_height - eval_height;
     }
-   
-    
-    
+
     //base of the bar
     let bar_rect = Rect::from_min_size(
         pos2(top_left.x - 20.0 - self.ui.padding as f32, top_left.y),
@@ -231,84 +178,22 @@ let eval_rect = Rect::from_min_size(
             pos2(top_left.x - 40.0 - self.ui.padding as f32, top_left.y+ bar_height/2.0),
             vec2(bar_width, bar_height/8.0),
         ), 0.0, Color32::RED);
-    
-}
-pub fn render_move_history(&self, top_left: Pos2, ui: &mut Ui, is_visible: bool){
-    if !is_visible {return;}
-    let mut k=0;
-    let test_moves = &self.board.meta_data.move_list;
-      
-    let painter = ui.painter();
-    let bar_height = self.ui.square_size * 8.0;
-    let bar_width = self.ui.square_size*2.0;
-    let pad = self.ui.padding as f32;
-    let move_text_size = (bar_width * 0.15).min(12.0);
-    let (history_x, history_y) = (top_left.x + self.ui.square_size*8.0+pad, top_left.y);
-    let rect = Rect::from_min_size(pos2(history_x, history_y), vec2(bar_width, bar_height));
-    painter.rect_filled(rect, 0.0, Color32::BLACK);
-    ui.allocate_new_ui(
-        UiBuilder::default().max_rect(rect),
-        |ui| {
-            ui.vertical(|ui| {
-                
-                let move_rect_width = ui.available_width()*0.4;
-
-                let move_rect_height = bar_width * 0.2;
-                
-                let square_row_count = (bar_height / move_rect_height).round() as usize;
-                
-                for row in 0..square_row_count {
-                    let row_num_rect = Rect::from_min_size(
-                        pos2(history_x, history_y+pad + (move_rect_height * row as f32)), 
-                        vec2(bar_width*0.2, move_rect_height));
-
-                    let ply1_rect = Rect::from_min_size(
-                        pos2(history_x+bar_width*0.2, history_y+ pad + (move_rect_height * row as f32)), 
-                        vec2(move_rect_width, move_rect_height));
-                    let ply2_rect = Rect::from_min_size(
-                        pos2(history_x +bar_width*0.2+ move_rect_width, history_y+ pad + (move_rect_height * row as f32)), 
-                        vec2(move_rect_width, move_rect_height));
-                   
-                    let painter = ui.painter();
-                    painter.rect_filled(row_num_rect, 0.0, Color32::BLACK);
-                    painter.rect_filled(ply1_rect, 0.0, Color32::BLACK);
-                    painter.rect_filled(ply2_rect, 0.0, Color32::BLACK);
-                    ui.allocate_new_ui(UiBuilder::default().max_rect(ply1_rect), |ui|{
-                        ui.horizontal_centered(|ui|{
-                            ui.add_space(pad);
-                            if k < test_moves.len() {
-                                ui.label(RichText::new(test_moves[k].uci.clone()).size(move_text_size));
-                            }
-                        })
-                    });
-                    k += 1;
-                    ui.allocate_new_ui(UiBuilder::default().max_rect(ply2_rect), |ui|{
-                        ui.horizontal_centered(|ui|{
-                            ui.add_space(pad);
-                            if k < test_moves.len() {
-                                ui.label(RichText::new(test_moves[k].uci.clone()).size(move_text_size));
-                            }
-                        })
-                    });
-                    ui.allocate_new_ui(UiBuilder::default().max_rect(row_num_rect), |ui|{
-                        ui.horizontal_centered(|ui|{
-                            ui.add_space(pad);
-                            if k <= test_moves.len() {
-                                ui.label(RichText::new(format!("{}.", row)).size(move_text_size));
-                            }
-                        })
-                    });
-                    k+=1;
-                    
-                    
-                }
-                
-                
-            });
-        },
-    );
-    
-
+    // the score is written at the end of the side that is ahead
+    let white_ahead = score.centipawns() >= 0;
+    let at_bottom = white_ahead == (pov == PieceColor::White);
+    let (label_pos, align) = if at_bottom {
+        (pos2(bar_rect.center().x, bar_rect.bottom() - 3.0), Align2::CENTER_BOTTOM)
+    } else {
+        (pos2(bar_rect.center().x, bar_rect.top() + 3.0), Align2::CENTER_TOP)
+    };
+    let text_color = if white_ahead { Color32::BLACK } else { Color32::WHITE };
+    let label = match score {
+        Score::Mate(moves) => format!("M{}", moves.abs()),
+        Score::Cp(cp) => format!("{:.1}", (cp as f32/100.0).abs()),
+    };
+    painter.text(label_pos, align, label, FontId::proportional(9.0), text_color);
+    let (win, draw, loss) = wdl.percentages();
+    ui.interact(bar_rect, ui.id().with("eval_bar"), Sense::hover())
+        .on_hover_text(format!("White wins {:.0}%\nDraw {:.0}%\nBlack wins {:.0}%", win, draw, loss));
 }
-
 }
\ No newline at end of file
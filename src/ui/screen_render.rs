@@ -1,6 +1,6 @@
 use eframe::egui::{self, vec2, Button, CentralPanel, Color32, CornerRadius, Frame, SidePanel, Stroke};
 
-use crate::{engine::{Board, PieceColor, PieceType}, game::{controller::{GameController, GameMode}, stockfish_engine::StockfishCmd}, ui::{app::{AppScreen, MyApp}, DEFAULT_FEN}};
+use crate::{engine::{board::GameResult, Board, PieceColor, PieceType}, game::{controller::{GameMode, LostBy}, stockfish_engine::StockfishCmd}, ui::{app::{AppScreen, MyApp}, DEFAULT_FEN}};
 
 
 
@@ -74,19 +74,67 @@ impl MyApp{
                         
                         ui.add_space(12.0 * scale);
 
+                        let pvp_btn = Button::new(egui::RichText::new("Two Players").raised().strong().size(18.0))
+                        .corner_radius(CornerRadius::from(5.0))
+                        .min_size(vec2(button_width, 40.0)); 
+
+                        ui.add_space(12.0 * scale);
+
                         let history_btn = Button::new(egui::RichText::new("Game History").raised().strong().size(18.0))
                         .corner_radius(CornerRadius::from(5.0))
                         .min_size(vec2(button_width, 40.0)); 
 
                         ui.add_space(12.0 * scale);
 
+                        let analysis_btn = Button::new(egui::RichText::new("Analysis").raised().strong().size(18.0))
+                        .corner_radius(CornerRadius::from(5.0))
+                        .min_size(vec2(button_width, 40.0)); 
+
+                        ui.add_space(12.0 * scale);
+
+                        let problem_btn = Button::new(egui::RichText::new("Problem Solver").raised().strong().size(18.0))
+                        .corner_radius(CornerRadius::from(5.0))
+                        .min_size(vec2(button_width, 40.0)); 
+
+                        ui.add_space(12.0 * scale);
+
+                        let engines_btn = Button::new(egui::RichText::new("Engines").raised().strong().size(18.0))
+                        .corner_radius(CornerRadius::from(5.0))
+                        .min_size(vec2(button_width, 40.0)); 
+
+                        ui.add_space(12.0 * scale);
+
+                        let match_btn = Button::new(egui::RichText::new("Engine Match").raised().strong().size(18.0))
+                        .corner_radius(CornerRadius::from(5.0))
+                        .min_size(vec2(button_width, 40.0)); 
+
                         if ui.add(train_btn).clicked() {
                             self.screen = AppScreen::TrainWithAi;
                         }
                         ui.add_space(4.0);
+                        if ui.add(pvp_btn).clicked() {
+                            self.screen = AppScreen::Multiplayer;
+                        }
+                        ui.add_space(4.0);
                         if ui.add(history_btn).clicked() {
                             self.screen = AppScreen::History;
                         }
+                        ui.add_space(4.0);
+                        if ui.add(analysis_btn).clicked() {
+                            self.open_analysis();
+                        }
+                        ui.add_space(4.0);
+                        if ui.add(problem_btn).clicked() {
+                            self.screen = AppScreen::ProblemSolver;
+                        }
+                        ui.add_space(4.0);
+                        if ui.add(engines_btn).clicked() {
+                            self.screen = AppScreen::EngineSettings;
+                        }
+                        ui.add_space(4.0);
+                        if ui.add(match_btn).clicked() {
+                            self.screen = AppScreen::EngineMatch;
+                        }
                             
                     } );
                     
@@ -98,13 +146,27 @@ impl MyApp{
 
     
     pub fn render_train_with_ai(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame){
+        self.update_analysis();
+        self.update_clock();
+        if !ctx.wants_keyboard_input() {
+            let (back, forward) = ctx.input(|i| (i.key_pressed(egui::Key::ArrowLeft), i.key_pressed(egui::Key::ArrowRight)));
+            if back {
+                self.take_back();
+            } else if forward {
+                self.redo();
+            }
+        }
         SidePanel::left("menu")
             .resizable(true)
             .min_width(250.0)
             .default_width(250.0)
             .show(ctx, |ui| {
+                if ui.button("back").clicked() {
+                    self.stop_following_game();
+                    self.screen = AppScreen::MainMenu;
+                }
                 ui.heading("Chess");
-                ui.label(&self.board.to_string());
+                ui.label(self.board.to_string());
                 ui.label(format!(
                     "piesa: {:?}",
                     match self.board.state.selected_piece {
@@ -127,8 +189,19 @@ impl MyApp{
                     ctx.request_repaint();
                 };
                 if ui.button("reset-board").clicked() {
+                    self.abandon_game();
+                    self.stop_following_game();
                     self.board = Board::from(&DEFAULT_FEN.to_owned());
                 };
+                ui.horizontal(|ui| {
+                    let allowed = self.can_take_back();
+                    if ui.add_enabled(allowed, egui::Button::new("take back")).on_hover_text("Left arrow").clicked() {
+                        self.take_back();
+                    }
+                    if ui.add_enabled(allowed, egui::Button::new("redo")).on_hover_text("Right arrow").clicked() {
+                        self.redo();
+                    }
+                });
                 ui.separator();
                 if ui.button("gameMode: Sandbox").clicked() {
                     self.game.mode = GameMode::Sandbox;
@@ -137,42 +210,44 @@ impl MyApp{
                     self.game.mode = GameMode::PvE;
                 }
                 if ui.button("start-game").clicked() {
-                   
-                    self.board = Board::from(&DEFAULT_FEN.to_owned());
-                   
-                    self.game.game_over = false;
-                    let colors = [PieceColor::White, PieceColor::Black];
-                    let player_color = colors[rand::random::<i32>() as usize % 2];
-                    self.game.player = player_color;
-                    self.game.enemey = match player_color {
-                        PieceColor::White => PieceColor::Black,
-                        PieceColor::Black => PieceColor::White,
-                    };
-                    if self.board.state.pov != self.game.player {
-                        self.board.state.pov = match self.board.state.pov {
-                            PieceColor::White => PieceColor::Black,
-                            PieceColor::Black => PieceColor::White,
-                        };
-                        ctx.request_repaint();
-                    }
-                    
-                    self.start_stockfish();       // ← start the cmd_rx loop right away
-                    
-                    
-                    
+                    self.new_game.set_strength(self.game.strength);
+                    self.new_game.set_time_control(self.game.time_control.as_ref());
+                    self.new_game.open = true;
                 };
                 ui.label(format!("{:?}", self.game.game_over));
                 ui.label(format!("{:?}{:?}", self.game.player, self.game.enemey));
                 if ui.button("end-game").clicked() {
-                    self.board = Board::from(&DEFAULT_FEN.to_owned());
-                    self.game.game_over = true;
-                    if let Some(tx) = &self.game.stockfish_tx {
-                        if let Err(e) = tx.send(StockfishCmd::Stop) {
-                            eprintln!("failed to send `stop` to stockfish: {}", e);
-                        }
+                    // ending a running game counts as resigning, the board stays until the popup closes
+                    if !self.game.game_over {
+                        let result = match self.game.player {
+                            PieceColor::White => GameResult::BlackWin,
+                            PieceColor::Black => GameResult::WhiteWin,
+                        };
+                        self.end_game(result, LostBy::Resignation, "You resigned".to_string());
+                    } else {
+                        self.board = Board::from(&DEFAULT_FEN.to_owned());
+                    }
+                    if let Some(tx) = &self.game.stockfish_tx
+                        && let Err(e) = tx.send(StockfishCmd::Stop) {
+                        eprintln!("failed to send `stop` to stockfish: {}", e);
                     }
                 }
                 ui.label(format!("current eval: {}", self.get_evaluation()));
+                ui.separator();
+                let mut follow = self.analysis.follow_game;
+                if ui.checkbox(&mut follow, "Infinite analysis").changed() {
+                    self.set_follow_game(follow);
+                }
+                if self.analysis.follow_game {
+                    if let Some(best) = self.analysis.best_line() {
+                        ui.label(format!("{}  {}", best.score, best.san.join(" ")));
+                    }
+                    self.render_search_stats(ui);
+                }
+                ui.separator();
+                egui::CollapsingHeader::new("Time usage").show(ui, |ui| {
+                    self.render_time_chart(ui);
+                });
 
                 ui.vertical(|ui| {
                     let check = if self.board.is_in_check(PieceColor::White) {"true"} else {"false"};
@@ -205,6 +280,12 @@ impl MyApp{
                 }
             
             });
+            self.render_game_board(ctx);
+    }
+
+    /// The game board with the eval bar, move list, players and clocks, and the windows that
+    /// go with it.
+    pub fn render_game_board(&mut self, ctx: &egui::Context) {
             CentralPanel::default() .frame(
         Frame::default()
             .fill(Color32::from_rgb(0x30, 0x30, 0x30))        // your background color            // optional corner rounding
@@ -224,6 +305,7 @@ impl MyApp{
                     self.render_board(top_left, ui);
                     ctx.request_repaint();
                     self.render_game_info(top_left, ui);
+                    self.render_new_game_dialog(ctx);
                     if let Some((new_pos, old_pos)) = self.board.state.promtion_pending {
                         egui::Window::new("Promote Pawn")
                             .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
@@ -239,8 +321,9 @@ impl MyApp{
                                         PieceType::Rook,
                                     ] {
                                         if ui.button(kind.to_string()).clicked() {
-                                            self.board.record_move(old_pos, new_pos, Some(kind), false);
+                                            let is_capture = old_pos.1 != new_pos.1;
                                             self.board.promote_pawn(new_pos, kind);
+                                            self.board.record_move(old_pos, new_pos, Some(kind), is_capture);
                                             self.board.state.promtion_pending = None;
                                             ctx.request_repaint();
                                         }
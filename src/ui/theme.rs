@@ -3,16 +3,11 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::path::Path;
 
-use eframe::egui::load;
 use eframe::egui::Color32;
 use eframe::egui::ColorImage;
 use eframe::egui::TextureHandle;
 use eframe::egui::TextureOptions;
 
-
-use load::{SizeHint, LoadError};
-use egui_extras::install_image_loaders;
-
 use eframe::CreationContext;
 
 use crate::engine::PieceColor;
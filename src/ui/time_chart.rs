@@ -0,0 +1,53 @@
+use eframe::egui::{pos2, vec2, Color32, Rect, Sense, Stroke, Ui};
+
+use crate::{engine::{pgn::pgn_duration, PieceColor}, ui::app::MyApp};
+
+/// Height of the time usage chart.
+const CHART_HEIGHT: f32 = 100.0;
+/// Fewest moves the width is divided into, so the first bars are not huge.
+const MIN_CHART_MOVES: usize = 20;
+
+impl MyApp {
+    /// Bars of the time each move of the game took, White's above the middle line and
+    /// Black's below it. Hovering a bar shows the move and the clock after it.
+    pub fn render_time_chart(&self, ui: &mut Ui) {
+        let meta = &self.board.meta_data;
+        let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width(), CHART_HEIGHT), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, self.ui.timer_inside);
+        painter.hline(rect.x_range(), rect.center().y, Stroke::new(1.0, self.ui.timer_outside));
+
+        let line = meta.moves.line();
+        let spent: Vec<f32> = line.iter().map(|m| m.time.spent.map_or(0.0, |d| d.as_secs_f32())).collect();
+        let longest = spent.iter().copied().fold(0.0, f32::max);
+        if longest <= 0.0 {
+            return;
+        }
+        let bar_width = rect.width() / spent.len().max(MIN_CHART_MOVES) as f32;
+        let half = rect.height() / 2.0 - 2.0;
+        let hovered = response.hover_pos().map(|pos| ((pos.x - rect.left()) / bar_width) as usize);
+        for (index, seconds) in spent.iter().enumerate() {
+            let height = half * seconds / longest;
+            let left = rect.left() + index as f32 * bar_width;
+            let (top, color) = match meta.mover(index) {
+                PieceColor::White => (rect.center().y - height, Color32::from_gray(220)),
+                PieceColor::Black => (rect.center().y, Color32::from_gray(90)),
+            };
+            let bar = Rect::from_min_size(pos2(left, top), vec2((bar_width - 1.0).max(1.0), height));
+            let color = if hovered == Some(index) { Color32::LIGHT_BLUE } else { color };
+            painter.rect_filled(bar, 0.0, color);
+        }
+
+        if let Some((index, record)) = hovered.and_then(|index| Some((index, *line.get(index)?))) {
+            let dots = if meta.mover(index) == PieceColor::White { "." } else { "..." };
+            let mut text = format!("{}{} {}", record.move_number, dots, record.san);
+            if let Some(spent) = record.time.spent {
+                text += &format!("\nthought {}", pgn_duration(spent));
+            }
+            if let Some(clock) = record.time.clock {
+                text += &format!("\n{} left", pgn_duration(clock));
+            }
+            response.on_hover_text_at_pointer(text);
+        }
+    }
+}